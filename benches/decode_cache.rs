@@ -0,0 +1,110 @@
+//! Fibonacci loop with and without the decode cache, run with `cargo bench`
+use std::collections::HashMap;
+
+use criterion::{
+	criterion_group,
+	criterion_main,
+	BenchmarkId,
+	Criterion,
+};
+
+use bibe_asm::asm::emitter::link_instruction;
+use bibe_asm::asm::Directive;
+use bibe_asm::parser::{
+	parse,
+	tokenize,
+};
+use bibe_emu::memory::{
+	Memory,
+	SimpleImage,
+};
+use bibe_emu::state::csr::*;
+use bibe_emu::state::State;
+use bibe_emu::target::StdTarget;
+use bibe_instr::{
+	Encode,
+	Instruction,
+	Register,
+	Width,
+};
+
+type Machine = State<StdTarget, SimpleImage, Vec<Box<dyn CsrBlock>>>;
+
+const FIBONACCI: &'static str = "\
+	mov %l0, 0
+	mov %l1, 0
+	mov %l2, 1
+loop:
+	cmp %l0, %a0
+	b.ge end
+	add %l3, %l1, %l2
+	mov %l1, %l2
+	mov %l2, %l3
+	add %l0, %l0, 1
+	b loop
+end:
+	mov %o0, %l1
+	swi
+";
+
+/// Assemble a program made of instructions and labels, it's only ever the one above
+fn assemble(program: &str) -> Vec<Instruction> {
+	let (_, tokens) = tokenize(program).expect("Failed to tokenize program");
+	let (_, statements) = parse(&tokens).expect("Failed to parse program");
+
+	let mut labels = HashMap::new();
+	let mut addr = 0;
+	for statement in &statements {
+		match statement.directive() {
+			Some(Directive::Label(id)) => { labels.insert(*id, addr); },
+			Some(_) => panic!("Unsupported asm directive"),
+			None => addr += 4,
+		}
+	}
+
+	statements.iter()
+		.filter_map(|statement| statement.instruction())
+		.enumerate()
+		.map(|(i, instr)| link_instruction(&labels, 4 * i as u32, &instr).expect("Failed to link program"))
+		.collect()
+}
+
+/// `program` at address 0 of 4K of RAM
+fn machine(program: &[Instruction]) -> Machine {
+	let mut state = State::new(StdTarget::new(), Some(SimpleImage::new(0x1000)), vec![
+		Box::new(PsrBlock::new()) as Box<dyn CsrBlock>,
+		Box::new(IsrBlock::new()),
+	]);
+
+	for (i, instr) in program.iter().enumerate() {
+		assert!(state.write(4 * i as u32, Width::Word, instr.encode()).is_ok());
+	}
+
+	state
+}
+
+fn fibonacci(c: &mut Criterion) {
+	let program = assemble(FIBONACCI);
+	let end = 4 * (program.len() as u32 - 1);
+	let mut group = c.benchmark_group("fibonacci");
+
+	for cached in [false, true] {
+		let mut state = machine(&program);
+		state.set_decode_cache(cached);
+
+		let name = if cached { "cached" } else { "uncached" };
+		group.bench_function(BenchmarkId::new(name, 40), |b| b.iter(|| {
+			state.core_mut().write_pc(0);
+			state.core_mut().write_reg(Register::a0(), 40);
+			while state.core().read_pc() != end {
+				state.execute_one();
+			}
+			state.core().read_reg(Register::o0())
+		}));
+	}
+
+	group.finish();
+}
+
+criterion_group!(benches, fibonacci);
+criterion_main!(benches);
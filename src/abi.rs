@@ -0,0 +1,132 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Register names used by tools
+//!
+//! Registers use the ABI names bibe-asm accepts: `pc` and `sp`, and the `a`, `l` and `o` groups
+//! numbered from their first register. The rest, including the link register, are `rN`.
+use core::fmt;
+
+use bibe_instr::Register;
+
+/// Register branch-and-link writes the return address to
+pub const LINK_REGISTER: u8 = 30;
+
+/// Most registers a group can have
+const GROUP_SIZE: u8 = 8;
+
+/// Registers with a fixed role, the numbers come from `bibe_instr`
+fn fixed_names() -> [(&'static str, u8); 2] {
+	[
+		("pc", Register::pc().as_u8()),
+		("sp", Register::sp().as_u8()),
+	]
+}
+
+/// Prefix and first register of each group, from `bibe_instr`
+fn groups() -> [(char, u8); 3] {
+	[
+		('a', Register::a0().as_u8()),
+		('l', Register::l0().as_u8()),
+		('o', Register::o0().as_u8()),
+	]
+}
+
+/// Group prefix and index of register `n`
+///
+/// A group ends at the next group, a fixed role register or the link register.
+fn group_name(n: u8) -> Option<(char, u8)> {
+	let (prefix, first) = groups().into_iter()
+		.filter(|(_, first)| *first <= n)
+		.max_by_key(|(_, first)| *first)?;
+
+	let mut ends = groups().map(|(_, first)| first).into_iter()
+		.chain(fixed_names().map(|(_, reg)| reg))
+		.chain([LINK_REGISTER]);
+	let index = n - first;
+
+	if index >= GROUP_SIZE || ends.any(|end| end > first && end <= n) {
+		return None;
+	}
+
+	Some((prefix, index))
+}
+
+/// Displays a register by name, honours width and alignment
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RegisterName(pub Register);
+
+impl fmt::Display for RegisterName {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let n = self.0.as_u8();
+		if let Some((name, _)) = fixed_names().iter().find(|(_, reg)| *reg == n) {
+			return f.pad(name);
+		}
+
+		// Group indices are a single digit and registers are below 100, so names fit in three bytes
+		let mut buf = [0u8; 3];
+		let len = match group_name(n) {
+			Some((prefix, index)) => {
+				buf[..2].copy_from_slice(&[prefix as u8, b'0' + index]);
+				2
+			},
+			None if n < 10 => {
+				buf[..2].copy_from_slice(&[b'r', b'0' + n]);
+				2
+			},
+			None => {
+				buf = [b'r', b'0' + n / 10, b'0' + n % 10];
+				3
+			},
+		};
+		f.pad(core::str::from_utf8(&buf[..len]).map_err(|_| fmt::Error)?)
+	}
+}
+
+/// Parse an ABI name or `rN`, with an optional `%` prefix
+pub fn parse_register(name: &str) -> Option<Register> {
+	let name = name.strip_prefix('%').unwrap_or(name);
+
+	if let Some((_, n)) = fixed_names().iter().find(|(abi, _)| *abi == name) {
+		return Register::new(*n);
+	}
+
+	let mut chars = name.chars();
+	let prefix = chars.next()?;
+	if let Some((_, first)) = groups().into_iter().find(|(p, _)| *p == prefix) {
+		let index: u8 = chars.as_str().parse().ok()?;
+		let n = first.checked_add(index)?;
+		return (group_name(n) == Some((prefix, index))).then(|| Register::new(n)).flatten();
+	}
+
+	Register::new(name.strip_prefix('r')?.parse().ok()?)
+}
+
+#[cfg(test)]
+#[cfg(feature = "std")]
+mod test {
+	extern crate std;
+
+	use super::*;
+	use std::string::ToString;
+
+	#[test]
+	fn test_names() {
+		assert_eq!(RegisterName(Register::pc()).to_string(), "pc");
+		assert_eq!(RegisterName(Register::a0()).to_string(), "a0");
+		assert_eq!(RegisterName(Register::l0()).to_string(), "l0");
+		assert_eq!(RegisterName(Register::new(LINK_REGISTER).unwrap()).to_string(), "r30");
+		assert_eq!(std::format!("{:<4}|", RegisterName(Register::sp())), "sp  |");
+		assert_eq!(parse_register("%sp"), Some(Register::sp()));
+		assert_eq!(parse_register("o0"), Some(Register::o0()));
+		assert_eq!(parse_register("%l1"), Register::new(Register::l0().as_u8() + 1));
+		assert_eq!(parse_register("r31"), Some(Register::pc()));
+		assert_eq!(parse_register("r32"), None);
+		assert_eq!(parse_register("x1"), None);
+		assert_eq!(parse_register("l9"), None);
+
+		// Every register round trips through its name
+		for n in 0..32 {
+			let reg = Register::new(n).unwrap();
+			assert_eq!(parse_register(&RegisterName(reg).to_string()), Some(reg));
+		}
+	}
+}
@@ -0,0 +1,479 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Machine descriptions
+//!
+//! A machine is described in TOML and built into a ready to run `State`:
+//!
+//! ```toml
+//! target = "bibe32i"
+//! reset_vector = 0x0
+//! isr_base = 0x100
+//! csr = [{ kind = "dbg_out" }, { kind = "cycle", base = 0x10000 }]
+//!
+//! [[memory]]
+//! kind = "rom"
+//! base = 0x0
+//! file = "boot.bin"
+//!
+//! [[memory]]
+//! kind = "ram"
+//! base = 0x10000
+//! size = 0x10000
+//! wait_states = 1
+//!
+//! [[peripheral]]
+//! kind = "uart"
+//! base = 0xf0000000
+//! output = "uart.log"
+//!
+//! [[image]]
+//! path = "firmware.hex"
+//! ```
+//!
+//! Relative paths are resolved against the directory of the description file. ROM contents
+//! can only come from the region's `file`, images are loaded after the ROMs are sealed.
+#![cfg(feature = "config")]
+extern crate std;
+
+use core::fmt;
+use std::{
+	boxed::Box,
+	fs::{
+		self,
+		File,
+	},
+	io,
+	path::{
+		Path,
+		PathBuf,
+	},
+	string::String,
+	vec,
+	vec::Vec,
+};
+
+use serde::Deserialize;
+
+use crate::{
+	loader::{
+		self,
+		Format,
+		LoadError,
+	},
+	memory::{
+		Mapped,
+		Memory,
+		Rom,
+		SimpleImage,
+		Uart,
+	},
+	state::{
+		csr::*,
+		ResetConfig,
+		State,
+	},
+	symbols::{
+		SymbolError,
+		SymbolMap,
+	},
+	target::StdTarget,
+};
+
+/// State built from a machine description
+pub type Machine = State<StdTarget, Mapped, Vec<Box<dyn CsrBlock>>>;
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(PathBuf, io::Error),
+	Parse(toml::de::Error),
+	/// Target string not accepted by `StdTarget::parse`
+	Target(String),
+	/// Region has neither a size nor a file, or its file is larger than its size
+	Size(u32),
+	/// Region or peripheral overlaps another one
+	Overlap(u32),
+	/// Image format couldn't be determined from the file name
+	Format(PathBuf),
+	Load(PathBuf, LoadError),
+	Symbols(PathBuf, SymbolError),
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Io(path, e) => write!(f, "{}: {e}", path.display()),
+			ConfigError::Parse(e) => write!(f, "{e}"),
+			ConfigError::Target(target) => write!(f, "invalid target {target}"),
+			ConfigError::Size(base) => write!(f, "region at {base:08x} has an invalid size"),
+			ConfigError::Overlap(base) => write!(f, "region at {base:08x} overlaps another region"),
+			ConfigError::Format(path) => write!(f, "{}: unknown image format", path.display()),
+			ConfigError::Load(path, e) => write!(f, "{}: {e}", path.display()),
+			ConfigError::Symbols(path, e) => write!(f, "{}: {e}", path.display()),
+		}
+	}
+}
+
+pub type Result<T> = core::result::Result<T, ConfigError>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryKind {
+	Ram,
+	Rom,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryConfig {
+	pub kind: MemoryKind,
+	pub base: u32,
+	/// Defaults to the size of `file`
+	pub size: Option<u32>,
+	/// Initial contents, placed at `base`
+	pub file: Option<PathBuf>,
+	#[serde(default)]
+	pub wait_states: u32,
+}
+
+/// Memory mapped device, selected by `kind`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PeripheralConfig {
+	/// See `memory::Uart`, `input` and `output` default to stdio
+	Uart {
+		base: u32,
+		input: Option<PathBuf>,
+		/// Created or truncated when the machine is built
+		output: Option<PathBuf>,
+	},
+}
+
+impl PeripheralConfig {
+	pub fn base(&self) -> u32 {
+		match self {
+			PeripheralConfig::Uart { base, .. } => *base,
+		}
+	}
+}
+
+fn default_cycle_base() -> u32 {
+	CYCLE_BASE
+}
+
+/// Implementation defined CSR blocks, the PSR and ISR blocks are always present
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CsrConfig {
+	DbgOut,
+	Cycle {
+		#[serde(default = "default_cycle_base")]
+		base: u32,
+	},
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageConfig {
+	pub path: PathBuf,
+	/// `raw`, `elf` or a text format extension, guessed from the file when missing
+	pub format: Option<String>,
+	/// Load address of raw images
+	#[serde(default)]
+	pub base: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MachineConfig {
+	pub target: String,
+	#[serde(default)]
+	pub reset_vector: u32,
+	#[serde(default)]
+	pub isr_base: u32,
+	#[serde(default)]
+	pub csr: Vec<CsrConfig>,
+	#[serde(default)]
+	pub memory: Vec<MemoryConfig>,
+	#[serde(default)]
+	pub peripheral: Vec<PeripheralConfig>,
+	#[serde(default)]
+	pub image: Vec<ImageConfig>,
+	/// Directory relative paths are resolved against
+	#[serde(skip)]
+	pub base_dir: PathBuf,
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+	fs::read(path).map_err(|e| ConfigError::Io(path.into(), e))
+}
+
+/// Images can each bring symbols, keep the ones of earlier images
+fn add_symbols(machine: &mut Machine, symbols: SymbolMap) {
+	if symbols.is_empty() {
+		return;
+	}
+
+	let mut merged = machine.symbols().cloned().unwrap_or_default();
+	merged.merge(symbols);
+	machine.set_symbols(Some(merged));
+}
+
+impl MachineConfig {
+	pub fn parse(src: &str) -> Result<Self> {
+		toml::from_str(src).map_err(ConfigError::Parse)
+	}
+
+	/// Read a description, paths in it are relative to the file's directory
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let path = path.as_ref();
+		let src = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.into(), e))?;
+
+		let mut config = Self::parse(&src)?;
+		config.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+		Ok(config)
+	}
+
+	fn resolve(&self, path: &Path) -> PathBuf {
+		self.base_dir.join(path)
+	}
+
+	fn region(&self, region: &MemoryConfig) -> Result<Box<dyn Memory>> {
+		let contents = match &region.file {
+			Some(file) => read(&self.resolve(file))?,
+			None => Vec::new(),
+		};
+
+		let size = match (region.size, &region.file) {
+			(Some(size), _) => size,
+			(None, Some(_)) => u32::try_from(contents.len()).map_err(|_| ConfigError::Size(region.base))?,
+			(None, None) => return Err(ConfigError::Size(region.base)),
+		};
+
+		if contents.len() > size as usize {
+			return Err(ConfigError::Size(region.base));
+		}
+
+		let mut image = SimpleImage::new(size);
+		image.write_bytes(0, &contents).map_err(|_| ConfigError::Size(region.base))?;
+
+		Ok(match region.kind {
+			MemoryKind::Ram => Box::new(image),
+			MemoryKind::Rom => Box::new(Rom::new(image)),
+		})
+	}
+
+	fn memory(&self) -> Result<Mapped> {
+		let mut memory = Mapped::new();
+
+		for region in &self.memory {
+			let contents = self.region(region)?;
+			memory.map_with_wait_states(region.base, contents, region.wait_states)
+				.ok_or(ConfigError::Overlap(region.base))?;
+		}
+
+		for peripheral in &self.peripheral {
+			let device = self.peripheral(peripheral)?;
+			memory.map(peripheral.base(), device).ok_or(ConfigError::Overlap(peripheral.base()))?;
+		}
+
+		Ok(memory)
+	}
+
+	fn peripheral(&self, peripheral: &PeripheralConfig) -> Result<Box<dyn Memory>> {
+		Ok(match peripheral {
+			PeripheralConfig::Uart { input, output, .. } => {
+				let input: Box<dyn io::Read> = match input {
+					Some(path) => {
+						let path = self.resolve(path);
+						Box::new(File::open(&path).map_err(|e| ConfigError::Io(path, e))?)
+					},
+					None => Box::new(io::stdin()),
+				};
+
+				let output: Box<dyn io::Write> = match output {
+					Some(path) => {
+						let path = self.resolve(path);
+						Box::new(File::create(&path).map_err(|e| ConfigError::Io(path, e))?)
+					},
+					None => Box::new(io::stdout()),
+				};
+
+				Box::new(Uart::with_io(input, output))
+			},
+		})
+	}
+
+	fn csr_blocks(&self) -> Vec<Box<dyn CsrBlock>> {
+		let mut blocks: Vec<Box<dyn CsrBlock>> = vec![
+			Box::new(PsrBlock::new()),
+			Box::new(IsrBlock::new()),
+		];
+
+		for csr in &self.csr {
+			blocks.push(match csr {
+				CsrConfig::DbgOut => Box::new(DbgOutBlock::new()),
+				CsrConfig::Cycle { base } => Box::new(CycleBlock::with_base(*base)),
+			});
+		}
+
+		blocks
+	}
+
+	fn load_image(&self, machine: &mut Machine, image: &ImageConfig) -> Result<()> {
+		let path = self.resolve(&image.path);
+		let data = read(&path)?;
+
+		let format = match &image.format {
+			Some(format) => format.clone(),
+			None if loader::is_elf(&data) => String::from("elf"),
+			None => path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().into(),
+		};
+
+		let res = match format.as_str() {
+			"raw" | "bin" => machine.write_bytes(image.base, &data).map(|_| None).map_err(LoadError::from),
+			"elf" => {
+				let symbols = SymbolMap::from_elf(&data).map_err(|e| ConfigError::Symbols(path.clone(), e))?;
+				add_symbols(machine, symbols);
+				loader::load_elf(&data, machine)
+			},
+			ext => {
+				let format = Format::from_extension(ext).ok_or_else(|| ConfigError::Format(path.clone()))?;
+				let src = String::from_utf8_lossy(&data);
+				loader::load(format, &src, machine)
+			},
+		};
+
+		// Entry points in images are ignored, execution starts at the reset vector
+		res.map(|_| ()).map_err(|e| ConfigError::Load(path, e))
+	}
+
+	/// Create the machine, it has been reset and is ready to run
+	pub fn build(&self) -> Result<Machine> {
+		let target = StdTarget::parse(&self.target).ok_or_else(|| ConfigError::Target(self.target.clone()))?;
+		let reset = ResetConfig {
+			reset_vector: self.reset_vector,
+			isr_base: self.isr_base,
+		};
+
+		let mut machine = State::with_reset_config(target, Some(self.memory()?), self.csr_blocks(), reset);
+		for image in &self.image {
+			self.load_image(&mut machine, image)?;
+		}
+
+		Ok(machine)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use bibe_instr::Width;
+
+	#[test]
+	fn test_build() {
+		let config = MachineConfig::parse(r#"
+			target = "bibe32i"
+			reset_vector = 0x100
+			csr = [{ kind = "cycle" }]
+
+			[[memory]]
+			kind = "ram"
+			base = 0
+			size = 0x1000
+
+			[[memory]]
+			kind = "rom"
+			base = 0x2000
+			size = 0x100
+			wait_states = 2
+
+			[[peripheral]]
+			kind = "uart"
+			base = 0xf0000000
+		"#).unwrap();
+
+		let mut machine = config.build().unwrap();
+		assert_eq!(machine.core().read_pc(), 0x100);
+		assert!(machine.write(0x10, Width::Word, 1).is_ok());
+		assert!(machine.write(0x2000, Width::Word, 1).is_err());
+		assert_eq!(machine.wait_states(0x2000, Width::Word), 2);
+		assert!(machine.read_csr(CYCLE_LO_REG, Width::Word).is_some());
+		assert!(machine.target().has_extension(crate::target::Extension::IntegerMultplication));
+	}
+
+	#[test]
+	fn test_errors() {
+		assert!(matches!(MachineConfig::parse("target = 1"), Err(ConfigError::Parse(_))));
+		assert!(matches!(MachineConfig::parse("target = \"x\"").unwrap().build(), Err(ConfigError::Target(_))));
+
+		let overlap = MachineConfig::parse(r#"
+			target = "bibe32"
+
+			[[memory]]
+			kind = "ram"
+			base = 0
+			size = 0x1000
+
+			[[peripheral]]
+			kind = "uart"
+			base = 0x800
+		"#).unwrap();
+		assert!(matches!(overlap.build(), Err(ConfigError::Overlap(0x800))));
+	}
+
+	#[test]
+	fn test_parameters() {
+		let dir = std::env::temp_dir().join(std::format!("bibe-config-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("uart.in"), b"x").unwrap();
+
+		let mut config = MachineConfig::parse(r#"
+			target = "bibe32"
+
+			[[csr]]
+			kind = "cycle"
+			base = 0x20000
+
+			[[peripheral]]
+			kind = "uart"
+			base = 0x1000
+			input = "uart.in"
+			output = "uart.out"
+		"#).unwrap();
+		config.base_dir = dir.clone();
+		assert_eq!(config.csr, [CsrConfig::Cycle { base: 0x20000 }]);
+
+		let mut machine = config.build().unwrap();
+		assert!(machine.read_csr(0x20000, Width::Word).is_some());
+		assert!(machine.read_csr(CYCLE_LO_REG, Width::Word).is_none());
+
+		assert_eq!(machine.read(0x1000, Width::Word).ok(), Some('x' as u32));
+		assert!(machine.write(0x1000, Width::Word, 'y' as u32).is_ok());
+		assert_eq!(fs::read(dir.join("uart.out")).unwrap(), b"y");
+
+		// Parameters belong to a kind
+		assert!(matches!(MachineConfig::parse(r#"
+			target = "bibe32"
+			csr = [{ kind = "dbg_out", base = 0x20000 }]
+		"#), Err(ConfigError::Parse(_))));
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn test_merge_symbols() {
+		let mut machine = MachineConfig::parse("target = \"bibe32\"").unwrap().build().unwrap();
+		add_symbols(&mut machine, SymbolMap::from_labels([("boot", 0x0)]));
+		add_symbols(&mut machine, SymbolMap::new());
+		add_symbols(&mut machine, SymbolMap::from_labels([("app", 0x1000)]));
+
+		let symbols = machine.symbols().unwrap();
+		assert_eq!(symbols.address_of("boot"), Some(0x0));
+		assert_eq!(symbols.address_of("app"), Some(0x1000));
+	}
+
+	#[test]
+	fn test_boards() {
+		let config = MachineConfig::parse(include_str!("../boards/default.toml")).unwrap();
+		assert!(config.build().is_ok());
+	}
+}
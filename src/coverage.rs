@@ -0,0 +1,303 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Code coverage of emulated runs
+//!
+//! A `Coverage` observer records how often every address executed and, for conditional
+//! instructions, how often the condition held. Runs are combined with `merge`, and saved as
+//! a listing that `from_listing` reads back:
+//!
+//! ```text
+//! 00000100 12
+//! 00000104 12 3 9
+//! ```
+//!
+//! Each line is an address and its execution count, conditional instructions add the number
+//! of times they were taken and not taken. With a `LineMap` the results can also be exported
+//! in lcov's tracefile format.
+#![cfg(feature = "std")]
+extern crate std;
+
+use core::fmt;
+use std::{
+	collections::BTreeMap,
+	string::{
+		String,
+		ToString,
+	},
+	vec::Vec,
+};
+
+use crate::state::{
+	condition,
+	observer::{
+		Observer,
+		Retired,
+	},
+};
+use bibe_instr::Condition;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CoverageError {
+	/// Line of a listing or line map couldn't be parsed
+	Malformed { line: usize },
+}
+
+impl fmt::Display for CoverageError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CoverageError::Malformed { line } => write!(f, "line {line}: malformed entry"),
+		}
+	}
+}
+
+pub type Result<T> = core::result::Result<T, CoverageError>;
+
+/// Counts for one instruction address
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Hits {
+	pub executed: u64,
+	/// Taken and not taken counts, only for conditional instructions
+	pub branches: Option<(u64, u64)>,
+}
+
+impl Hits {
+	fn merge(&mut self, other: &Hits) {
+		self.executed += other.executed;
+		self.branches = match (self.branches, other.branches) {
+			(Some((taken, not_taken)), Some((other_taken, other_not_taken))) => {
+				Some((taken + other_taken, not_taken + other_not_taken))
+			},
+			(branches, None) | (None, branches) => branches,
+		};
+	}
+}
+
+fn parse_hex(s: &str) -> Option<u32> {
+	u32::from_str_radix(s.strip_prefix("0x").unwrap_or(s), 16).ok()
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+	hits: BTreeMap<u32, Hits>,
+}
+
+impl Coverage {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn hits(&self) -> &BTreeMap<u32, Hits> {
+		&self.hits
+	}
+
+	pub fn get(&self, addr: u32) -> Option<&Hits> {
+		self.hits.get(&addr)
+	}
+
+	/// Add the counts of another run
+	pub fn merge(&mut self, other: &Coverage) {
+		for (addr, hits) in &other.hits {
+			self.hits.entry(*addr).or_default().merge(hits);
+		}
+	}
+
+	/// Read a listing written by `write_listing`
+	pub fn from_listing(src: &str) -> Result<Self> {
+		let mut coverage = Self::new();
+
+		for (i, line) in src.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let malformed = || CoverageError::Malformed { line: i + 1 };
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			let count = |s: &str| s.parse::<u64>().map_err(|_| malformed());
+
+			let (addr, hits) = match fields.as_slice() {
+				[addr, executed] => (*addr, Hits {
+					executed: count(*executed)?,
+					branches: None,
+				}),
+				[addr, executed, taken, not_taken] => (*addr, Hits {
+					executed: count(*executed)?,
+					branches: Some((count(*taken)?, count(*not_taken)?)),
+				}),
+				_ => return Err(malformed()),
+			};
+
+			let addr = parse_hex(addr).ok_or_else(malformed)?;
+			coverage.hits.entry(addr).or_default().merge(&hits);
+		}
+
+		Ok(coverage)
+	}
+
+	/// One line per executed address, see the module documentation
+	pub fn write_listing(&self, out: &mut dyn fmt::Write) -> fmt::Result {
+		for (addr, hits) in &self.hits {
+			write!(out, "{addr:08x} {}", hits.executed)?;
+			if let Some((taken, not_taken)) = hits.branches {
+				write!(out, " {taken} {not_taken}")?;
+			}
+			writeln!(out)?;
+		}
+		Ok(())
+	}
+
+	/// lcov tracefile with line and branch coverage of every line in `lines`
+	///
+	/// A line's count is the highest count of its instructions. Each conditional instruction is
+	/// a block with a taken and a not taken branch, conditional instructions that never executed
+	/// can't be told apart and have no branches.
+	pub fn write_lcov(&self, lines: &LineMap, test_name: &str, out: &mut dyn fmt::Write) -> fmt::Result {
+		// Per file and line, the hits of every instruction
+		let mut files: BTreeMap<&str, BTreeMap<u32, Vec<Hits>>> = BTreeMap::new();
+		for (addr, (file, line)) in &lines.lines {
+			let hits = self.hits.get(addr).copied().unwrap_or_default();
+			files.entry(file.as_str()).or_default().entry(*line).or_default().push(hits);
+		}
+
+		for (file, file_lines) in files {
+			writeln!(out, "TN:{test_name}")?;
+			writeln!(out, "SF:{file}")?;
+
+			let (mut branches, mut branches_hit) = (0, 0);
+			for (line, hits) in &file_lines {
+				let conditional = hits.iter().filter_map(|hits| hits.branches);
+				for (block, (taken, not_taken)) in conditional.enumerate() {
+					for (branch, count) in [taken, not_taken].into_iter().enumerate() {
+						writeln!(out, "BRDA:{line},{block},{branch},{count}")?;
+						branches += 1;
+						branches_hit += (count > 0) as u32;
+					}
+				}
+			}
+
+			let mut lines_hit = 0;
+			for (line, hits) in &file_lines {
+				let count = hits.iter().map(|hits| hits.executed).max().unwrap_or(0);
+				writeln!(out, "DA:{line},{count}")?;
+				lines_hit += (count > 0) as u32;
+			}
+
+			writeln!(out, "LF:{}", file_lines.len())?;
+			writeln!(out, "LH:{lines_hit}")?;
+			writeln!(out, "BRF:{branches}")?;
+			writeln!(out, "BRH:{branches_hit}")?;
+			writeln!(out, "end_of_record")?;
+		}
+
+		Ok(())
+	}
+}
+
+impl Observer for Coverage {
+	fn retired(&mut self, event: &Retired) {
+		let hits = self.hits.entry(event.pc).or_default();
+		hits.executed += 1;
+
+		if condition(event.instr).map_or(false, |cond| cond != Condition::Always) {
+			let (taken, not_taken) = hits.branches.get_or_insert((0, 0));
+			if event.passed {
+				*taken += 1;
+			} else {
+				*not_taken += 1;
+			}
+		}
+	}
+}
+
+/// Source line of every instruction address
+///
+/// Read from text with one `addr file:line` entry per line, e.g. generated from
+/// `addr2line` or the assembler's listing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineMap {
+	lines: BTreeMap<u32, (String, u32)>,
+}
+
+impl LineMap {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn insert(&mut self, addr: u32, file: &str, line: u32) {
+		self.lines.insert(addr, (file.to_string(), line));
+	}
+
+	pub fn get(&self, addr: u32) -> Option<(&str, u32)> {
+		self.lines.get(&addr).map(|(file, line)| (file.as_str(), *line))
+	}
+
+	pub fn from_text(src: &str) -> Result<Self> {
+		let mut map = Self::new();
+
+		for (i, line) in src.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let malformed = || CoverageError::Malformed { line: i + 1 };
+			let (addr, location) = line.split_once(char::is_whitespace).ok_or_else(malformed)?;
+			let (file, number) = location.trim().rsplit_once(':').ok_or_else(malformed)?;
+
+			let addr = parse_hex(addr).ok_or_else(malformed)?;
+			let number = number.parse().map_err(|_| malformed())?;
+			map.insert(addr, file, number);
+		}
+
+		Ok(map)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_listing() {
+		let src = "00000100 12\n00000104 12 3 9\n";
+		let coverage = Coverage::from_listing(src).unwrap();
+		assert_eq!(coverage.get(0x104), Some(&Hits { executed: 12, branches: Some((3, 9)) }));
+
+		let mut listing = String::new();
+		coverage.write_listing(&mut listing).unwrap();
+		assert_eq!(listing, src);
+
+		let mut merged = coverage.clone();
+		merged.merge(&Coverage::from_listing("0x104 1 1 0\n0x108 2\n").unwrap());
+		assert_eq!(merged.get(0x100), Some(&Hits { executed: 12, branches: None }));
+		assert_eq!(merged.get(0x104), Some(&Hits { executed: 13, branches: Some((4, 9)) }));
+		assert_eq!(merged.get(0x108), Some(&Hits { executed: 2, branches: None }));
+
+		assert_eq!(Coverage::from_listing("100 1\n100\n"), Err(CoverageError::Malformed { line: 2 }));
+		assert_eq!(Coverage::from_listing("zz 1\n"), Err(CoverageError::Malformed { line: 1 }));
+	}
+
+	#[test]
+	fn test_lcov() {
+		let coverage = Coverage::from_listing("0 1\n4 1 1 0\n").unwrap();
+		let lines = LineMap::from_text("0 main.s:1\n4 main.s:2\n8 main.s:2\nc main.s:4\n").unwrap();
+
+		let mut lcov = String::new();
+		coverage.write_lcov(&lines, "run", &mut lcov).unwrap();
+		assert_eq!(lcov, "\
+			TN:run\n\
+			SF:main.s\n\
+			BRDA:2,0,0,1\n\
+			BRDA:2,0,1,0\n\
+			DA:1,1\n\
+			DA:2,1\n\
+			DA:4,0\n\
+			LF:3\n\
+			LH:2\n\
+			BRF:2\n\
+			BRH:1\n\
+			end_of_record\n\
+		");
+
+		assert_eq!(LineMap::from_text("0 main.s\n"), Err(CoverageError::Malformed { line: 1 }));
+	}
+}
@@ -1,8 +1,17 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
 #![no_std]
+pub mod abi;
+pub mod config;
+pub mod coverage;
+pub mod loader;
 pub mod memory;
+pub mod monitor;
+pub mod profile;
 pub mod state;
+pub mod stats;
+pub mod symbols;
 pub mod target;
+pub mod trace;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum InterruptKind {
@@ -73,6 +82,23 @@ impl Interrupt {
 			err2: 0,
 		}
 	}
+
+	/// External interrupt `n`
+	pub fn irq(n: u8) -> Interrupt {
+		Interrupt {
+			kind: InterruptKind::Irq(n),
+			err1: 0,
+			err2: 0,
+		}
+	}
+
+	pub fn isr_exit() -> Interrupt {
+		Interrupt {
+			kind: InterruptKind::IsrExit,
+			err1: 0,
+			err2: 0,
+		}
+	}
 }
 
 pub type Result<T> = core::result::Result<T, Interrupt>;
@@ -0,0 +1,275 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use crate::memory::Memory;
+
+use super::{
+	LoadError,
+	Result,
+};
+
+const MAGIC: &[u8] = b"\x7fELF";
+const CLASS_32: u8 = 1;
+const DATA_LE: u8 = 1;
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHN_UNDEF: u16 = 0;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+const SHDR_SIZE: usize = 40;
+const SYM_SIZE: usize = 16;
+
+fn le_u16(data: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le_u32(data: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+}
+
+/// Whether `data` starts with the ELF magic
+pub fn is_elf(data: &[u8]) -> bool {
+	data.starts_with(MAGIC)
+}
+
+fn check_header(data: &[u8]) -> Result<()> {
+	if data.len() < EHDR_SIZE || !is_elf(data) {
+		return Err(LoadError::Elf("not an ELF file"));
+	}
+
+	if data[4] != CLASS_32 || data[5] != DATA_LE {
+		return Err(LoadError::Elf("not a little endian ELF32 file"));
+	}
+
+	Ok(())
+}
+
+/// `len` bytes at `offset`, or `err` if that is past the end of `data`
+fn slice<'a>(data: &'a [u8], offset: usize, len: usize, err: &'static str) -> Result<&'a [u8]> {
+	offset.checked_add(len)
+		.and_then(|end| data.get(offset..end))
+		.ok_or(LoadError::Elf(err))
+}
+
+/// NUL terminated string at `offset` in a string table
+fn string(strtab: &[u8], offset: usize) -> Result<&str> {
+	let bytes = strtab.get(offset..).ok_or(LoadError::Elf("symbol name out of bounds"))?;
+	let len = bytes.iter().position(|b| *b == 0).ok_or(LoadError::Elf("unterminated symbol name"))?;
+	core::str::from_utf8(&bytes[..len]).map_err(|_| LoadError::Elf("symbol name isn't UTF-8"))
+}
+
+/// Call `f` with the name, value and size of every defined symbol in the symbol tables of `data`
+///
+/// Section and file symbols are skipped, as are symbols without a name.
+pub fn elf_symbols<F: FnMut(&str, u32, u32)>(data: &[u8], mut f: F) -> Result<()> {
+	check_header(data)?;
+
+	let shoff = le_u32(data, 32) as usize;
+	let shentsize = le_u16(data, 46) as usize;
+	let shnum = le_u16(data, 48) as usize;
+
+	if shnum > 0 && shentsize < SHDR_SIZE {
+		return Err(LoadError::Elf("section header too small"));
+	}
+
+	let section = |i: usize| {
+		let offset = shentsize.checked_mul(i)
+			.and_then(|o| o.checked_add(shoff))
+			.ok_or(LoadError::Elf("section header out of bounds"))?;
+		slice(data, offset, SHDR_SIZE, "section header out of bounds")
+	};
+
+	for i in 0..shnum {
+		let shdr = section(i)?;
+		if le_u32(shdr, 4) != SHT_SYMTAB {
+			continue;
+		}
+
+		let symtab = slice(data, le_u32(shdr, 16) as usize, le_u32(shdr, 20) as usize, "symbol table out of bounds")?;
+		let strhdr = section(le_u32(shdr, 24) as usize)?;
+		let strtab = slice(data, le_u32(strhdr, 16) as usize, le_u32(strhdr, 20) as usize, "string table out of bounds")?;
+
+		for sym in symtab.chunks_exact(SYM_SIZE) {
+			let kind = sym[12] & 0xf;
+			if le_u16(sym, 14) == SHN_UNDEF || kind == STT_SECTION || kind == STT_FILE {
+				continue;
+			}
+
+			let name = string(strtab, le_u32(sym, 0) as usize)?;
+			if !name.is_empty() {
+				f(name, le_u32(sym, 4), le_u32(sym, 8));
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Load the `PT_LOAD` segments of a little endian ELF32 file into `memory`, returns the entry address
+///
+/// Segments are placed at their physical address, the part of a segment not present in the file is zeroed.
+pub fn load_elf<M: Memory + ?Sized>(data: &[u8], memory: &mut M) -> Result<Option<u32>> {
+	check_header(data)?;
+
+	let entry = le_u32(data, 24);
+	let phoff = le_u32(data, 28) as usize;
+	let phentsize = le_u16(data, 42) as usize;
+	let phnum = le_u16(data, 44) as usize;
+
+	if phnum > 0 && phentsize < PHDR_SIZE {
+		return Err(LoadError::Elf("program header too small"));
+	}
+
+	for i in 0..phnum {
+		let offset = phentsize.checked_mul(i)
+			.and_then(|o| o.checked_add(phoff))
+			.filter(|o| o.checked_add(PHDR_SIZE).map_or(false, |end| end <= data.len()))
+			.ok_or(LoadError::Elf("program header out of bounds"))?;
+		let phdr = &data[offset..offset + PHDR_SIZE];
+
+		if le_u32(phdr, 0) != PT_LOAD {
+			continue;
+		}
+
+		let file_offset = le_u32(phdr, 4) as usize;
+		let paddr = le_u32(phdr, 12);
+		let filesz = le_u32(phdr, 16) as usize;
+		let memsz = le_u32(phdr, 20);
+
+		if (memsz as usize) < filesz {
+			return Err(LoadError::Elf("segment file size larger than memory size"));
+		}
+
+		let contents = slice(data, file_offset, filesz, "segment out of bounds")?;
+		memory.write_bytes(paddr, contents)?;
+
+		// Zero the rest of the segment, e.g. .bss
+		let zeros = [0u8; 256];
+		let mut addr = paddr.wrapping_add(filesz as u32);
+		let mut remaining = memsz - filesz as u32;
+		while remaining > 0 {
+			let len = remaining.min(zeros.len() as u32);
+			memory.write_bytes(addr, &zeros[..len as usize])?;
+			addr = addr.wrapping_add(len);
+			remaining -= len;
+		}
+	}
+
+	Ok(Some(entry))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::memory::{
+		Image,
+		PageSize,
+	};
+	use bibe_instr::Width;
+
+	/// ELF header and one program header followed by `contents`
+	fn elf(entry: u32, paddr: u32, contents: &[u8], memsz: u32) -> [u8; 96] {
+		let mut data = [0u8; 96];
+		let offset = (EHDR_SIZE + PHDR_SIZE) as u32;
+
+		data[..4].copy_from_slice(MAGIC);
+		data[4] = CLASS_32;
+		data[5] = DATA_LE;
+		data[24..28].copy_from_slice(&entry.to_le_bytes());
+		data[28..32].copy_from_slice(&(EHDR_SIZE as u32).to_le_bytes());
+		data[42..44].copy_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+		data[44..46].copy_from_slice(&1u16.to_le_bytes());
+
+		let phdr = &mut data[EHDR_SIZE..EHDR_SIZE + PHDR_SIZE];
+		phdr[0..4].copy_from_slice(&PT_LOAD.to_le_bytes());
+		phdr[4..8].copy_from_slice(&offset.to_le_bytes());
+		phdr[12..16].copy_from_slice(&paddr.to_le_bytes());
+		phdr[16..20].copy_from_slice(&(contents.len() as u32).to_le_bytes());
+		phdr[20..24].copy_from_slice(&memsz.to_le_bytes());
+
+		data[offset as usize..offset as usize + contents.len()].copy_from_slice(contents);
+		data
+	}
+
+	#[test]
+	fn test_load() {
+		let mut image = Image::new(PageSize::K4);
+		assert!(image.write(0x1004, Width::Word, 0xffffffff).is_ok());
+
+		let data = elf(0x1000, 0x1000, &[0x78, 0x56, 0x34, 0x12], 8);
+		assert_eq!(load_elf(&data, &mut image).ok(), Some(Some(0x1000)));
+		assert_eq!(image.read(0x1000, Width::Word).ok(), Some(0x12345678));
+
+		// Zero filled up to the memory size
+		assert_eq!(image.read(0x1004, Width::Word).ok(), Some(0));
+	}
+
+	#[test]
+	fn test_symbols() {
+		// Header, string table, symbol table, then the null, string table and symbol table sections
+		let mut data = [0u8; 320];
+		data[..96].copy_from_slice(&elf(0, 0, &[], 0));
+
+		let strtab = b"\0main\0data\0";
+		data[96..96 + strtab.len()].copy_from_slice(strtab);
+
+		// Null symbol, main, data, an undefined symbol and a section symbol
+		let syms: [(u32, u32, u32, u8, u16); 5] = [
+			(0, 0, 0, 0, 0),
+			(1, 0x100, 0x20, 2, 1),
+			(6, 0x2000, 4, 1, 2),
+			(1, 0, 0, 2, SHN_UNDEF),
+			(0, 0x100, 0, STT_SECTION, 1),
+		];
+		for (i, (name, value, size, kind, shndx)) in syms.into_iter().enumerate() {
+			let sym = &mut data[112 + i * SYM_SIZE..][..SYM_SIZE];
+			sym[0..4].copy_from_slice(&name.to_le_bytes());
+			sym[4..8].copy_from_slice(&value.to_le_bytes());
+			sym[8..12].copy_from_slice(&size.to_le_bytes());
+			sym[12] = kind;
+			sym[14..16].copy_from_slice(&shndx.to_le_bytes());
+		}
+
+		let shoff = 112 + syms.len() * SYM_SIZE;
+		let sections: [(u32, u32, u32, u32); 3] = [
+			(0, 0, 0, 0),
+			(3, 96, strtab.len() as u32, 0),
+			(SHT_SYMTAB, 112, (syms.len() * SYM_SIZE) as u32, 1),
+		];
+		for (i, (kind, offset, size, link)) in sections.into_iter().enumerate() {
+			let shdr = &mut data[shoff + i * SHDR_SIZE..][..SHDR_SIZE];
+			shdr[4..8].copy_from_slice(&kind.to_le_bytes());
+			shdr[16..20].copy_from_slice(&offset.to_le_bytes());
+			shdr[20..24].copy_from_slice(&size.to_le_bytes());
+			shdr[24..28].copy_from_slice(&link.to_le_bytes());
+		}
+		data[32..36].copy_from_slice(&(shoff as u32).to_le_bytes());
+		data[46..48].copy_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+		data[48..50].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+
+		let expected = [("main", 0x100, 0x20), ("data", 0x2000, 4)];
+		let mut count = 0;
+		let res = elf_symbols(&data, |name, value, size| {
+			assert_eq!(Some(&(name, value, size)), expected.get(count));
+			count += 1;
+		});
+
+		assert!(res.is_ok());
+		assert_eq!(count, expected.len());
+	}
+
+	#[test]
+	fn test_errors() {
+		let mut image = Image::new(PageSize::K4);
+
+		assert!(matches!(load_elf(b"MZ", &mut image), Err(LoadError::Elf(_))));
+
+		let mut data = elf(0, 0, &[1, 2, 3, 4], 2);
+		assert!(matches!(load_elf(&data, &mut image), Err(LoadError::Elf(_))));
+
+		// Program headers past the end of the file
+		data[28] = 0xff;
+		assert!(matches!(load_elf(&data, &mut image), Err(LoadError::Elf(_))));
+	}
+}
@@ -0,0 +1,135 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use crate::memory::Memory;
+
+use super::{
+	be_u32,
+	decode_hex,
+	LoadError,
+	Result,
+	MAX_RECORD,
+};
+
+const DATA: u8 = 0x00;
+const EOF: u8 = 0x01;
+const EXT_SEGMENT_ADDR: u8 = 0x02;
+const START_SEGMENT_ADDR: u8 = 0x03;
+const EXT_LINEAR_ADDR: u8 = 0x04;
+const START_LINEAR_ADDR: u8 = 0x05;
+
+/// Load an Intel HEX image into `memory`, returns the start address if the image has one
+pub fn load_ihex<M: Memory + ?Sized>(src: &str, memory: &mut M) -> Result<Option<u32>> {
+	let mut buf = [0u8; MAX_RECORD];
+	let mut base = 0u32;
+	let mut entry = None;
+
+	for (i, line) in src.lines().enumerate() {
+		let line_num = i + 1;
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		if !line.starts_with(':') {
+			return Err(LoadError::Malformed { line: line_num });
+		}
+
+		// Length, address, type and checksum
+		let record = decode_hex(&line[1..], &mut buf, line_num)?;
+		if record.len() < 5 || record.len() != record[0] as usize + 5 {
+			return Err(LoadError::Malformed { line: line_num });
+		}
+
+		let (contents, checksum) = record.split_at(record.len() - 1);
+		let expected = contents.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)).wrapping_neg();
+		if expected != checksum[0] {
+			return Err(LoadError::Checksum {
+				line: line_num,
+				expected,
+				actual: checksum[0],
+			});
+		}
+
+		let offset = be_u32(&contents[1..3]);
+		let kind = contents[3];
+		let data = &contents[4..];
+
+		match kind {
+			DATA => memory.write_bytes(base.wrapping_add(offset), data)?,
+			EOF => return Ok(entry),
+			EXT_SEGMENT_ADDR | EXT_LINEAR_ADDR => {
+				if data.len() != 2 {
+					return Err(LoadError::Malformed { line: line_num });
+				}
+
+				base = if kind == EXT_SEGMENT_ADDR {
+					be_u32(data) << 4
+				} else {
+					be_u32(data) << 16
+				};
+			},
+			START_SEGMENT_ADDR | START_LINEAR_ADDR => {
+				if data.len() != 4 {
+					return Err(LoadError::Malformed { line: line_num });
+				}
+
+				entry = Some(if kind == START_SEGMENT_ADDR {
+					// CS:IP pair
+					(be_u32(&data[0..2]) << 4).wrapping_add(be_u32(&data[2..4]))
+				} else {
+					be_u32(data)
+				});
+			},
+			_ => return Err(LoadError::UnsupportedRecord { line: line_num, kind }),
+		}
+	}
+
+	Err(LoadError::MissingEof)
+}
+
+#[cfg(test)]
+#[cfg(feature = "std")]
+mod test {
+	use super::*;
+	use crate::memory::{
+		Image,
+		PageSize,
+	};
+	use bibe_instr::Width;
+
+	#[test]
+	fn test_load() {
+		let src = "\
+			:020000041000EA\n\
+			:0400100078563412D8\n\
+			:0400000510000010D7\n\
+			:00000001FF\n";
+
+		let mut image = Image::new(PageSize::K4);
+		let entry = load_ihex(src, &mut image);
+
+		assert_eq!(entry.ok(), Some(Some(0x10000010)));
+		assert_eq!(image.read(0x10000010, Width::Word).ok(), Some(0x12345678));
+	}
+
+	#[test]
+	fn test_errors() {
+		let mut image = Image::new(PageSize::K4);
+
+		assert!(matches!(
+			load_ihex(":0400100078563412D9\n:00000001FF\n", &mut image),
+			Err(LoadError::Checksum { line: 1, expected: 0xd8, actual: 0xd9 })
+		));
+		assert!(matches!(
+			load_ihex(":0400100078563412D8\n", &mut image),
+			Err(LoadError::MissingEof)
+		));
+		assert!(matches!(
+			load_ihex("0400100078563412E8\n", &mut image),
+			Err(LoadError::Malformed { line: 1 })
+		));
+		assert!(matches!(
+			load_ihex(":00000006FA\n", &mut image),
+			Err(LoadError::UnsupportedRecord { line: 1, kind: 6 })
+		));
+	}
+}
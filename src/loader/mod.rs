@@ -0,0 +1,141 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use core::fmt;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+use crate::{
+	memory::Memory,
+	Interrupt,
+};
+
+mod elf;
+mod ihex;
+mod srec;
+
+pub use elf::{
+	elf_symbols,
+	is_elf,
+	load_elf,
+};
+pub use ihex::load_ihex;
+pub use srec::load_srec;
+
+/// Longest record either format can produce, in bytes
+const MAX_RECORD: usize = 260;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+	IntelHex,
+	SRecord,
+}
+
+impl Format {
+	/// Guess the format from a file extension
+	pub fn from_extension(ext: &str) -> Option<Self> {
+		match ext {
+			"hex" | "ihex" | "ihx" => Some(Format::IntelHex),
+			"srec" | "s19" | "s28" | "s37" | "mot" => Some(Format::SRecord),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+	/// Line isn't a valid record
+	Malformed { line: usize },
+	/// Record checksum doesn't match its contents
+	Checksum { line: usize, expected: u8, actual: u8 },
+	/// Record type isn't defined by the format
+	UnsupportedRecord { line: usize, kind: u8 },
+	/// Input ended without an end-of-file record
+	MissingEof,
+	/// Writing record data to memory faulted at the given address
+	Memory(u32),
+	/// There's no memory attached to load into
+	NoMemory,
+	/// ELF file is malformed or not supported
+	Elf(&'static str),
+	#[cfg(feature = "std")]
+	Io(std::io::Error),
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			LoadError::Malformed { line } => write!(f, "line {line}: malformed record"),
+			LoadError::Checksum { line, expected, actual } => {
+				write!(f, "line {line}: checksum mismatch, expected {expected:02x}, got {actual:02x}")
+			},
+			LoadError::UnsupportedRecord { line, kind } => write!(f, "line {line}: unsupported record type {kind}"),
+			LoadError::MissingEof => write!(f, "missing end of file record"),
+			LoadError::Memory(addr) => write!(f, "memory fault at {addr:08x}"),
+			LoadError::NoMemory => write!(f, "no memory attached"),
+			LoadError::Elf(reason) => write!(f, "ELF: {reason}"),
+			#[cfg(feature = "std")]
+			LoadError::Io(e) => write!(f, "{e}"),
+		}
+	}
+}
+
+impl From<Interrupt> for LoadError {
+	fn from(e: Interrupt) -> Self {
+		LoadError::Memory(e.err1)
+	}
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for LoadError {
+	fn from(e: std::io::Error) -> Self {
+		LoadError::Io(e)
+	}
+}
+
+pub type Result<T> = core::result::Result<T, LoadError>;
+
+/// Load an image in the given format into `memory`, returns the entry address if the image has one
+pub fn load<M: Memory + ?Sized>(format: Format, src: &str, memory: &mut M) -> Result<Option<u32>> {
+	match format {
+		Format::IntelHex => load_ihex(src, memory),
+		Format::SRecord => load_srec(src, memory),
+	}
+}
+
+#[cfg(feature = "std")]
+pub fn load_from<M: Memory + ?Sized>(format: Format, r: &mut dyn std::io::Read, memory: &mut M) -> Result<Option<u32>> {
+	use std::string::String;
+
+	let mut src = String::new();
+	r.read_to_string(&mut src)?;
+	load(format, &src, memory)
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+	match c {
+		b'0'..=b'9' => Some(c - b'0'),
+		b'a'..=b'f' => Some(c - b'a' + 10),
+		b'A'..=b'F' => Some(c - b'A' + 10),
+		_ => None,
+	}
+}
+
+/// Decode a string of hex digit pairs into `buf`, returns the decoded bytes
+fn decode_hex<'a>(s: &str, buf: &'a mut [u8; MAX_RECORD], line: usize) -> Result<&'a [u8]> {
+	let s = s.as_bytes();
+	if s.len() % 2 != 0 || s.len() / 2 > buf.len() {
+		return Err(LoadError::Malformed { line });
+	}
+
+	for (i, pair) in s.chunks(2).enumerate() {
+		let hi = hex_digit(pair[0]).ok_or(LoadError::Malformed { line })?;
+		let lo = hex_digit(pair[1]).ok_or(LoadError::Malformed { line })?;
+		buf[i] = hi << 4 | lo;
+	}
+
+	Ok(&buf[..s.len() / 2])
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+	bytes.iter().fold(0, |acc, b| acc << 8 | *b as u32)
+}
@@ -0,0 +1,113 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use crate::memory::Memory;
+
+use super::{
+	be_u32,
+	decode_hex,
+	LoadError,
+	Result,
+	MAX_RECORD,
+};
+
+/// Load a Motorola S-record image into `memory`, returns the start address if the image has one
+pub fn load_srec<M: Memory + ?Sized>(src: &str, memory: &mut M) -> Result<Option<u32>> {
+	let mut buf = [0u8; MAX_RECORD];
+
+	for (i, line) in src.lines().enumerate() {
+		let line_num = i + 1;
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		let bytes = line.as_bytes();
+		if bytes.len() < 4 || bytes[0] != b'S' || !bytes[1].is_ascii_digit() {
+			return Err(LoadError::Malformed { line: line_num });
+		}
+
+		let kind = bytes[1] - b'0';
+		// Count, address, data and checksum
+		let record = decode_hex(&line[2..], &mut buf, line_num)?;
+		if record.len() < 2 || record.len() != record[0] as usize + 1 {
+			return Err(LoadError::Malformed { line: line_num });
+		}
+
+		let (contents, checksum) = record.split_at(record.len() - 1);
+		let expected = !contents.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+		if expected != checksum[0] {
+			return Err(LoadError::Checksum {
+				line: line_num,
+				expected,
+				actual: checksum[0],
+			});
+		}
+
+		let addr_len = match kind {
+			0 | 1 | 5 | 9 => 2,
+			2 | 6 | 8 => 3,
+			3 | 7 => 4,
+			_ => return Err(LoadError::UnsupportedRecord { line: line_num, kind }),
+		};
+
+		let contents = &contents[1..];
+		if contents.len() < addr_len {
+			return Err(LoadError::Malformed { line: line_num });
+		}
+
+		let (addr, data) = contents.split_at(addr_len);
+		let addr = be_u32(addr);
+
+		match kind {
+			// Header and record counts carry nothing to load
+			0 | 5 | 6 => (),
+			1 | 2 | 3 => memory.write_bytes(addr, data)?,
+			// Termination records carry the start address
+			_ => return Ok(Some(addr)),
+		}
+	}
+
+	Err(LoadError::MissingEof)
+}
+
+#[cfg(test)]
+#[cfg(feature = "std")]
+mod test {
+	use super::*;
+	use crate::memory::{
+		Image,
+		PageSize,
+	};
+	use bibe_instr::Width;
+
+	#[test]
+	fn test_load() {
+		let src = "\
+			S00600004844521B\n\
+			S3091000002078563412B2\n\
+			S70510000020CA\n";
+
+		let mut image = Image::new(PageSize::K4);
+		let entry = load_srec(src, &mut image);
+
+		assert_eq!(entry.ok(), Some(Some(0x10000020)));
+		assert_eq!(image.read(0x10000020, Width::Word).ok(), Some(0x12345678));
+	}
+
+	#[test]
+	fn test_errors() {
+		let mut image = Image::new(PageSize::K4);
+
+		assert!(matches!(
+			load_srec("S3091000002078563412B3\nS9030000FC\n", &mut image),
+			Err(LoadError::Checksum { line: 1, expected: 0xb2, actual: 0xb3 })
+		));
+		assert!(matches!(
+			load_srec("S3091000002078563412B2\n", &mut image),
+			Err(LoadError::MissingEof)
+		));
+		assert!(matches!(
+			load_srec("S4030000FC\n", &mut image),
+			Err(LoadError::UnsupportedRecord { line: 1, kind: 4 })
+		));
+	}
+}
@@ -0,0 +1,690 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Command line runner for Big Bend programs
+use std::{
+	cell::RefCell,
+	env,
+	fs,
+	io,
+	path::{
+		Path,
+		PathBuf,
+	},
+	process::ExitCode,
+	rc::Rc,
+};
+
+#[cfg(feature = "config")]
+use bibe_emu::config::MachineConfig;
+use bibe_emu::{
+	coverage::{
+		Coverage,
+		LineMap,
+	},
+	loader::{
+		self,
+		Format,
+	},
+	memory::{
+		Mapped,
+		Memory,
+		SimpleImage,
+		Uart,
+	},
+	monitor::Monitor,
+	profile::{
+		Profiler,
+		Weight,
+	},
+	state::{
+		csr::*,
+		semihost::Semihost,
+		Engine,
+		FaultRecord,
+		ResetConfig,
+		State,
+		StopReason,
+	},
+	stats::Statistics,
+	symbols::SymbolMap,
+	target::StdTarget,
+	trace::{
+		Symbols,
+		Tracer,
+	},
+};
+use bibe_instr::{
+	csr::regs::ISR_ENTER_REG,
+	Instruction,
+	Register,
+	Width,
+};
+
+const USAGE: &str = "\
+usage: bibe-emu [options] [<image>] [-- <guest args>...]
+
+options:
+  -t, --target <target>        target string, e.g. bibe32i (default bibe32)
+  -f, --format <format>        raw, elf, ihex or srec, guessed from the file by default
+  -m, --memory <start>:<size>[:<wait states>]
+                               map RAM, can be repeated (default 0:16M)
+      --load-addr <addr>       address raw images are loaded at (default 0)
+      --entry <addr>           start address, overrides the image's entry
+  -n, --max-instructions <n>   stop after executing n instructions
+      --max-cycles <n>         stop after n cycles
+      --trace                  print each instruction and the registers it changed to stderr, implies --engine interp
+      --uart <addr>            map a UART connected to stdio at addr
+      --swi <handle|exit>      SWIs enter the guest's handler, or exit with o0 as the status (default handle)
+      --engine <interp|block>  execution engine (default interp)
+      --semihost <dir>         enable semihosting, guest files are limited to dir
+      --symbols <file>         load `addr name` symbols, ELF images provide their own
+      --profile <file>         write folded stacks weighted by cycles to file and print
+                               a per-function profile at exit
+      --coverage <file>        add the addresses executed by this run to a coverage listing
+      --lcov <file>            write lcov coverage of this run, requires --line-map
+      --line-map <file>        source line of each address, `addr file:line` per line
+      --stats                  print instruction mix and memory access statistics at exit
+      --monitor                start in the interactive monitor on stdin/stdout
+      --machine <file>         build the machine from a description, see boards/,
+                               replaces --target, --memory and --uart
+  -h, --help                   print this message
+
+The exit code is the guest's exit status, 1 if it doesn't fit in 0-255. 124 is returned
+when a limit is reached, 125 on lockup and 2 for usage or load errors.";
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_ERROR: u8 = 2;
+const EXIT_LIMIT: u8 = 124;
+const EXIT_LOCKUP: u8 = 125;
+
+const DEFAULT_MEMORY: u32 = 16 << 20;
+
+type EmuState = State<StdTarget, Mapped, Vec<Box<dyn CsrBlock>>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ImageFormat {
+	Raw,
+	Elf,
+	Text(Format),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SwiPolicy {
+	Handle,
+	Exit,
+}
+
+struct Region {
+	start: u32,
+	size: u32,
+	wait_states: u32,
+}
+
+struct Options {
+	image: Option<PathBuf>,
+	machine: Option<PathBuf>,
+	format: Option<ImageFormat>,
+	target: String,
+	memory: Vec<Region>,
+	load_addr: u32,
+	entry: Option<u32>,
+	max_instructions: Option<u64>,
+	max_cycles: Option<u64>,
+	trace: bool,
+	monitor: bool,
+	symbols: Option<PathBuf>,
+	profile: Option<PathBuf>,
+	coverage: Option<PathBuf>,
+	lcov: Option<PathBuf>,
+	line_map: Option<PathBuf>,
+	stats: bool,
+	uart: Option<u32>,
+	swi: SwiPolicy,
+	engine: Engine,
+	semihost: Option<PathBuf>,
+	args: Vec<String>,
+}
+
+/// Decimal or `0x` prefixed hex
+fn parse_u64(s: &str) -> Option<u64> {
+	match s.strip_prefix("0x") {
+		Some(hex) => u64::from_str_radix(hex, 16).ok(),
+		None => s.parse().ok(),
+	}
+}
+
+fn parse_u32(s: &str) -> Option<u32> {
+	parse_u64(s)?.try_into().ok()
+}
+
+/// Number with an optional `K` or `M` suffix
+fn parse_size(s: &str) -> Option<u32> {
+	let (s, shift) = match s.as_bytes().last()? {
+		b'K' | b'k' => (&s[..s.len() - 1], 10),
+		b'M' | b'm' => (&s[..s.len() - 1], 20),
+		_ => (s, 0),
+	};
+
+	parse_u32(s)?.checked_mul(1 << shift)
+}
+
+fn parse_region(s: &str) -> Option<Region> {
+	let mut parts = s.split(':');
+	let region = Region {
+		start: parse_u32(parts.next()?)?,
+		size: parse_size(parts.next()?)?,
+		wait_states: match parts.next() {
+			Some(wait) => parse_u32(wait)?,
+			None => 0,
+		},
+	};
+
+	parts.next().is_none().then_some(region)
+}
+
+fn parse_format(s: &str) -> Option<ImageFormat> {
+	match s {
+		"raw" | "bin" => Some(ImageFormat::Raw),
+		"elf" => Some(ImageFormat::Elf),
+		_ => Format::from_extension(s).map(ImageFormat::Text),
+	}
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+	let mut options = Options {
+		image: None,
+		machine: None,
+		format: None,
+		target: String::from("bibe32"),
+		memory: Vec::new(),
+		load_addr: 0,
+		entry: None,
+		max_instructions: None,
+		max_cycles: None,
+		trace: false,
+		monitor: false,
+		symbols: None,
+		profile: None,
+		coverage: None,
+		lcov: None,
+		line_map: None,
+		stats: false,
+		uart: None,
+		swi: SwiPolicy::Handle,
+		engine: Engine::Interpreter,
+		semihost: None,
+		args: Vec::new(),
+	};
+	while let Some(arg) = args.next() {
+		let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} requires a value"));
+		let invalid = |name: &str, value: &str| format!("invalid value for {name}: {value}");
+
+		match arg.as_str() {
+			"-h" | "--help" => return Err(String::new()),
+			"-t" | "--target" => options.target = value(&arg)?,
+			"-f" | "--format" => {
+				let v = value(&arg)?;
+				options.format = Some(parse_format(&v).ok_or_else(|| invalid(&arg, &v))?);
+			},
+			"-m" | "--memory" => {
+				let v = value(&arg)?;
+				options.memory.push(parse_region(&v).ok_or_else(|| invalid(&arg, &v))?);
+			},
+			"--load-addr" => {
+				let v = value(&arg)?;
+				options.load_addr = parse_u32(&v).ok_or_else(|| invalid(&arg, &v))?;
+			},
+			"--entry" => {
+				let v = value(&arg)?;
+				options.entry = Some(parse_u32(&v).ok_or_else(|| invalid(&arg, &v))?);
+			},
+			"-n" | "--max-instructions" => {
+				let v = value(&arg)?;
+				options.max_instructions = Some(parse_u64(&v).ok_or_else(|| invalid(&arg, &v))?);
+			},
+			"--max-cycles" => {
+				let v = value(&arg)?;
+				options.max_cycles = Some(parse_u64(&v).ok_or_else(|| invalid(&arg, &v))?);
+			},
+			"--trace" => options.trace = true,
+			"--monitor" => options.monitor = true,
+			"--symbols" => options.symbols = Some(PathBuf::from(value(&arg)?)),
+			"--profile" => options.profile = Some(PathBuf::from(value(&arg)?)),
+			"--coverage" => options.coverage = Some(PathBuf::from(value(&arg)?)),
+			"--lcov" => options.lcov = Some(PathBuf::from(value(&arg)?)),
+			"--line-map" => options.line_map = Some(PathBuf::from(value(&arg)?)),
+			"--stats" => options.stats = true,
+			"--uart" => {
+				let v = value(&arg)?;
+				options.uart = Some(parse_u32(&v).ok_or_else(|| invalid(&arg, &v))?);
+			},
+			"--swi" => {
+				let v = value(&arg)?;
+				options.swi = match v.as_str() {
+					"handle" => SwiPolicy::Handle,
+					"exit" => SwiPolicy::Exit,
+					_ => return Err(invalid(&arg, &v)),
+				};
+			},
+			"--engine" => {
+				let v = value(&arg)?;
+				options.engine = match v.as_str() {
+					"interp" => Engine::Interpreter,
+					"block" => Engine::Block,
+					_ => return Err(invalid(&arg, &v)),
+				};
+			},
+			"--semihost" => options.semihost = Some(PathBuf::from(value(&arg)?)),
+			"--machine" => options.machine = Some(PathBuf::from(value(&arg)?)),
+			"--" => {
+				options.args.extend(args.by_ref());
+				break;
+			},
+			_ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+			_ if options.image.is_none() => options.image = Some(PathBuf::from(arg)),
+			_ => return Err(format!("unexpected argument {arg}")),
+		}
+	}
+
+	if options.image.is_none() && options.machine.is_none() {
+		return Err(String::from("no image given"));
+	}
+
+	if options.lcov.is_some() && options.line_map.is_none() {
+		return Err(String::from("--lcov requires --line-map"));
+	}
+
+	Ok(options)
+}
+
+fn build_memory(options: &Options) -> Result<Mapped, String> {
+	let mut memory = Mapped::new();
+
+	for region in &options.memory {
+		if memory.map_with_wait_states(region.start, Box::new(SimpleImage::new(region.size)), region.wait_states).is_none() {
+			return Err(format!("memory region at {:08x} overlaps another region", region.start));
+		}
+	}
+
+	if options.memory.is_empty() {
+		memory.map(0, Box::new(SimpleImage::new(DEFAULT_MEMORY)));
+	}
+
+	if let Some(addr) = options.uart {
+		if memory.map(addr, Box::new(Uart::new())).is_none() {
+			return Err(format!("UART at {addr:08x} overlaps memory"));
+		}
+	}
+
+	Ok(memory)
+}
+
+fn guess_format(path: &Path, data: &[u8]) -> ImageFormat {
+	if loader::is_elf(data) {
+		return ImageFormat::Elf;
+	}
+
+	path.extension()
+		.and_then(|ext| ext.to_str())
+		.and_then(Format::from_extension)
+		.map_or(ImageFormat::Raw, ImageFormat::Text)
+}
+
+/// Load the image, returns its entry address if it has one
+fn load_image(path: &Path, options: &Options, state: &mut EmuState) -> Result<Option<u32>, String> {
+	let data = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+	let format = options.format.unwrap_or_else(|| guess_format(path, &data));
+
+	let res = match format {
+		ImageFormat::Raw => state.write_bytes(options.load_addr, &data)
+			.map(|_| None)
+			.map_err(loader::LoadError::from),
+		ImageFormat::Elf => {
+			let symbols = SymbolMap::from_elf(&data).map_err(|e| format!("{}: {e}", path.display()))?;
+			if !symbols.is_empty() {
+				state.set_symbols(Some(symbols));
+			}
+			loader::load_elf(&data, state)
+		},
+		ImageFormat::Text(format) => match std::str::from_utf8(&data) {
+			Ok(src) => loader::load(format, src, state),
+			Err(_) => return Err(format!("{}: not a text file", path.display())),
+		},
+	};
+
+	res.map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn load_symbols(path: &Path) -> Result<SymbolMap, String> {
+	let src = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+	SymbolMap::from_text(&src).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn print_fault(name: &str, fault: &FaultRecord, state: &EmuState) {
+	eprintln!(
+		"  {name}: {:?} at {}, err1 {:08x}, err2 {:08x}",
+		fault.kind, state.describe(fault.core.read_pc()), fault.err1, fault.err2,
+	);
+}
+
+fn is_swi(instr: &Instruction) -> bool {
+	matches!(instr, Instruction::Csr(i) if !i.op.is_load() && i.imm == ISR_ENTER_REG)
+}
+
+/// Statuses the host can't represent are reported as a failure rather than truncated
+fn exit_code(status: i32) -> u8 {
+	u8::try_from(status).unwrap_or(EXIT_FAILURE)
+}
+
+#[cfg(feature = "config")]
+fn build_machine(path: &Path) -> Result<EmuState, String> {
+	MachineConfig::load(path)
+		.and_then(|config| config.build())
+		.map_err(|e| format!("{}: {e}", path.display()))
+}
+
+#[cfg(not(feature = "config"))]
+fn build_machine(_path: &Path) -> Result<EmuState, String> {
+	Err(String::from("--machine requires the config feature"))
+}
+
+fn create_state(options: &Options) -> Result<EmuState, String> {
+	if let Some(path) = &options.machine {
+		return build_machine(path);
+	}
+
+	let target = StdTarget::parse(&options.target).ok_or_else(|| format!("invalid target {}", options.target))?;
+	let memory = build_memory(options)?;
+
+	Ok(State::new(target, Some(memory), vec![
+		Box::new(PsrBlock::new()),
+		Box::new(IsrBlock::new()),
+		Box::new(DbgOutBlock::new()),
+		Box::new(CycleBlock::new()),
+	]))
+}
+
+fn run(options: Options) -> Result<ExitCode, String> {
+	let mut state = create_state(&options)?;
+
+	let entry = match &options.image {
+		Some(path) => load_image(path, &options, &mut state)?,
+		None => None,
+	};
+
+	if let Some(path) = &options.symbols {
+		state.set_symbols(Some(load_symbols(path)?));
+	}
+
+	// Machine descriptions have their own reset vector
+	let fallback = if options.machine.is_some() { None } else { Some(options.load_addr) };
+	if let Some(reset_vector) = options.entry.or(entry).or(fallback) {
+		state.set_reset_config(ResetConfig {
+			reset_vector,
+			..state.reset_config()
+		});
+	}
+	state.reset();
+
+	// Blocks run many instructions per step, which would hide them from the trace
+	state.set_engine(if options.trace { Engine::Interpreter } else { options.engine });
+	state.set_cycle_budget(options.max_cycles);
+	if let Some(root) = &options.semihost {
+		let mut semihost = Semihost::new(root);
+		semihost.args = options.args.clone();
+		semihost.env = env::vars().collect();
+		state.set_semihost(Some(semihost));
+	}
+
+	let profiler = options.profile.as_ref().map(|_| Rc::new(RefCell::new(Profiler::new())));
+	if let Some(profiler) = &profiler {
+		state.add_observer(Box::new(profiler.clone()));
+	}
+
+	let line_map = match &options.line_map {
+		Some(path) => {
+			let src = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+			Some(LineMap::from_text(&src).map_err(|e| format!("{}: {e}", path.display()))?)
+		},
+		None => None,
+	};
+
+	let coverage = (options.coverage.is_some() || options.lcov.is_some()).then(|| Rc::new(RefCell::new(Coverage::new())));
+	if let Some(coverage) = &coverage {
+		state.add_observer(Box::new(coverage.clone()));
+	}
+
+	let stats = options.stats.then(|| {
+		let regions = state.memory().into_iter().flat_map(|memory| memory.regions());
+		Rc::new(RefCell::new(Statistics::with_regions(regions)))
+	});
+	if let Some(stats) = &stats {
+		state.add_observer(Box::new(stats.clone()));
+	}
+
+	let code = if options.monitor {
+		let mut monitor = Monitor::new(&mut state);
+		monitor.run(&mut io::stdin().lock(), &mut io::stdout()).map_err(|e| e.to_string())?;
+		match state.stop_reason() {
+			Some(StopReason::Exit(status)) => exit_code(*status),
+			_ => 0,
+		}
+	} else {
+		execute(&options, &mut state)?
+	};
+
+	if let (Some(path), Some(profiler)) = (&options.profile, &profiler) {
+		write_profile(path, &profiler.borrow(), &state)?;
+	}
+
+	if let Some(coverage) = &coverage {
+		write_coverage(&options, &coverage.borrow(), line_map.as_ref())?;
+	}
+
+	if let Some(stats) = &stats {
+		eprint!("{}", stats.borrow());
+	}
+
+	Ok(ExitCode::from(code))
+}
+
+/// Folded stacks weighted by cycles to `path`, the per-function report to stderr
+fn write_profile(path: &Path, profiler: &Profiler, state: &EmuState) -> Result<(), String> {
+	let symbols = state.symbols().map(|symbols| symbols as &dyn Symbols);
+
+	let mut folded = String::new();
+	let mut report = String::new();
+	profiler.write_folded(symbols, Weight::Cycles, &mut folded).map_err(|e| e.to_string())?;
+	profiler.write_report(symbols, &mut report).map_err(|e| e.to_string())?;
+
+	fs::write(path, folded).map_err(|e| format!("{}: {e}", path.display()))?;
+	eprint!("{report}");
+	Ok(())
+}
+
+/// Merge the run into the `--coverage` listing and write the `--lcov` tracefile
+fn write_coverage(options: &Options, coverage: &Coverage, line_map: Option<&LineMap>) -> Result<(), String> {
+	if let Some(path) = &options.coverage {
+		let mut merged = match fs::read_to_string(path) {
+			Ok(src) => Coverage::from_listing(&src).map_err(|e| format!("{}: {e}", path.display()))?,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Coverage::new(),
+			Err(e) => return Err(format!("{}: {e}", path.display())),
+		};
+		merged.merge(coverage);
+
+		let mut listing = String::new();
+		merged.write_listing(&mut listing).map_err(|e| e.to_string())?;
+		fs::write(path, listing).map_err(|e| format!("{}: {e}", path.display()))?;
+	}
+
+	if let (Some(path), Some(line_map)) = (&options.lcov, line_map) {
+		let name = options.image.as_ref()
+			.and_then(|image| image.file_stem())
+			.map_or_else(|| String::from("bibe-emu"), |stem| stem.to_string_lossy().into_owned());
+
+		let mut lcov = String::new();
+		coverage.write_lcov(line_map, &name, &mut lcov).map_err(|e| e.to_string())?;
+		fs::write(path, lcov).map_err(|e| format!("{}: {e}", path.display()))?;
+	}
+
+	Ok(())
+}
+
+/// Run until the guest exits or a limit is reached, returns the exit code
+fn execute(options: &Options, state: &mut EmuState) -> Result<u8, String> {
+	let inspect = options.trace || options.swi == SwiPolicy::Exit;
+	let mut tracer = Tracer::new(state);
+	let mut line = String::new();
+	loop {
+		match state.stop_reason() {
+			Some(StopReason::Exit(status)) => return Ok(exit_code(*status)),
+			Some(StopReason::CycleBudget) => {
+				eprintln!("cycle limit reached at {}", state.describe(state.core().read_pc()));
+				return Ok(EXIT_LIMIT);
+			},
+			Some(StopReason::Lockup(lockup)) => {
+				eprintln!("lockup");
+				if let Some(original) = &lockup.original {
+					print_fault("original", original, state);
+				}
+				print_fault("double fault", &lockup.double_fault, state);
+				print_fault("fault", &lockup.fault, state);
+				return Ok(EXIT_LOCKUP);
+			},
+			None => (),
+		}
+
+		if options.max_instructions.map_or(false, |max| state.retired() >= max) {
+			eprintln!("instruction limit reached at {}", state.describe(state.core().read_pc()));
+			return Ok(EXIT_LIMIT);
+		}
+
+		if !inspect {
+			state.step();
+			continue;
+		}
+
+		let pc = state.core().read_pc();
+		let instr = state.read(pc, Width::Word).ok().and_then(Instruction::decode);
+
+		if options.swi == SwiPolicy::Exit && instr.as_ref().map_or(false, is_swi) {
+			return Ok(exit_code(state.core().read_reg(Register::o0()) as i32));
+		}
+
+		state.step();
+
+		if options.trace {
+			line.clear();
+			tracer.step(pc, instr.as_ref(), state, &mut line).map_err(|e| e.to_string())?;
+			eprintln!("{line}");
+		}
+	}
+}
+
+fn main() -> ExitCode {
+	let options = match parse_args(env::args().skip(1)) {
+		Ok(options) => options,
+		Err(e) if e.is_empty() => {
+			println!("{USAGE}");
+			return ExitCode::SUCCESS;
+		},
+		Err(e) => {
+			eprintln!("bibe-emu: {e}\n\n{USAGE}");
+			return ExitCode::from(EXIT_ERROR);
+		},
+	};
+
+	match run(options) {
+		Ok(code) => code,
+		Err(e) => {
+			eprintln!("bibe-emu: {e}");
+			ExitCode::from(EXIT_ERROR)
+		},
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[path = "../../tests/common/mod.rs"]
+	#[allow(dead_code)]
+	mod common;
+
+	fn parse(args: &[&str]) -> Result<Options, String> {
+		parse_args(args.iter().map(|arg| arg.to_string()))
+	}
+
+	#[test]
+	fn test_parse_args() {
+		let options = parse(&["prog.elf"]).unwrap();
+		assert_eq!(options.image, Some(PathBuf::from("prog.elf")));
+		assert_eq!(options.target, "bibe32");
+		assert_eq!(options.swi, SwiPolicy::Handle);
+		assert_eq!(options.engine, Engine::Interpreter);
+		assert!(options.memory.is_empty());
+
+		let options = parse(&[
+			"-t", "bibe32i",
+			"-f", "raw",
+			"-m", "0x1000:64K:2",
+			"--load-addr", "0x1000",
+			"-n", "100",
+			"--uart", "0xf000",
+			"--swi", "exit",
+			"--engine", "block",
+			"prog.bin",
+			"--", "a", "-b",
+		]).unwrap();
+		assert_eq!(options.target, "bibe32i");
+		assert_eq!(options.format, Some(ImageFormat::Raw));
+		assert_eq!(options.memory.len(), 1);
+		assert_eq!(options.memory[0].start, 0x1000);
+		assert_eq!(options.memory[0].size, 64 << 10);
+		assert_eq!(options.memory[0].wait_states, 2);
+		assert_eq!(options.load_addr, 0x1000);
+		assert_eq!(options.max_instructions, Some(100));
+		assert_eq!(options.uart, Some(0xf000));
+		assert_eq!(options.swi, SwiPolicy::Exit);
+		assert_eq!(options.engine, Engine::Block);
+		assert_eq!(options.args, ["a", "-b"]);
+	}
+
+	#[test]
+	fn test_parse_errors() {
+		let err = |args: &[&str]| parse(args).err();
+
+		// Help is reported as an empty error
+		assert_eq!(err(&["--help"]), Some(String::new()));
+		assert_eq!(err(&[]), Some(String::from("no image given")));
+		assert_eq!(err(&["prog.bin", "--target"]), Some(String::from("--target requires a value")));
+		assert_eq!(err(&["--swi", "ignore", "prog.bin"]), Some(String::from("invalid value for --swi: ignore")));
+		assert_eq!(err(&["-m", "0:1M:1:1", "prog.bin"]), Some(String::from("invalid value for -m: 0:1M:1:1")));
+		assert_eq!(err(&["--bogus", "prog.bin"]), Some(String::from("unknown option --bogus")));
+		assert_eq!(err(&["a.bin", "b.bin"]), Some(String::from("unexpected argument b.bin")));
+		assert_eq!(err(&["--lcov", "out.info", "prog.bin"]), Some(String::from("--lcov requires --line-map")));
+	}
+
+	#[test]
+	fn test_exit_code() {
+		assert_eq!(exit_code(0), 0);
+		assert_eq!(exit_code(255), 255);
+
+		// Out of range statuses must not wrap around to success
+		assert_eq!(exit_code(256), EXIT_FAILURE);
+		assert_eq!(exit_code(-1), EXIT_FAILURE);
+	}
+
+	#[test]
+	fn test_swi_exit() {
+		let options = parse(&["--swi", "exit", "prog.bin"]).unwrap();
+		let mut state = create_state(&options).unwrap();
+		common::write_program(&mut state, &common::assemble("\
+			mov %o0, 3
+			swi
+			mov %o0, 4
+		"));
+
+		assert_eq!(execute(&options, &mut state).unwrap(), 3);
+		assert_eq!(state.core().read_pc(), 4);
+	}
+}
@@ -0,0 +1,350 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Hexdump, raw dump and diff helpers for inspecting guest memory
+extern crate std;
+
+use core::{
+	cmp,
+	fmt,
+};
+use std::{
+	boxed::Box,
+	io,
+	vec,
+	vec::Vec,
+};
+
+use bibe_instr::Width;
+
+use super::{
+	Mapped,
+	Memory,
+	SimpleImage,
+};
+
+const LINE_SIZE: u64 = 16;
+
+/// Largest number of bytes compared at once
+const CHUNK_SIZE: u64 = 4096;
+
+/// Range of bytes that differ between two memories
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DiffSpan {
+	pub start: u32,
+	pub len: u32,
+}
+
+/// Populated spans of `memory` clipped to `[start, end)`
+fn populated<M: Memory + ?Sized>(memory: &M, start: u64, end: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+	let mut cursor = start;
+
+	core::iter::from_fn(move || {
+		if cursor >= end || cursor > u32::MAX as u64 {
+			return None;
+		}
+
+		let (span_start, span_end) = memory.next_populated(cursor as u32)?;
+		let span_start = cmp::max(span_start as u64, cursor);
+		let span_end = cmp::min(span_end, end);
+		if span_start >= span_end {
+			return None;
+		}
+
+		cursor = span_end;
+		Some((span_start, span_end))
+	})
+}
+
+/// Write a hexdump of `len` bytes starting at `start`, unpopulated ranges are skipped
+pub fn hexdump<M: Memory + ?Sized>(memory: &M, start: u32, len: u32, out: &mut dyn fmt::Write) -> fmt::Result {
+	let end = start as u64 + len as u64;
+
+	for (span_start, span_end) in populated(memory, start as u64, end) {
+		let mut line = span_start - span_start % LINE_SIZE;
+
+		while line < span_end {
+			let mut bytes = [None; LINE_SIZE as usize];
+			for i in 0..LINE_SIZE {
+				let addr = line + i;
+				if addr >= span_start && addr < span_end {
+					bytes[i as usize] = Some(memory.read(addr as u32, Width::Byte).map(|b| b as u8).ok());
+				}
+			}
+
+			write_line(line as u32, &bytes, out)?;
+			line += LINE_SIZE;
+		}
+	}
+
+	Ok(())
+}
+
+/// Write a single hexdump line, `None` is outside the dumped range and `Some(None)` faulted
+fn write_line(addr: u32, bytes: &[Option<Option<u8>>], out: &mut dyn fmt::Write) -> fmt::Result {
+	write!(out, "{addr:08x} ")?;
+	for (i, byte) in bytes.iter().enumerate() {
+		if i % 8 == 0 {
+			out.write_char(' ')?;
+		}
+
+		match byte {
+			Some(Some(b)) => write!(out, "{b:02x} ")?,
+			Some(None) => out.write_str("?? ")?,
+			None => out.write_str("   ")?,
+		}
+	}
+
+	out.write_str(" |")?;
+	for byte in bytes {
+		let c = match byte {
+			Some(Some(b)) if b.is_ascii_graphic() || *b == b' ' => *b as char,
+			Some(_) => '.',
+			None => ' ',
+		};
+		out.write_char(c)?;
+	}
+	out.write_str("|\n")
+}
+
+/// Write `len` bytes starting at `start` as raw binary, unpopulated and faulting bytes are written as zero
+pub fn dump_raw<M: Memory + ?Sized>(memory: &M, start: u32, len: u32, out: &mut dyn io::Write) -> io::Result<()> {
+	let end = start as u64 + len as u64;
+	let mut cursor = start as u64;
+	let mut buf = vec![0u8; CHUNK_SIZE as usize];
+
+	for (span_start, span_end) in populated(memory, start as u64, end) {
+		write_zeros(span_start - cursor, out)?;
+
+		let mut addr = span_start;
+		while addr < span_end {
+			let chunk = &mut buf[..cmp::min(span_end - addr, CHUNK_SIZE) as usize];
+			read_chunk(memory, addr as u32, chunk);
+			out.write_all(chunk)?;
+			addr += chunk.len() as u64;
+		}
+
+		cursor = span_end;
+	}
+
+	write_zeros(end - cursor, out)
+}
+
+fn write_zeros(mut len: u64, out: &mut dyn io::Write) -> io::Result<()> {
+	let zeros = [0u8; CHUNK_SIZE as usize];
+
+	while len > 0 {
+		let chunk = cmp::min(len, CHUNK_SIZE);
+		out.write_all(&zeros[..chunk as usize])?;
+		len -= chunk;
+	}
+
+	Ok(())
+}
+
+/// Fill `buf` from `memory`, falling back to byte accesses if the bulk read faults
+fn read_chunk<M: Memory + ?Sized>(memory: &M, addr: u32, buf: &mut [u8]) -> Vec<bool> {
+	if memory.read_bytes(addr, buf).is_ok() {
+		return vec![true; buf.len()];
+	}
+
+	buf.iter_mut().enumerate().map(|(i, byte)| {
+		match memory.read(addr + i as u32, Width::Byte) {
+			Ok(b) => {
+				*byte = b as u8;
+				true
+			},
+			Err(_) => {
+				*byte = 0;
+				false
+			},
+		}
+	}).collect()
+}
+
+/// Contents of `[addr, addr + len)`, `populated` is whether the range is populated in `memory`
+///
+/// Unpopulated bytes read as zero if the memory covers them, e.g. unallocated `Image` pages,
+/// and are absent otherwise
+fn fetch<M: Memory + ?Sized>(memory: &M, populated: bool, addr: u32, len: usize) -> Vec<Option<u8>> {
+	if !populated {
+		let fill = if memory.contains(addr) { Some(0) } else { None };
+		return vec![fill; len];
+	}
+
+	let mut buf = vec![0u8; len];
+	let present = read_chunk(memory, addr, &mut buf);
+	buf.into_iter().zip(present).map(|(b, present)| present.then_some(b)).collect()
+}
+
+/// Compare `len` bytes starting at `start`, returns the spans that differ
+///
+/// Only ranges populated in at least one of the memories are read, so sparse memories
+/// are compared in time proportional to their populated size
+pub fn diff<A, B>(expected: &A, actual: &B, start: u32, len: u32) -> Vec<DiffSpan>
+where
+	A: Memory + ?Sized,
+	B: Memory + ?Sized,
+{
+	let end = start as u64 + len as u64;
+	let mut spans: Vec<DiffSpan> = Vec::new();
+	let mut cursor = start as u64;
+
+	while cursor < end {
+		let a = populated(expected, cursor, end).next();
+		let b = populated(actual, cursor, end).next();
+
+		// Start of the next populated segment in either memory
+		let seg_start = match (a, b) {
+			(None, None) => break,
+			(Some((s, _)), None) | (None, Some((s, _))) => s,
+			(Some((sa, _)), Some((sb, _))) => cmp::min(sa, sb),
+		};
+
+		// Cut the segment at the next boundary so each memory is either populated or not throughout
+		let mut seg_end = cmp::min(end, seg_start + CHUNK_SIZE);
+		for span in [a, b].into_iter().flatten() {
+			let boundary = if span.0 > seg_start { span.0 } else { span.1 };
+			seg_end = cmp::min(seg_end, boundary);
+		}
+
+		let in_a = a.map_or(false, |(s, _)| s <= seg_start);
+		let in_b = b.map_or(false, |(s, _)| s <= seg_start);
+		let seg_len = (seg_end - seg_start) as usize;
+
+		let lhs = fetch(expected, in_a, seg_start as u32, seg_len);
+		let rhs = fetch(actual, in_b, seg_start as u32, seg_len);
+
+		for (i, (l, r)) in lhs.iter().zip(rhs.iter()).enumerate() {
+			if l == r {
+				continue;
+			}
+
+			let addr = seg_start as u32 + i as u32;
+			match spans.last_mut() {
+				Some(span) if span.start as u64 + span.len as u64 == addr as u64 => span.len += 1,
+				_ => spans.push(DiffSpan { start: addr, len: 1 }),
+			}
+		}
+
+		cursor = seg_end;
+	}
+
+	spans
+}
+
+/// Compare `memory` against the raw binary read from `r`, placed at `start`
+pub fn diff_file<M: Memory + ?Sized>(r: &mut dyn io::Read, memory: &M, start: u32) -> io::Result<Vec<DiffSpan>> {
+	let image = SimpleImage::load(r)?;
+	let len = image.size();
+
+	let mut expected = Mapped::new();
+	if expected.map(start, Box::new(image)).is_none() {
+		return Err(io::Error::new(io::ErrorKind::InvalidInput, "image doesn't fit in the address space"));
+	}
+
+	Ok(diff(&expected, memory, start, len))
+}
+
+/// Write a hexdump of both memories for every span in `spans`
+pub fn write_diff<A, B>(expected: &A, actual: &B, spans: &[DiffSpan], out: &mut dyn fmt::Write) -> fmt::Result
+where
+	A: Memory + ?Sized,
+	B: Memory + ?Sized,
+{
+	for span in spans {
+		writeln!(out, "{:08x}..{:08x} differs", span.start, span.start as u64 + span.len as u64)?;
+		out.write_str("expected:\n")?;
+		hexdump(expected, span.start, span.len, out)?;
+		out.write_str("actual:\n")?;
+		hexdump(actual, span.start, span.len, out)?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	extern crate std;
+
+	use super::*;
+	use std::string::String;
+	use crate::memory::{
+		Image,
+		PageSize,
+	};
+
+	#[test]
+	fn test_hexdump() {
+		let mut mapped = Mapped::new();
+		assert!(mapped.map(0x10, Box::new(SimpleImage::new(4))).is_some());
+		assert!(mapped.write_bytes(0x10, b"abc\n").is_ok());
+
+		let mut out = String::new();
+		assert!(hexdump(&mapped, 0, 0x100, &mut out).is_ok());
+		assert_eq!(out, "00000010  61 62 63 0a                                       |abc.            |\n");
+	}
+
+	#[test]
+	fn test_dump_raw() {
+		let mut mapped = Mapped::new();
+		assert!(mapped.map(2, Box::new(SimpleImage::new(2))).is_some());
+		assert!(mapped.write_bytes(2, &[1, 2]).is_ok());
+
+		let mut out = Vec::new();
+		assert!(dump_raw(&mapped, 0, 6, &mut out).is_ok());
+		assert_eq!(out, [0, 0, 1, 2, 0, 0]);
+	}
+
+	#[test]
+	fn test_diff() {
+		let mut expected = Image::new(PageSize::K4);
+		let mut actual = Image::new(PageSize::K4);
+
+		assert!(expected.write_bytes(0x8000_0000, &[1, 2, 3, 4]).is_ok());
+		assert!(actual.write_bytes(0x8000_0000, &[1, 0, 0, 4]).is_ok());
+		assert!(actual.write(0x9000_0000, Width::Byte, 0xff).is_ok());
+
+		// Unallocated pages read as zero, so a zero write doesn't count as a difference
+		assert!(actual.write(0xa000_0000, Width::Byte, 0).is_ok());
+
+		let spans = diff(&expected, &actual, 0, u32::MAX);
+		assert_eq!(spans, [
+			DiffSpan { start: 0x8000_0001, len: 2 },
+			DiffSpan { start: 0x9000_0000, len: 1 },
+		]);
+	}
+
+	#[test]
+	fn test_last_page() {
+		let mut expected = Image::new(PageSize::K4);
+		let mut actual = Image::new(PageSize::K4);
+
+		assert!(expected.write_bytes(0xffff_fffc, b"end!").is_ok());
+		assert!(actual.write_bytes(0xffff_fffc, b"end?").is_ok());
+		assert_eq!(actual.next_populated(0xffff_f000), Some((0xffff_f000, 1 << 32)));
+
+		let mut out = String::new();
+		assert!(hexdump(&actual, 0xffff_fff0, 0x10, &mut out).is_ok());
+		assert_eq!(out, "fffffff0  00 00 00 00 00 00 00 00  00 00 00 00 65 6e 64 3f  |............end?|\n");
+
+		let mut raw = Vec::new();
+		assert!(dump_raw(&actual, 0xffff_fffc, 4, &mut raw).is_ok());
+		assert_eq!(raw, b"end?");
+
+		let spans = diff(&expected, &actual, 0xffff_f000, 0x1000);
+		assert_eq!(spans, [DiffSpan { start: 0xffff_ffff, len: 1 }]);
+	}
+
+	#[test]
+	fn test_diff_file() {
+		let mut actual = Mapped::new();
+		assert!(actual.map(0x100, Box::new(SimpleImage::new(8))).is_some());
+		assert!(actual.write_bytes(0x100, &[1, 2, 3, 4]).is_ok());
+
+		let mut file: &[u8] = &[1, 2, 3, 5, 0, 0, 0, 0, 9];
+		let spans = diff_file(&mut file, &actual, 0x100);
+		assert_eq!(spans.ok(), Some(std::vec![
+			DiffSpan { start: 0x103, len: 1 },
+			DiffSpan { start: 0x108, len: 1 },
+		]));
+	}
+}
@@ -7,7 +7,10 @@ use std::{
 	cell::RefCell,
 };
 
+use bibe_instr::Width;
+
 use super::{
+	width_bytes,
 	Mapped,
 	Memory,
 	SimpleImage,
@@ -41,6 +44,8 @@ impl Into<u32> for PageSize {
 }
 
 /// Efficient memory device, memory is not allocated until needed
+///
+/// Covers the entire address space, pages are allocated on first access
 pub struct Image {
 	mapped: RefCell<Mapped>,
 	page_size: PageSize,
@@ -54,18 +59,44 @@ impl Image {
 		}
 	}
 
-	fn create_page(&self, addr: u32) -> Option<()> {
-		let mapped = &mut self.mapped.borrow_mut();
-		let start_addr = addr;
+	fn page_size(&self) -> u32 {
+		self.page_size.into()
+	}
+
+	fn page_start(&self, addr: u32) -> u32 {
+		addr & !(self.page_size() - 1)
+	}
+
+	/// Allocate the page containing `addr` if it doesn't exist yet
+	fn create_page(&self, addr: u32) {
+		let start = self.page_start(addr);
+		let mut mapped = self.mapped.borrow_mut();
+
+		if mapped.contains(start) {
+			return;
+		}
+
+		let image = Box::new(SimpleImage::new(self.page_size())) as Box<dyn Memory>;
+		// Pages are aligned so this can't overlap an existing page
+		let _ = mapped.map(start, image);
+	}
 
-		if self.contains(addr) {
-			return None;
+	/// Allocate every page touched by an access of `len` bytes starting at `addr`
+	fn create_pages(&self, addr: u32, len: usize) {
+		if len == 0 {
+			return;
 		}
 
-		let image = Box::new(SimpleImage::new(self.page_size.into())) as Box<dyn Memory>;
-		mapped.map(start_addr, image)?;
+		let last = addr.saturating_add((len - 1) as u32);
+		let mut page = self.page_start(addr);
+		loop {
+			self.create_page(page);
 
-		Some(())
+			match page.checked_add(self.page_size()) {
+				Some(next) if next <= last => page = next,
+				_ => break,
+			}
+		}
 	}
 }
 
@@ -74,19 +105,79 @@ impl Memory for Image {
 		self.mapped.borrow().size()
 	}
 
-	fn read(&self, addr: u32, width: bibe_instr::Width) -> crate::Result<u32> {
-		if !self.mapped.borrow().contains(addr) {
-			self.create_page(addr);
-		}
+	fn contains(&self, _addr: u32) -> bool {
+		true
+	}
+
+	fn validate_access(&self, addr: u32, width: Width) -> bool {
+		addr.checked_add(width_bytes(width) - 1).is_some()
+	}
+
+	fn next_populated(&self, addr: u32) -> Option<(u32, u64)> {
+		self.mapped.borrow().next_populated(addr)
+	}
 
+	fn read_validated(&self, addr: u32, width: Width) -> crate::Result<u32> {
+		self.create_pages(addr, width_bytes(width) as usize);
 		self.mapped.borrow().read(addr, width)
 	}
 
-	fn write(&mut self, addr: u32, width: bibe_instr::Width, value: u32) -> crate::Result<()> {
-		if !self.mapped.borrow().contains(addr) {
-			self.create_page(addr);
-		}
+	fn write_validated(&mut self, addr: u32, width: Width, value: u32) -> crate::Result<()> {
+		self.create_pages(addr, width_bytes(width) as usize);
+		self.mapped.get_mut().write(addr, width, value)
+	}
+
+	fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> crate::Result<()> {
+		self.create_pages(addr, buf.len());
+		self.mapped.borrow().read_bytes(addr, buf)
+	}
 
-		self.mapped.borrow_mut().write(addr, width, value)
+	fn write_bytes(&mut self, addr: u32, data: &[u8]) -> crate::Result<()> {
+		self.create_pages(addr, data.len());
+		self.mapped.get_mut().write_bytes(addr, data)
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_pages() {
+		let mut image = Image::new(PageSize::K4);
+
+		// Sparse accesses only allocate the pages they touch
+		assert!(image.write(0x1000_0004, Width::Word, 0x12345678).is_ok());
+		assert_eq!(image.read(0x1000_0004, Width::Word).ok(), Some(0x12345678));
+		assert_eq!(image.read(0x2000_0000, Width::Word).ok(), Some(0));
+
+		// Bulk accesses spanning a page boundary
+		let data = [0xaa; 16];
+		assert!(image.write_bytes(0x0fff_fff8, &data).is_ok());
+
+		let mut buf = [0u8; 16];
+		assert!(image.read_bytes(0x0fff_fff8, &mut buf).is_ok());
+		assert_eq!(buf, data);
+		assert_eq!(image.read(0x1000_0004, Width::Word).ok(), Some(0xaaaaaaaa));
+	}
+
+	#[test]
+	fn test_last_page() {
+		let mut image = Image::new(PageSize::K4);
+
+		// The last page ends at the top of the address space
+		assert!(image.write(0xffff_fffc, Width::Word, 0x12345678).is_ok());
+		assert_eq!(image.read(0xffff_fffc, Width::Word).ok(), Some(0x12345678));
+
+		let data = [0x55; 32];
+		assert!(image.write_bytes(0xffff_ffe0, &data).is_ok());
+
+		let mut buf = [0u8; 32];
+		assert!(image.read_bytes(0xffff_ffe0, &mut buf).is_ok());
+		assert_eq!(buf, data);
+		assert_eq!(image.read(0xffff_ffff, Width::Byte).ok(), Some(0x55));
+
+		// Accesses can't wrap around to address 0
+		assert!(image.read_bytes(0xffff_fff0, &mut buf).is_err());
+	}
+}
@@ -1,16 +1,21 @@
 /* Copyright 2023 Robert Zieba, see LICENSE file for full license. */
 #![cfg(feature = "std")]
 extern crate std;
+use core::cmp;
 use std::vec::Vec;
 use std::boxed::Box;
 use bibe_instr::Width;
 
 use super::Memory;
-use crate::Result;
+use crate::{
+	Interrupt,
+	Result,
+};
 
 struct MappedRegion {
 	start: u32,
 	memory: Box<dyn Memory>,
+	wait_states: u32,
 }
 
 pub struct Mapped {
@@ -19,12 +24,17 @@ pub struct Mapped {
 
 impl MappedRegion {
 	fn overlaps(&self, other: &MappedRegion) -> bool {
-		(self.start >= other.start && self.start < other.end())
-		|| (other.start >= self.start && other.start < self.end())
+		(self.start >= other.start && (self.start as u64) < other.end())
+		|| (other.start >= self.start && (other.start as u64) < self.end())
 	}
 
-	pub fn end(&self) -> u32 {
-		self.start + self.size()
+	/// Exclusive end address, a region can end at the top of the address space
+	pub fn end(&self) -> u64 {
+		self.start as u64 + self.size() as u64
+	}
+
+	fn contains_addr(&self, addr: u32) -> bool {
+		addr >= self.start && (addr as u64) < self.end()
 	}
 }
 
@@ -33,6 +43,10 @@ impl Memory for MappedRegion {
 		self.memory.size()
 	}
 
+	fn validate_access(&self, addr: u32, width: Width) -> bool {
+		self.memory.validate_access(addr, width)
+	}
+
 	fn read_validated(&self, addr: u32, width: Width) -> Result<u32> {
 		self.memory.read_validated(addr, width)
 	}
@@ -52,7 +66,7 @@ impl Mapped {
 	fn find_region(&self, addr: u32) -> Option<&MappedRegion> {
 		//TODO: make this work in constant time
 		for region in &self.regions {
-			if addr >= region.start && addr < region.end() {
+			if region.contains_addr(addr) {
 				return Some(region);
 			}
 		}
@@ -63,7 +77,7 @@ impl Mapped {
 	fn find_region_mut(&mut self, addr: u32) -> Option<&mut MappedRegion> {
 		//TODO: make this work in constant time
 		for region in &mut self.regions {
-			if addr >= region.start && addr < region.end() {
+			if region.contains_addr(addr) {
 				return Some(region);
 			}
 		}
@@ -71,44 +85,39 @@ impl Mapped {
 		None
 	}
 
+	/// Start address and size of every region, in address order
+	pub fn regions(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+		self.regions.iter().map(|region| (region.start, region.size()))
+	}
+
 	pub fn is_mapped(&self, addr: u32) -> bool {
 		self.find_region(addr).is_some()
 	}
 
 	/// Attempt to map `memory` at the given start address
+	///
+	/// Fails if the region overlaps another one or doesn't fit in the address space
 	pub fn map(&mut self, start: u32, memory: Box<dyn Memory>) -> Option<()> {
+		self.map_with_wait_states(start, memory, 0)
+	}
+
+	/// Attempt to map `memory`, accesses to the region take `wait_states` extra cycles
+	pub fn map_with_wait_states(&mut self, start: u32, memory: Box<dyn Memory>, wait_states: u32) -> Option<()> {
 		let new = MappedRegion {
 			start,
-			memory
+			memory,
+			wait_states,
 		};
 
-		// Find the index where this should be inserted
-		let mut index = None;
-		for (i, peripheral) in self.regions.iter().enumerate() {
-			if new.overlaps(peripheral) {
-				return None;
-			}
-
-			if start > peripheral.end() {
-				if i + 1 < self.regions.len() {
-					if new.overlaps(&self.regions[i + 1]) {
-						// Requested address range overlap with the next peripheral
-						return None;
-					}
-				}
-
-				index = Some(i);
-				break;
-			}
+		if new.end() > 1 << 32 || self.regions.iter().any(|region| new.overlaps(region)) {
+			return None;
 		}
 
-		if let Some(index) = index {
-			self.regions.insert(index, new);
-		}
-		else {
-			// Didn't find an index, must have an empty vec
-			self.regions.push(new);
-		}
+		// Keep regions sorted by start address
+		let index = self.regions.iter()
+			.position(|region| region.start > start)
+			.unwrap_or(self.regions.len());
+		self.regions.insert(index, new);
 
 		Some(())
 	}
@@ -125,31 +134,92 @@ impl Memory for Mapped {
 			return false;
 		}
 
-		region.unwrap().validate_access(addr, width)
+		let region = region.unwrap();
+		region.validate_access(addr - region.start, width)
 	}
 
 	fn size(&self) -> u32 {
 		if self.regions.len() == 0 {
 			0
 		} else {
+			// Saturates for a region that ends at the top of the address space
 			let last = self.regions.last().unwrap();
-			last.start + last.size()
+			cmp::min(last.end(), u32::MAX as u64) as u32
 		}
 	}
 
+	// Regions are addressed relative to their start address
 	fn read_validated(&self, addr: u32, width: Width) -> Result<u32> {
-		self.find_region(addr).unwrap().memory.read_validated(addr, width)
+		let region = self.find_region(addr).unwrap();
+		region.memory.read_validated(addr - region.start, width)
 	}
 
 	fn write_validated(&mut self, addr: u32, width: Width, value: u32) -> Result<()> {
-		self.find_region_mut(addr).unwrap().memory.write_validated(addr, width, value)
+		let region = self.find_region_mut(addr).unwrap();
+		region.memory.write_validated(addr - region.start, width, value)
+	}
+
+	fn wait_states(&self, addr: u32, width: Width) -> u32 {
+		match self.find_region(addr) {
+			Some(region) => region.wait_states + region.memory.wait_states(addr - region.start, width),
+			None => 0,
+		}
+	}
+
+	fn next_populated(&self, addr: u32) -> Option<(u32, u64)> {
+		for region in &self.regions {
+			if region.end() <= addr as u64 {
+				continue;
+			}
+
+			let offset = addr.saturating_sub(region.start);
+			if let Some((start, end)) = region.memory.next_populated(offset) {
+				return Some((region.start + start, region.start as u64 + end));
+			}
+		}
+
+		None
+	}
+
+	fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> Result<()> {
+		let mut offset = 0;
+
+		// Split the access at region boundaries
+		while offset < buf.len() {
+			let current = addr.wrapping_add(offset as u32);
+			let region = self.find_region(current).ok_or(Interrupt::mem_fault(current))?;
+			let len = cmp::min((buf.len() - offset) as u64, region.end() - current as u64) as usize;
+
+			region.memory.read_bytes(current - region.start, &mut buf[offset..offset + len])?;
+			offset += len;
+		}
+
+		Ok(())
+	}
+
+	fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+		let mut offset = 0;
+
+		while offset < data.len() {
+			let current = addr.wrapping_add(offset as u32);
+			let region = self.find_region_mut(current).ok_or(Interrupt::mem_fault(current))?;
+			let len = cmp::min((data.len() - offset) as u64, region.end() - current as u64) as usize;
+
+			region.memory.write_bytes(current - region.start, &data[offset..offset + len])?;
+			offset += len;
+		}
+
+		Ok(())
 	}
 }
 
 #[cfg(test)]
 mod test {
 	use super::*;
-	use crate::memory::Mock;
+	use crate::memory::{
+		Mock,
+		SimpleImage,
+	};
 
 	fn mock_memory(size: u32) -> Box<dyn Memory> {
 		Box::new(Mock::new(size))
@@ -159,6 +229,7 @@ mod test {
 		MappedRegion {
 			start,
 			memory: mock_memory(size),
+			wait_states: 0,
 		}
 	}
 
@@ -239,4 +310,62 @@ mod test {
 		// Sub region
 		assert!(mapped.map(0, mock_memory(16)).is_none());
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn test_bytes() {
+		let mut mapped = Mapped::new();
+
+		assert!(mapped.map(0, Box::new(SimpleImage::new(8))).is_some());
+		assert!(mapped.map(8, Box::new(SimpleImage::new(8))).is_some());
+		assert!(mapped.map(32, Box::new(SimpleImage::new(8))).is_some());
+
+		// Access spanning two contiguous regions
+		let data = [1, 2, 3, 4, 5, 6, 7, 8];
+		assert!(mapped.write_bytes(4, &data).is_ok());
+		assert_eq!(mapped.read(8, Width::Word).ok(), Some(0x08070605));
+
+		let mut buf = [0u8; 8];
+		assert!(mapped.read_bytes(4, &mut buf).is_ok());
+		assert_eq!(buf, data);
+
+		// Regions are addressed relative to their start
+		assert!(mapped.write(32, Width::Word, 0xdeadbeef).is_ok());
+		assert_eq!(mapped.read(32, Width::Word).ok(), Some(0xdeadbeef));
+
+		// Accesses running into a hole fault
+		assert!(mapped.read_bytes(12, &mut buf).is_err());
+		assert!(mapped.write_bytes(36, &data).is_err());
+	}
+
+	#[test]
+	fn test_top_of_address_space() {
+		let mut mapped = Mapped::new();
+		assert!(mapped.map(0xffff_fff0, Box::new(SimpleImage::new(0x10))).is_some());
+		assert!(mapped.map(0xffff_ffe0, Box::new(SimpleImage::new(0x10))).is_some());
+		assert!(mapped.map(0xffff_fff8, mock_memory(4)).is_none());
+		assert_eq!(mapped.size(), u32::MAX);
+
+		// Bulk accesses up to the last byte, crossing into the last region
+		let data = [0x5a; 0x18];
+		assert!(mapped.write_bytes(0xffff_ffe8, &data).is_ok());
+
+		let mut buf = [0u8; 0x18];
+		assert!(mapped.read_bytes(0xffff_ffe8, &mut buf).is_ok());
+		assert_eq!(buf, data);
+		assert_eq!(mapped.read(0xffff_fffc, Width::Word).ok(), Some(0x5a5a5a5a));
+		assert_eq!(mapped.read(0xffff_ffff, Width::Byte).ok(), Some(0x5a));
+	}
+
+	#[test]
+	fn test_past_address_space() {
+		let mut mapped = Mapped::new();
+
+		// Regions can't extend past the top of the address space
+		assert!(mapped.map(0xffff_fff0, mock_memory(0x20)).is_none());
+		assert!(mapped.map(0xffff_ffff, mock_memory(2)).is_none());
+		assert_eq!(mapped.regions().count(), 0);
+
+		// But can end exactly at it
+		assert!(mapped.map(0xffff_ffff, mock_memory(1)).is_some());
+	}
+}
@@ -6,22 +6,46 @@ use crate::{
 
 use bibe_instr::Width;
 
+#[cfg(feature = "std")]
+pub mod dump;
 #[cfg(feature = "std")]
 mod image;
 #[cfg(feature = "std")]
 mod mapped;
 #[cfg(feature = "std")]
+mod overlay;
+mod rom;
+#[cfg(feature = "std")]
 mod simple_image;
+#[cfg(feature = "std")]
+mod uart;
 mod mock;
 
 #[cfg(feature = "std")]
-pub use image::Image;
+pub use image::{
+	Image,
+	PageSize,
+};
 #[cfg(feature = "std")]
 pub use mapped::Mapped;
 #[cfg(feature = "std")]
+pub use overlay::Overlay;
+pub use rom::Rom;
+#[cfg(feature = "std")]
 pub use simple_image::SimpleImage;
+#[cfg(feature = "std")]
+pub use uart::*;
 pub use mock::Mock;
 
+/// Number of bytes covered by an access of the given width
+pub(crate) fn width_bytes(width: Width) -> u32 {
+	match width {
+		Width::Byte => 1,
+		Width::Short => 2,
+		Width::Word => 4,
+	}
+}
+
 pub struct RegionSlice<'a> {
 	parent: &'a mut dyn Memory,
 	_start: u32,
@@ -78,6 +102,63 @@ pub trait Memory {
 	fn write_validated(&mut self, addr: u32, _width: Width, _value: u32) -> Result<()> {
 		Err(Interrupt::mem_fault(addr))
 	}
+
+	/// Extra cycles taken by an access
+	fn wait_states(&self, _addr: u32, _width: Width) -> u32 {
+		0
+	}
+
+	/// Returns the first populated span at or after `addr` as `(start, end)`
+	///
+	/// `end` is exclusive, so a span reaching the top of the address space ends at 2^32.
+	/// Sparse memories override this so tools can skip unmapped or unallocated ranges
+	fn next_populated(&self, addr: u32) -> Option<(u32, u64)> {
+		if addr < self.size() {
+			Some((addr, self.size() as u64))
+		} else {
+			None
+		}
+	}
+
+	/// Fill `buf` with the bytes starting at `addr`
+	fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> Result<()> {
+		for (i, byte) in buf.iter_mut().enumerate() {
+			*byte = self.read(addr.wrapping_add(i as u32), Width::Byte)? as u8;
+		}
+
+		Ok(())
+	}
+
+	/// Write all of `data` starting at `addr`
+	fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+		for (i, byte) in data.iter().enumerate() {
+			self.write(addr.wrapping_add(i as u32), Width::Byte, *byte as u32)?;
+		}
+
+		Ok(())
+	}
+
+	/// Read a NUL-terminated string into `buf`, returns the length without the terminator
+	///
+	/// Fails if no terminator is found within `buf.len()` bytes
+	fn read_cstr(&self, addr: u32, buf: &mut [u8]) -> Result<usize> {
+		for i in 0..buf.len() {
+			let byte = self.read(addr.wrapping_add(i as u32), Width::Byte)? as u8;
+			if byte == 0 {
+				return Ok(i);
+			}
+
+			buf[i] = byte;
+		}
+
+		Err(Interrupt::mem_fault(addr.wrapping_add(buf.len() as u32)))
+	}
+
+	/// Write `s` followed by a NUL terminator
+	fn write_cstr(&mut self, addr: u32, s: &[u8]) -> Result<()> {
+		self.write_bytes(addr, s)?;
+		self.write(addr.wrapping_add(s.len() as u32), Width::Byte, 0)
+	}
 }
 
 impl Memory for RegionSlice<'_> {
@@ -123,4 +204,22 @@ mod test {
 		r.resize(4);
 		assert!(r.validate_access(0, Width::Word));
 	}
+
+	#[test]
+	fn test_cstr() {
+		let mut r = Mock::new(32);
+
+		// Mock always reads back the last value written
+		r.value = b'a' as u32;
+		let mut buf = [0u8; 4];
+		assert!(r.read_cstr(0, &mut buf).is_err());
+		assert_eq!(&buf, b"aaaa");
+
+		r.value = 0;
+		assert_eq!(r.read_cstr(0, &mut buf).ok(), Some(0));
+
+		assert!(r.write_cstr(0, b"abc").is_ok());
+		assert_eq!(r.last_addr(), 3);
+		assert_eq!(r.value, 0);
+	}
 }
\ No newline at end of file
@@ -0,0 +1,326 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+#![cfg(feature = "std")]
+extern crate std;
+
+use core::cmp;
+use std::{
+	boxed::Box,
+	collections::BTreeMap,
+	rc::Rc,
+	vec,
+	vec::Vec,
+};
+
+use bibe_instr::Width;
+
+use super::{
+	width_bytes,
+	Memory,
+	PageSize,
+};
+use crate::{
+	Interrupt,
+	Result,
+};
+
+/// Copy-on-write view of a shared base memory
+///
+/// Writes go to private copies of the touched pages, everything else is read from the base.
+/// Any number of overlays can share one base, e.g. to run many inputs from one boot snapshot.
+pub struct Overlay<B: Memory> {
+	base: Rc<B>,
+	// BTreeMap so that clearing is proportional to the number of dirty pages
+	pages: BTreeMap<u32, Box<[u8]>>,
+	page_size: u32,
+}
+
+impl<B: Memory> Overlay<B> {
+	pub fn new(base: Rc<B>, page_size: PageSize) -> Self {
+		Self {
+			base,
+			pages: BTreeMap::new(),
+			page_size: page_size.into(),
+		}
+	}
+
+	/// Create a new overlay on the same base, dirty pages are not shared
+	pub fn fork(&self) -> Self {
+		Self {
+			base: self.base.clone(),
+			pages: BTreeMap::new(),
+			page_size: self.page_size,
+		}
+	}
+
+	pub fn base(&self) -> &Rc<B> {
+		&self.base
+	}
+
+	/// Discard all writes, restoring the contents of the base
+	pub fn reset(&mut self) {
+		self.pages.clear();
+	}
+
+	pub fn dirty_pages(&self) -> usize {
+		self.pages.len()
+	}
+
+	fn page_start(&self, addr: u32) -> u32 {
+		addr & !(self.page_size - 1)
+	}
+
+	/// Calls `f` with the address, page start, page offset and length of each page sized chunk of an access
+	fn for_each_chunk<F>(&self, addr: u32, len: usize, mut f: F) -> Result<()>
+	where
+		F: FnMut(u32, u32, usize, usize) -> Result<()>,
+	{
+		let mut offset = 0;
+
+		while offset < len {
+			let current = addr.wrapping_add(offset as u32);
+			let start = self.page_start(current);
+			let page_offset = (current - start) as usize;
+			let chunk = cmp::min(len - offset, self.page_size as usize - page_offset);
+
+			f(current, start, page_offset, chunk)?;
+			offset += chunk;
+		}
+
+		Ok(())
+	}
+
+	fn is_clean(&self, addr: u32, len: usize) -> bool {
+		let first = self.page_start(addr);
+		let last = self.page_start(addr.wrapping_add(len as u32 - 1));
+		!self.pages.contains_key(&first) && !self.pages.contains_key(&last)
+	}
+
+	/// Dirty pages don't know which of their bytes exist in the base, so check with the base
+	///
+	/// Only the first and last byte are checked so bulk accesses don't cost a call per byte,
+	/// the base is expected to be contiguous within a single access.
+	fn check_range(&self, addr: u32, len: usize) -> Result<()> {
+		if len == 0 {
+			return Ok(());
+		}
+
+		let last = addr as u64 + len as u64 - 1;
+		if last > u32::MAX as u64 {
+			return Err(Interrupt::mem_fault(addr));
+		}
+
+		for current in [addr, last as u32] {
+			if !self.base.contains(current) {
+				return Err(Interrupt::mem_fault(current));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Private copy of the page starting at `start`, bytes the base can't provide are zero
+	fn copy_page(&self, start: u32) -> Box<[u8]> {
+		let mut page = vec![0u8; self.page_size as usize].into_boxed_slice();
+
+		if self.base.read_bytes(start, &mut page).is_err() {
+			for (i, byte) in page.iter_mut().enumerate() {
+				if let Ok(value) = self.base.read(start.wrapping_add(i as u32), Width::Byte) {
+					*byte = value as u8;
+				}
+			}
+		}
+
+		page
+	}
+
+	fn page_mut(&mut self, start: u32) -> &mut [u8] {
+		if !self.pages.contains_key(&start) {
+			let page = self.copy_page(start);
+			self.pages.insert(start, page);
+		}
+
+		self.pages.get_mut(&start).unwrap()
+	}
+}
+
+impl<B: Memory> Memory for Overlay<B> {
+	fn size(&self) -> u32 {
+		self.base.size()
+	}
+
+	fn contains(&self, addr: u32) -> bool {
+		self.base.contains(addr)
+	}
+
+	fn validate_access(&self, addr: u32, width: Width) -> bool {
+		self.base.validate_access(addr, width)
+	}
+
+	fn wait_states(&self, addr: u32, width: Width) -> u32 {
+		self.base.wait_states(addr, width)
+	}
+
+	fn next_populated(&self, addr: u32) -> Option<(u32, u64)> {
+		self.base.next_populated(addr)
+	}
+
+	fn read_validated(&self, addr: u32, width: Width) -> Result<u32> {
+		let len = width_bytes(width) as usize;
+
+		// Let the base handle the access itself if possible, it may not be plain memory
+		if self.is_clean(addr, len) {
+			return self.base.read_validated(addr, width);
+		}
+
+		let mut bytes = [0u8; 4];
+		self.read_bytes(addr, &mut bytes[..len])?;
+		Ok(u32::from_le_bytes(bytes))
+	}
+
+	fn write_validated(&mut self, addr: u32, width: Width, value: u32) -> Result<()> {
+		let len = width_bytes(width) as usize;
+		self.write_bytes(addr, &value.to_le_bytes()[..len])
+	}
+
+	fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> Result<()> {
+		let mut offset = 0;
+
+		self.for_each_chunk(addr, buf.len(), |current, start, page_offset, len| {
+			let chunk = &mut buf[offset..offset + len];
+			offset += len;
+
+			match self.pages.get(&start) {
+				Some(page) => {
+					self.check_range(current, len)?;
+					chunk.copy_from_slice(&page[page_offset..page_offset + len]);
+					Ok(())
+				},
+				None => self.base.read_bytes(current, chunk),
+			}
+		})
+	}
+
+	fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+		self.check_range(addr, data.len())?;
+
+		let mut chunks = Vec::new();
+		self.for_each_chunk(addr, data.len(), |_, start, page_offset, len| {
+			chunks.push((start, page_offset, len));
+			Ok(())
+		})?;
+
+		let mut offset = 0;
+		for (start, page_offset, len) in chunks {
+			let page = self.page_mut(start);
+			page[page_offset..page_offset + len].copy_from_slice(&data[offset..offset + len]);
+			offset += len;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use core::cell::Cell;
+	use crate::memory::{
+		Image,
+		Mapped,
+		SimpleImage,
+	};
+
+	/// Counts the calls to `contains`
+	struct Counting {
+		memory: SimpleImage,
+		contains: Cell<usize>,
+	}
+
+	impl Memory for Counting {
+		fn contains(&self, addr: u32) -> bool {
+			self.contains.set(self.contains.get() + 1);
+			self.memory.contains(addr)
+		}
+
+		fn size(&self) -> u32 {
+			self.memory.size()
+		}
+
+		fn read_validated(&self, addr: u32, width: Width) -> Result<u32> {
+			self.memory.read_validated(addr, width)
+		}
+
+		fn write_validated(&mut self, addr: u32, width: Width, value: u32) -> Result<()> {
+			self.memory.write_validated(addr, width, value)
+		}
+
+		fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> Result<()> {
+			self.memory.read_bytes(addr, buf)
+		}
+	}
+
+	#[test]
+	fn test_overlay() {
+		let mut base = SimpleImage::new(0x2000);
+		assert!(base.write(0x100, Width::Word, 0x11111111).is_ok());
+
+		let base = Rc::new(base);
+		let mut a = Overlay::new(base.clone(), PageSize::K4);
+		let mut b = a.fork();
+
+		// Writes are private to each overlay
+		assert!(a.write(0x100, Width::Word, 0x22222222).is_ok());
+		assert!(b.write(0x1ffe, Width::Short, 0x3333).is_ok());
+		assert_eq!(a.read(0x100, Width::Word).ok(), Some(0x22222222));
+		assert_eq!(b.read(0x100, Width::Word).ok(), Some(0x11111111));
+		assert_eq!(base.read(0x100, Width::Word).ok(), Some(0x11111111));
+		assert_eq!(a.read(0x1ffe, Width::Short).ok(), Some(0));
+		assert_eq!(b.read(0x1ffe, Width::Short).ok(), Some(0x3333));
+
+		// Unmodified bytes in a dirty page come from the base
+		assert_eq!(a.read(0x104, Width::Word).ok(), Some(0));
+		assert_eq!(a.dirty_pages(), 1);
+
+		// Accesses outside the base still fault
+		assert!(a.write(0x2000, Width::Byte, 0).is_err());
+		assert!(a.write_bytes(0x1fff, &[1, 2]).is_err());
+
+		a.reset();
+		assert_eq!(a.dirty_pages(), 0);
+		assert_eq!(a.read(0x100, Width::Word).ok(), Some(0x11111111));
+	}
+
+	#[test]
+	fn test_bulk_checks() {
+		let base = Rc::new(Counting {
+			memory: SimpleImage::new(0x4000),
+			contains: Cell::new(0),
+		});
+		let mut overlay = Overlay::new(base.clone(), PageSize::K4);
+
+		// The range is checked once, not per byte
+		assert!(overlay.write_bytes(0x800, &[0x5a; 0x2000]).is_ok());
+		assert!(base.contains.get() <= 2, "{} calls", base.contains.get());
+
+		assert!(overlay.write_bytes(0x3fff, &[1, 2]).is_err());
+		assert!(overlay.write_bytes(0xffff_ffff, &[1, 2]).is_err());
+	}
+
+	#[test]
+	fn test_compose() {
+		let mut base = Image::new(PageSize::K4);
+		assert!(base.write_bytes(0x8000_0ffc, &[1, 2, 3, 4, 5, 6, 7, 8]).is_ok());
+
+		// Overlay of an `Image`, mapped into a `Mapped`
+		let overlay = Overlay::new(Rc::new(base), PageSize::K4);
+		let mut mapped = Mapped::new();
+		assert!(mapped.map(0, Box::new(overlay)).is_some());
+
+		// Word access straddling two pages
+		assert!(mapped.write_bytes(0x8000_0ffe, &[0xaa, 0xbb, 0xcc, 0xdd]).is_ok());
+
+		let mut buf = [0u8; 8];
+		assert!(mapped.read_bytes(0x8000_0ffc, &mut buf).is_ok());
+		assert_eq!(buf, [1, 2, 0xaa, 0xbb, 0xcc, 0xdd, 7, 8]);
+	}
+}
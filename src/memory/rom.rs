@@ -0,0 +1,68 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use bibe_instr::Width;
+
+use crate::Result;
+
+use super::Memory;
+
+/// Read-only view of a memory, every write faults
+pub struct Rom<M: Memory>(M);
+
+impl<M: Memory> Rom<M> {
+	pub fn new(memory: M) -> Self {
+		Self(memory)
+	}
+
+	pub fn into_inner(self) -> M {
+		self.0
+	}
+}
+
+impl<M: Memory> Memory for Rom<M> {
+	fn size(&self) -> u32 {
+		self.0.size()
+	}
+
+	fn contains(&self, addr: u32) -> bool {
+		self.0.contains(addr)
+	}
+
+	fn validate_access(&self, addr: u32, width: Width) -> bool {
+		self.0.validate_access(addr, width)
+	}
+
+	fn read_validated(&self, addr: u32, width: Width) -> Result<u32> {
+		self.0.read_validated(addr, width)
+	}
+
+	fn wait_states(&self, addr: u32, width: Width) -> u32 {
+		self.0.wait_states(addr, width)
+	}
+
+	fn next_populated(&self, addr: u32) -> Option<(u32, u64)> {
+		self.0.next_populated(addr)
+	}
+
+	fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> Result<()> {
+		self.0.read_bytes(addr, buf)
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "std")]
+mod test {
+	use super::*;
+	use crate::memory::SimpleImage;
+
+	#[test]
+	fn test_rom() {
+		let mut image = SimpleImage::new(8);
+		assert!(image.write(0, Width::Word, 0x12345678).is_ok());
+
+		let mut rom = Rom::new(image);
+		assert_eq!(rom.read(0, Width::Word).ok(), Some(0x12345678));
+		assert!(rom.write(0, Width::Word, 0).is_err());
+		assert!(rom.write_bytes(4, &[1, 2]).is_err());
+		assert_eq!(rom.read(0, Width::Word).ok(), Some(0x12345678));
+	}
+}
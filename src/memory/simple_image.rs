@@ -2,10 +2,14 @@
 #![cfg(feature = "std")]
 use bibe_instr::Width;
 
-use crate::Result;
+use crate::{
+	Interrupt,
+	Result,
+};
 
 extern crate std;
 
+use core::ops::Range;
 use std::io;
 use std::vec::Vec;
 use std::vec;
@@ -25,13 +29,14 @@ impl SimpleImage {
 		}
 	}
 
-	pub fn load(r: &mut dyn io::Read) -> Self {
+	/// Create an image from a raw binary, the binary is placed at address 0
+	pub fn load(r: &mut dyn io::Read) -> io::Result<Self> {
 		let mut data = Vec::new();
-		r.read_to_end(&mut data).expect("Failed to load image");
+		r.read_to_end(&mut data)?;
 
-		Self {
+		Ok(Self {
 			mem: data
-		}
+		})
 	}
 
 	#[inline(always)]
@@ -43,6 +48,18 @@ impl SimpleImage {
 	fn set(&mut self, addr: u32, value: u32) {
 		self.mem[addr as usize] = value as u8;
 	}
+
+	/// Byte range of an access of `len` bytes at `addr`, if it is entirely contained
+	fn range(&self, addr: u32, len: usize) -> Option<Range<usize>> {
+		let start = addr as usize;
+		let end = start.checked_add(len)?;
+
+		if end > self.mem.len() {
+			return None;
+		}
+
+		Some(start..end)
+	}
 }
 
 impl Memory for SimpleImage {
@@ -74,4 +91,38 @@ impl Memory for SimpleImage {
 			},
 		})
 	}
-}
\ No newline at end of file
+
+	fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> Result<()> {
+		let range = self.range(addr, buf.len()).ok_or(Interrupt::mem_fault(addr))?;
+		buf.copy_from_slice(&self.mem[range]);
+		Ok(())
+	}
+
+	fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+		let range = self.range(addr, data.len()).ok_or(Interrupt::mem_fault(addr))?;
+		self.mem[range].copy_from_slice(data);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_bytes() {
+		let mut image = SimpleImage::new(16);
+
+		assert!(image.write_bytes(4, &[1, 2, 3, 4]).is_ok());
+		assert_eq!(image.read(4, Width::Word).ok(), Some(0x04030201));
+
+		let mut buf = [0u8; 6];
+		assert!(image.read_bytes(3, &mut buf).is_ok());
+		assert_eq!(buf, [0, 1, 2, 3, 4, 0]);
+
+		// Accesses past the end fail without touching memory
+		assert!(image.write_bytes(14, &[0xff; 4]).is_err());
+		assert!(image.read_bytes(12, &mut buf).is_err());
+		assert_eq!(image.read(12, Width::Word).ok(), Some(0));
+	}
+}
@@ -0,0 +1,138 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+#![cfg(feature = "std")]
+extern crate std;
+
+use core::cell::{
+	Cell,
+	RefCell,
+};
+use std::{
+	boxed::Box,
+	io::{
+		self,
+		Read,
+		Write,
+	},
+};
+
+use bibe_instr::Width;
+
+use crate::Result;
+
+use super::Memory;
+
+/// Writes send the low byte to stdout, reads block for the next byte of stdin
+pub const UART_DATA_REG: u32 = 0;
+/// Bit 0 is set once stdin has reached end of file
+pub const UART_STATUS_REG: u32 = 4;
+pub const UART_SIZE: u32 = 8;
+
+/// Read from `UART_DATA_REG` at end of file
+pub const UART_EOF: u32 = u32::MAX;
+
+/// Memory mapped UART, connected to the host's stdio by default
+pub struct Uart {
+	input: RefCell<Box<dyn Read>>,
+	output: Box<dyn Write>,
+	eof: Cell<bool>,
+}
+
+impl Uart {
+	pub fn new() -> Self {
+		Self::with_io(Box::new(io::stdin()), Box::new(io::stdout()))
+	}
+
+	/// UART connected to `input` and `output` instead of stdio
+	pub fn with_io(input: Box<dyn Read>, output: Box<dyn Write>) -> Self {
+		Self {
+			input: RefCell::new(input),
+			output,
+			eof: Cell::new(false),
+		}
+	}
+}
+
+impl Memory for Uart {
+	fn size(&self) -> u32 {
+		UART_SIZE
+	}
+
+	/// Registers only support aligned accesses
+	fn validate_access(&self, addr: u32, _width: Width) -> bool {
+		addr % 4 == 0 && addr < UART_SIZE
+	}
+
+	/// Registers have side effects, so never dump them
+	fn next_populated(&self, _addr: u32) -> Option<(u32, u64)> {
+		None
+	}
+
+	fn read_validated(&self, addr: u32, _width: Width) -> Result<u32> {
+		if addr == UART_STATUS_REG {
+			return Ok(self.eof.get() as u32);
+		}
+
+		let mut byte = [0u8];
+		match self.input.borrow_mut().read(&mut byte) {
+			Ok(1) => Ok(byte[0] as u32),
+			_ => {
+				self.eof.set(true);
+				Ok(UART_EOF)
+			},
+		}
+	}
+
+	fn write_validated(&mut self, addr: u32, _width: Width, value: u32) -> Result<()> {
+		if addr == UART_DATA_REG {
+			// Output errors aren't visible to the guest
+			let _ = self.output.write_all(&[value as u8]);
+			let _ = self.output.flush();
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::{
+		rc::Rc,
+		vec::Vec,
+	};
+
+	/// Output that can be inspected after it was given to the UART
+	#[derive(Clone)]
+	struct Shared(Rc<RefCell<Vec<u8>>>);
+
+	impl Write for Shared {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.borrow_mut().write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn test_registers() {
+		let output = Shared(Rc::new(RefCell::new(Vec::new())));
+		let mut uart = Uart::with_io(Box::new(io::Cursor::new(b"hi".to_vec())), Box::new(output.clone()));
+
+		assert!(uart.write(UART_DATA_REG, Width::Word, 0x141).is_ok());
+		assert!(uart.write(UART_DATA_REG, Width::Byte, 'b' as u32).is_ok());
+		assert!(uart.write(UART_STATUS_REG, Width::Word, 1).is_ok());
+		assert_eq!(*output.0.borrow(), b"Ab");
+
+		assert_eq!(uart.read(UART_STATUS_REG, Width::Word).ok(), Some(0));
+		assert_eq!(uart.read(UART_DATA_REG, Width::Word).ok(), Some('h' as u32));
+		assert_eq!(uart.read(UART_DATA_REG, Width::Word).ok(), Some('i' as u32));
+		assert_eq!(uart.read(UART_DATA_REG, Width::Word).ok(), Some(UART_EOF));
+		assert_eq!(uart.read(UART_STATUS_REG, Width::Word).ok(), Some(1));
+
+		// Only aligned register accesses are valid
+		assert!(uart.read(2, Width::Short).is_err());
+		assert!(uart.read(UART_SIZE, Width::Word).is_err());
+	}
+}
@@ -0,0 +1,405 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Interactive monitor for stepping and inspecting a machine
+#![cfg(feature = "std")]
+extern crate std;
+
+use std::{
+	collections::BTreeSet,
+	io::{
+		self,
+		BufRead,
+		Write,
+	},
+	string::String,
+	vec::Vec,
+};
+
+use bibe_instr::{
+	Instruction,
+	Register,
+	Width,
+};
+
+use crate::{
+	abi::{
+		parse_register,
+		RegisterName,
+	},
+	memory::{
+		dump,
+		Memory,
+	},
+	state::{
+		csr::CsrCollection,
+		Psr,
+		ResetKind,
+		State,
+	},
+	target::Target,
+	trace::Disasm,
+	Interrupt,
+};
+
+const HELP: &str = "\
+commands:
+  s, step [n]          execute n instructions (default 1)
+  c, cont [addr]       run until pc reaches addr, a breakpoint, or the machine stops
+  b, break [addr]      set a breakpoint, or list them
+  d, delete <addr>     remove a breakpoint
+  r, regs [reg]        print all registers, or one
+  set <reg> <value>    set a register
+  psr                  print the PSR with its flags decoded
+  x <addr> [len]       dump memory (default 64 bytes)
+  dis [addr] [n]       disassemble n instructions around pc, or from addr
+  sym <addr>           show the symbol containing addr
+  csr                  list CSR blocks
+  irq <n>              raise external interrupt n, refused while interrupts are masked
+  reset                warm reset
+  q, quit              leave the monitor
+
+addresses are decimal, 0x prefixed hex, or symbol names";
+
+/// Instructions `cont` runs before giving control back
+const CONTINUE_LIMIT: u64 = 100_000_000;
+/// Instructions shown before and after pc by `dis`
+const DIS_CONTEXT: u32 = 4;
+
+/// Whether the monitor should keep reading commands
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+	Continue,
+	Quit,
+}
+
+/// Decimal or `0x` prefixed hex
+fn parse_u32(s: &str) -> Option<u32> {
+	match s.strip_prefix("0x") {
+		Some(hex) => u32::from_str_radix(hex, 16).ok(),
+		None => s.parse().ok(),
+	}
+}
+
+pub struct Monitor<'a, T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	state: &'a mut State<T, M, C>,
+	breakpoints: BTreeSet<u32>,
+}
+
+impl<'a, T, M, C> Monitor<'a, T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	pub fn new(state: &'a mut State<T, M, C>) -> Self {
+		Self {
+			state,
+			breakpoints: BTreeSet::new(),
+		}
+	}
+
+	/// Read commands from `input` until it ends or `quit` is entered
+	pub fn run(&mut self, input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+		loop {
+			write!(out, "(bibe) ")?;
+			out.flush()?;
+
+			let mut line = String::new();
+			if input.read_line(&mut line)? == 0 {
+				return Ok(());
+			}
+
+			if self.command(line.trim(), out)? == Action::Quit {
+				return Ok(());
+			}
+		}
+	}
+
+	/// Execute a single command line
+	pub fn command(&mut self, line: &str, out: &mut dyn Write) -> io::Result<Action> {
+		let mut args = line.split_whitespace();
+		let command = match args.next() {
+			Some(command) => command,
+			None => return Ok(Action::Continue),
+		};
+		let args: Vec<&str> = args.collect();
+
+		let res = match command {
+			"s" | "step" => self.step(&args, out),
+			"c" | "cont" => self.cont(&args, out),
+			"b" | "break" => self.set_breakpoint(&args, out),
+			"d" | "delete" => self.delete_breakpoint(&args, out),
+			"r" | "regs" => self.regs(&args, out),
+			"set" => self.set(&args, out),
+			"psr" => self.psr(out),
+			"x" => self.examine(&args, out),
+			"dis" => self.dis(&args, out),
+			"sym" => self.sym(&args, out),
+			"csr" => self.csr(out),
+			"irq" => self.irq(&args, out),
+			"reset" => {
+				self.state.reset_with(ResetKind::Warm);
+				self.where_am_i(out)
+			},
+			"q" | "quit" => return Ok(Action::Quit),
+			"h" | "help" => writeln!(out, "{HELP}").map(|_| true),
+			_ => {
+				writeln!(out, "unknown command {command}, try help")?;
+				Ok(true)
+			},
+		};
+
+		if !res? {
+			writeln!(out, "invalid arguments for {command}")?;
+		}
+
+		Ok(Action::Continue)
+	}
+
+	fn pc(&self) -> u32 {
+		self.state.core().read_pc()
+	}
+
+	/// Number, or the address of a symbol
+	fn address(&self, s: &str) -> Option<u32> {
+		parse_u32(s).or_else(|| self.state.symbols()?.address_of(s))
+	}
+
+	/// Print the instruction at `addr`, marked if it is the next one to execute
+	fn print_instruction(&self, addr: u32, out: &mut dyn Write) -> io::Result<()> {
+		let marker = if addr == self.pc() { "=>" } else { "  " };
+		let location = self.state.describe(addr);
+
+		match self.state.read(addr, Width::Word) {
+			Ok(word) => match Instruction::decode(word) {
+				Some(instr) => writeln!(out, "{marker} {location}: {word:08x}  {}", Disasm(&instr)),
+				None => writeln!(out, "{marker} {location}: {word:08x}  <invalid>"),
+			},
+			Err(_) => writeln!(out, "{marker} {location}: <unmapped>"),
+		}
+	}
+
+	fn where_am_i(&self, out: &mut dyn Write) -> io::Result<bool> {
+		if let Some(reason) = self.state.stop_reason() {
+			writeln!(out, "stopped: {reason:?}")?;
+		}
+
+		self.print_instruction(self.pc(), out)?;
+		Ok(true)
+	}
+
+	fn step(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		let count = match args {
+			[] => 1,
+			[n] => match parse_u32(n) {
+				Some(n) => n,
+				None => return Ok(false),
+			},
+			_ => return Ok(false),
+		};
+
+		for _ in 0..count {
+			if self.state.is_stopped() {
+				break;
+			}
+			self.state.execute_one();
+		}
+
+		self.where_am_i(out)
+	}
+
+	fn cont(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		let target = match args {
+			[] => None,
+			[addr] => match self.address(addr) {
+				Some(addr) => Some(addr),
+				None => return Ok(false),
+			},
+			_ => return Ok(false),
+		};
+
+		let mut executed = 0;
+		while !self.state.is_stopped() && executed < CONTINUE_LIMIT {
+			self.state.execute_one();
+			executed += 1;
+
+			let pc = self.pc();
+			if Some(pc) == target || self.breakpoints.contains(&pc) {
+				break;
+			}
+		}
+
+		if executed == CONTINUE_LIMIT {
+			writeln!(out, "gave up after {CONTINUE_LIMIT} instructions")?;
+		}
+
+		self.where_am_i(out)
+	}
+
+	fn set_breakpoint(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		match args {
+			[] => {
+				for addr in &self.breakpoints {
+					writeln!(out, "{}", self.state.describe(*addr))?;
+				}
+				Ok(true)
+			},
+			[addr] => match self.address(addr) {
+				Some(addr) => {
+					self.breakpoints.insert(addr);
+					writeln!(out, "breakpoint at {}", self.state.describe(addr))?;
+					Ok(true)
+				},
+				None => Ok(false),
+			},
+			_ => Ok(false),
+		}
+	}
+
+	fn delete_breakpoint(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		let addr = match args {
+			[addr] => match self.address(addr) {
+				Some(addr) => addr,
+				None => return Ok(false),
+			},
+			_ => return Ok(false),
+		};
+
+		if !self.breakpoints.remove(&addr) {
+			writeln!(out, "no breakpoint at {}", self.state.describe(addr))?;
+		}
+		Ok(true)
+	}
+
+	fn sym(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		match args {
+			[addr] => match self.address(addr) {
+				Some(addr) => writeln!(out, "{}", self.state.describe(addr)).map(|_| true),
+				None => Ok(false),
+			},
+			_ => Ok(false),
+		}
+	}
+
+	fn print_reg(&self, reg: Register, out: &mut dyn Write) -> io::Result<()> {
+		let value = self.state.core().read_reg(reg);
+		writeln!(out, "{}: 0x{value:08x} ({value})", RegisterName(reg))
+	}
+
+	fn regs(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		match args {
+			[] => {
+				for row in 0..8 {
+					for col in 0..4 {
+						let reg = Register::new(row * 4 + col).unwrap();
+						write!(out, "{:>4}: {:08x}  ", RegisterName(reg), self.state.core().read_reg(reg))?;
+					}
+					writeln!(out)?;
+				}
+				Ok(true)
+			},
+			[name] => match parse_register(name) {
+				Some(reg) => self.print_reg(reg, out).map(|_| true),
+				None => Ok(false),
+			},
+			_ => Ok(false),
+		}
+	}
+
+	fn set(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		let (reg, value) = match args {
+			[reg, value] => match (parse_register(reg), parse_u32(value)) {
+				(Some(reg), Some(value)) => (reg, value),
+				_ => return Ok(false),
+			},
+			_ => return Ok(false),
+		};
+
+		self.state.core_mut().write_reg(reg, value);
+		self.print_reg(reg, out).map(|_| true)
+	}
+
+	fn psr(&mut self, out: &mut dyn Write) -> io::Result<bool> {
+		let psr = Psr(self.state.read_psr());
+		writeln!(
+			out,
+			"psr: 0x{:08x} n={} z={} c={} v={} interrupt_mode={} exception_enabled={}",
+			psr.0, psr.n(), psr.z(), psr.c(), psr.v(), psr.interrupt_mode(), psr.exception_enabled(),
+		)?;
+		Ok(true)
+	}
+
+	fn examine(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		let (addr, len) = match args {
+			[addr] => (self.address(addr), Some(64)),
+			[addr, len] => (self.address(addr), parse_u32(len)),
+			_ => return Ok(false),
+		};
+
+		let (addr, len) = match (addr, len) {
+			(Some(addr), Some(len)) => (addr, len),
+			_ => return Ok(false),
+		};
+
+		let mut dumped = String::new();
+		dump::hexdump(&*self.state, addr, len, &mut dumped).map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+		if dumped.is_empty() {
+			writeln!(out, "nothing mapped at {addr:08x}")?;
+		}
+		out.write_all(dumped.as_bytes())?;
+		Ok(true)
+	}
+
+	fn dis(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		let (start, count) = match args {
+			[] => (self.pc().saturating_sub(4 * DIS_CONTEXT), 2 * DIS_CONTEXT + 1),
+			[addr] => match self.address(addr) {
+				Some(addr) => (addr, 2 * DIS_CONTEXT + 1),
+				None => return Ok(false),
+			},
+			[addr, count] => match (self.address(addr), parse_u32(count)) {
+				(Some(addr), Some(count)) => (addr, count),
+				_ => return Ok(false),
+			},
+			_ => return Ok(false),
+		};
+
+		for i in 0..count {
+			self.print_instruction(start.wrapping_add(4 * i), out)?;
+		}
+		Ok(true)
+	}
+
+	fn csr(&mut self, out: &mut dyn Write) -> io::Result<bool> {
+		let blocks = self.state.csr_blocks();
+		for i in 0..blocks.len() {
+			let block = blocks.index(i);
+			let end = block.base_reg() as u64 + block.size() as u64;
+			writeln!(out, "{i}: {:<8} {:08x}..{end:08x}", block.name(), block.base_reg())?;
+		}
+		Ok(true)
+	}
+
+	fn irq(&mut self, args: &[&str], out: &mut dyn Write) -> io::Result<bool> {
+		let n = match args {
+			[n] => match n.parse() {
+				Ok(n) => n,
+				Err(_) => return Ok(false),
+			},
+			_ => return Ok(false),
+		};
+
+		// External interrupts can't be delivered until the guest enables them again
+		if Psr(self.state.read_psr()).exception_enabled() == 0 {
+			writeln!(out, "interrupts are masked, irq {n} not raised")?;
+			return Ok(true);
+		}
+
+		self.state.handle_interrupt(&Interrupt::irq(n));
+		self.where_am_i(out)
+	}
+}
@@ -0,0 +1,294 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Instruction level profiler
+//!
+//! Attach a `Profiler` to a state with `State::add_observer`. Calls are tracked from
+//! branch-and-link instructions, a call returns once execution reaches its return address.
+//! Results are exported as folded stacks, the input format of `flamegraph.pl` and `inferno`,
+//! or as a per-function report.
+#![cfg(feature = "std")]
+extern crate std;
+
+use core::fmt;
+use std::{
+	collections::{
+		BTreeMap,
+		HashMap,
+	},
+	format,
+	string::String,
+	vec::Vec,
+};
+
+use bibe_instr::Instruction;
+
+use crate::{
+	state::observer::{
+		Observer,
+		Retired,
+	},
+	trace::Symbols,
+};
+
+/// Counters for one pc or one stack
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+	pub instructions: u64,
+	pub cycles: u64,
+	/// Samples taken, only counted by sampling profilers
+	pub samples: u64,
+}
+
+impl Counts {
+	fn add(&mut self, other: &Counts) {
+		self.instructions += other.instructions;
+		self.cycles += other.cycles;
+		self.samples += other.samples;
+	}
+
+	fn get(&self, weight: Weight) -> u64 {
+		match weight {
+			Weight::Instructions => self.instructions,
+			Weight::Cycles => self.cycles,
+			Weight::Samples => self.samples,
+		}
+	}
+}
+
+/// Counter used to weigh exported stacks
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Weight {
+	Instructions,
+	Cycles,
+	Samples,
+}
+
+/// Name of code outside of every symbol
+const UNKNOWN: &str = "[unknown]";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Frame {
+	/// Entry address of the function
+	function: u32,
+	/// Address execution continues at when the function returns
+	ret: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct Profiler {
+	/// Sample every `period` cycles, zero counts every instruction
+	period: u64,
+	/// Cycles left until the next sample
+	until_sample: u64,
+	per_pc: BTreeMap<u32, Counts>,
+	/// Keyed by the entry addresses of the functions on the call stack, outermost first
+	stacks: HashMap<Vec<u32>, Counts>,
+	frames: Vec<Frame>,
+	/// Outermost function, the first pc seen
+	root: Option<u32>,
+}
+
+impl Profiler {
+	/// Profiler counting every retired instruction and its cycles
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Profiler that takes a sample every `period` cycles, attributed to the instruction executing at the time
+	pub fn sampling(period: u64) -> Self {
+		Self {
+			period,
+			until_sample: period,
+			..Self::default()
+		}
+	}
+
+	pub fn per_pc(&self) -> &BTreeMap<u32, Counts> {
+		&self.per_pc
+	}
+
+	/// Totals over the whole run
+	pub fn total(&self) -> Counts {
+		let mut total = Counts::default();
+		for counts in self.per_pc.values() {
+			total.add(counts);
+		}
+		total
+	}
+
+	fn stack(&self, root: u32) -> Vec<u32> {
+		let mut stack = Vec::with_capacity(self.frames.len() + 1);
+		stack.push(root);
+		stack.extend(self.frames.iter().map(|frame| frame.function));
+		stack
+	}
+
+	fn count(&mut self, pc: u32, root: u32, counts: Counts) {
+		self.per_pc.entry(pc).or_default().add(&counts);
+		let stack = self.stack(root);
+		self.stacks.entry(stack).or_default().add(&counts);
+	}
+
+	fn track_calls(&mut self, event: &Retired) {
+		if let Instruction::Jump(jump) = event.instr {
+			if jump.link && event.next_pc != event.pc.wrapping_add(4) {
+				self.frames.push(Frame {
+					function: event.next_pc,
+					ret: event.pc.wrapping_add(4),
+				});
+				return;
+			}
+		}
+
+		// A return writes the pc, falling through to a return address, e.g. past a conditional
+		// call that wasn't taken, doesn't leave the function
+		if event.next_pc == event.pc.wrapping_add(4) {
+			return;
+		}
+
+		// Returns may skip frames, e.g. tail calls or longjmp
+		if let Some(depth) = self.frames.iter().rposition(|frame| frame.ret == event.next_pc) {
+			self.frames.truncate(depth);
+		}
+	}
+
+	/// Folded stacks, one `outer;inner count` line per call stack
+	pub fn write_folded(&self, symbols: Option<&dyn Symbols>, weight: Weight, out: &mut dyn fmt::Write) -> fmt::Result {
+		let mut lines = BTreeMap::new();
+		for (stack, counts) in &self.stacks {
+			let count = counts.get(weight);
+			if count == 0 {
+				continue;
+			}
+
+			let names: Vec<String> = stack.iter().map(|addr| function_name(symbols, *addr)).collect();
+			*lines.entry(names.join(";")).or_insert(0) += count;
+		}
+
+		for (stack, count) in lines {
+			writeln!(out, "{stack} {count}")?;
+		}
+		Ok(())
+	}
+
+	/// Counts per function, sorted by self cycles
+	///
+	/// Without symbols only the functions found through calls can be told apart.
+	pub fn functions(&self, symbols: Option<&dyn Symbols>) -> Vec<FunctionCounts> {
+		let mut functions: HashMap<String, FunctionCounts> = HashMap::new();
+
+		match symbols {
+			Some(symbols) => {
+				for (pc, counts) in &self.per_pc {
+					let name = function_name(Some(symbols), *pc);
+					functions.entry(name.clone()).or_insert_with(|| FunctionCounts::new(name)).own.add(counts);
+				}
+			},
+			None => {
+				for (stack, counts) in &self.stacks {
+					let name = function_name(None, *stack.last().unwrap());
+					functions.entry(name.clone()).or_insert_with(|| FunctionCounts::new(name)).own.add(counts);
+				}
+			},
+		}
+
+		// A function is counted once per stack, even if it recursed
+		for (stack, counts) in &self.stacks {
+			let mut names: Vec<String> = stack.iter().map(|addr| function_name(symbols, *addr)).collect();
+			names.sort();
+			names.dedup();
+
+			for name in names {
+				functions.entry(name.clone()).or_insert_with(|| FunctionCounts::new(name)).total.add(counts);
+			}
+		}
+
+		let mut functions: Vec<FunctionCounts> = functions.into_values().collect();
+		functions.sort_by(|a, b| b.own.cycles.cmp(&a.own.cycles)
+			.then(b.own.samples.cmp(&a.own.samples))
+			.then(a.name.cmp(&b.name)));
+		functions
+	}
+
+	/// Table of `functions`
+	pub fn write_report(&self, symbols: Option<&dyn Symbols>, out: &mut dyn fmt::Write) -> fmt::Result {
+		let total = self.total();
+		let weight = if self.period == 0 { Weight::Cycles } else { Weight::Samples };
+		let percent = |count: u64| match total.get(weight) {
+			0 => 0.0,
+			total => 100.0 * count as f64 / total as f64,
+		};
+
+		writeln!(out, "{:>7} {:>7} {:>14} {:>14}  function", "self%", "total%", "instructions", "cycles")?;
+		for function in self.functions(symbols) {
+			writeln!(
+				out,
+				"{:>6.2}% {:>6.2}% {:>14} {:>14}  {}",
+				percent(function.own.get(weight)),
+				percent(function.total.get(weight)),
+				function.own.instructions,
+				function.own.cycles,
+				function.name,
+			)?;
+		}
+		Ok(())
+	}
+}
+
+/// Name of the function containing `addr`, its address if there are no symbols
+fn function_name(symbols: Option<&dyn Symbols>, addr: u32) -> String {
+	match symbols {
+		Some(symbols) => symbols.lookup(addr).map_or(UNKNOWN, |(name, _)| name).into(),
+		None => format!("0x{addr:08x}"),
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionCounts {
+	pub name: String,
+	/// Spent in the function itself
+	pub own: Counts,
+	/// Spent in the function and everything it called
+	pub total: Counts,
+}
+
+impl FunctionCounts {
+	fn new(name: String) -> Self {
+		Self {
+			name,
+			own: Counts::default(),
+			total: Counts::default(),
+		}
+	}
+}
+
+impl Observer for Profiler {
+	fn retired(&mut self, event: &Retired) {
+		let root = *self.root.get_or_insert(event.pc);
+
+		if self.period == 0 {
+			self.count(event.pc, root, Counts {
+				instructions: 1,
+				cycles: event.cycles,
+				samples: 0,
+			});
+		} else {
+			let mut samples = 0;
+			let mut cycles = event.cycles;
+			while cycles >= self.until_sample {
+				cycles -= self.until_sample;
+				self.until_sample = self.period;
+				samples += 1;
+			}
+			self.until_sample -= cycles;
+
+			if samples > 0 {
+				self.count(event.pc, root, Counts {
+					samples,
+					..Counts::default()
+				});
+			}
+		}
+
+		self.track_calls(event);
+	}
+}
@@ -0,0 +1,299 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Block engine, basic blocks are translated into threaded code
+//!
+//! Each instruction of a block is translated once into its handler and operands, so executing
+//! a block skips the fetch, decode and dispatch of the interpreter. Blocks end at branches,
+//! CSR accesses and anything that doesn't decode, those are left to the interpreter.
+#![cfg(feature = "std")]
+extern crate std;
+
+use std::{
+	collections::BTreeMap,
+	rc::Rc,
+	vec::Vec,
+};
+
+use bibe_instr::{
+	Instruction,
+	Width,
+};
+
+use crate::{
+	memory::Memory,
+	target::{
+		InstructionClass,
+		Target,
+	},
+	Result,
+};
+
+use super::{
+	csr::CsrCollection,
+	jump,
+	memory,
+	rri,
+	rrr,
+	ResetKind,
+	State,
+};
+
+/// Longest block in instructions
+const MAX_BLOCK_LEN: usize = 64;
+const MAX_BLOCK_BYTES: u32 = 4 * MAX_BLOCK_LEN as u32;
+
+type Handler<T, M, C> = fn(&mut State<T, M, C>, &Instruction) -> Result<()>;
+
+struct Op<T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	handler: Handler<T, M, C>,
+	instr: Instruction,
+	class: InstructionClass,
+}
+
+pub(super) struct Block<T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	start: u32,
+	ops: Vec<Op<T, M, C>>,
+}
+
+impl<T, M, C> Block<T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	fn end(&self) -> u64 {
+		self.start as u64 + 4 * self.ops.len() as u64
+	}
+}
+
+/// Translated blocks keyed by start address
+pub(super) struct BlockCache<T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	blocks: BTreeMap<u32, Rc<Block<T, M, C>>>,
+	/// Incremented whenever a block is dropped, so a running block can tell it went stale
+	generation: u64,
+}
+
+impl<T, M, C> BlockCache<T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	pub fn new() -> Self {
+		Self {
+			blocks: BTreeMap::new(),
+			generation: 0,
+		}
+	}
+
+	/// Drop every block containing any of the `len` bytes starting at `addr`
+	pub fn invalidate(&mut self, addr: u32, len: usize) {
+		if len == 0 || self.blocks.is_empty() {
+			return;
+		}
+
+		let end = addr as u64 + len as u64;
+		let first = addr.saturating_sub(MAX_BLOCK_BYTES);
+
+		loop {
+			let stale = self.blocks.range(first..)
+				.take_while(|(start, _)| (**start as u64) < end)
+				.find(|(_, block)| block.end() > addr as u64)
+				.map(|(start, _)| *start);
+
+			match stale {
+				Some(start) => {
+					self.blocks.remove(&start);
+					self.generation += 1;
+				},
+				None => break,
+			}
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.blocks.clear();
+		self.generation += 1;
+	}
+}
+
+fn rrr_op<T, M, C>(s: &mut State<T, M, C>, instr: &Instruction) -> Result<()>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	match instr {
+		Instruction::Rrr(i) => rrr::execute(s, i),
+		_ => unreachable!(),
+	}
+}
+
+fn rri_op<T, M, C>(s: &mut State<T, M, C>, instr: &Instruction) -> Result<()>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	match instr {
+		Instruction::Rri(i) => rri::execute(s, i),
+		_ => unreachable!(),
+	}
+}
+
+fn memory_op<T, M, C>(s: &mut State<T, M, C>, instr: &Instruction) -> Result<()>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	match instr {
+		Instruction::Memory(i) => memory::execute(s, i),
+		_ => unreachable!(),
+	}
+}
+
+fn jump_op<T, M, C>(s: &mut State<T, M, C>, instr: &Instruction) -> Result<()>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	match instr {
+		Instruction::Jump(i) => jump::execute(s, i),
+		_ => unreachable!(),
+	}
+}
+
+impl<T, M, C> Op<T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	/// `None` if the instruction has to go through the interpreter
+	fn translate(instr: Instruction) -> Option<Self> {
+		let handler: Handler<T, M, C> = match instr {
+			Instruction::Rrr(_) => rrr_op,
+			Instruction::Rri(_) => rri_op,
+			Instruction::Memory(_) => memory_op,
+			Instruction::Jump(_) => jump_op,
+			_ => return None,
+		};
+
+		Some(Op {
+			handler,
+			class: InstructionClass::of(&instr),
+			instr,
+		})
+	}
+}
+
+impl<T, M, C> State<T, M, C>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	/// Translate the block starting at `start`, `None` if its first instruction can't be translated
+	fn translate(&mut self, start: u32) -> Option<Rc<Block<T, M, C>>> {
+		let mut ops = Vec::new();
+		let mut pc = start;
+
+		while ops.len() < MAX_BLOCK_LEN {
+			let instr = match self.read(pc, Width::Word).and_then(|word| self.decode(word)) {
+				Ok(instr) => instr,
+				Err(_) => break,
+			};
+
+			let is_branch = matches!(instr, Instruction::Jump(_));
+			match Op::translate(instr) {
+				Some(op) => ops.push(op),
+				None => break,
+			}
+
+			pc = match pc.checked_add(4) {
+				Some(pc) if !is_branch => pc,
+				_ => break,
+			};
+		}
+
+		if ops.is_empty() {
+			return None;
+		}
+
+		let block = Rc::new(Block {
+			start,
+			ops,
+		});
+		self.blocks.blocks.insert(start, block.clone());
+		Some(block)
+	}
+
+	/// Execute the block at the current pc, falls back to the interpreter for a single instruction
+	pub(super) fn execute_block(&mut self) {
+		if self.reset_pending {
+			self.reset_with(ResetKind::Warm);
+		}
+
+		self.check_cycle_budget();
+		if self.memory.is_none() || self.is_stopped() {
+			return;
+		}
+
+		let start = self.core.read_pc();
+		let block = match self.blocks.blocks.get(&start).cloned() {
+			Some(block) => block,
+			None => match self.translate(start) {
+				Some(block) => block,
+				None => {
+					self.execute_one();
+					return;
+				},
+			},
+		};
+
+		let generation = self.blocks.generation;
+		for (i, op) in block.ops.iter().enumerate() {
+			if i > 0 {
+				self.check_cycle_budget();
+				if self.is_stopped() {
+					return;
+				}
+			}
+
+			let pc = block.start + 4 * i as u32;
+			let start = self.cycles();
+			self.add_cycles(self.wait_states(pc, Width::Word));
+			self.core.pc_touched = false;
+			let passed = self.condition_passed(&op.instr);
+
+			if let Err(int) = (op.handler)(self, &op.instr) {
+				self.handle_interrupt(&int);
+				return;
+			}
+
+			let taken = self.core.pc_touched;
+			self.retire(pc, &op.instr, op.class, start, passed);
+
+			// Leave once control flow changes or a store modified translated code
+			if taken || self.blocks.generation != generation {
+				return;
+			}
+		}
+	}
+}
@@ -0,0 +1,186 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use bibe_instr::{
+	memory,
+	Condition,
+	Instruction,
+};
+
+use super::Psr;
+
+/// Every predicate the PSR flags can express
+///
+/// Subtraction sets C on borrow, so the unsigned conditions are `Lower` when C is set
+/// and `HigherSame` when it is clear. Signed conditions take V into account.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConditionCode {
+	Always,
+	Never,
+	Equal,
+	NotEqual,
+	Negative,
+	NotNegative,
+	Overflow,
+	NotOverflow,
+	/// Unsigned <, same as carry set
+	Lower,
+	/// Unsigned >=, same as carry clear
+	HigherSame,
+	/// Unsigned >
+	Higher,
+	/// Unsigned <=
+	LowerSame,
+	/// Signed <
+	LessThan,
+	/// Signed <=
+	LessEqual,
+	/// Signed >
+	GreaterThan,
+	/// Signed >=
+	GreaterEqual,
+}
+
+/// The ISA condition field names the predicate used after a compare: `b.lt` and `b.ge`
+/// assemble to `Negative` and `NotNegative`, so those are the signed `<` and `>=` and
+/// look at V as well as N. `Carry` is the unsigned `<`.
+impl From<Condition> for ConditionCode {
+	fn from(cond: Condition) -> Self {
+		match cond {
+			Condition::Always => ConditionCode::Always,
+			Condition::Overflow => ConditionCode::Overflow,
+			Condition::Carry => ConditionCode::Lower,
+			Condition::Zero => ConditionCode::Equal,
+			Condition::Negative => ConditionCode::LessThan,
+			Condition::NotZero => ConditionCode::NotEqual,
+			Condition::NotNegative => ConditionCode::GreaterEqual,
+			Condition::GreaterThan => ConditionCode::GreaterThan,
+		}
+	}
+}
+
+impl ConditionCode {
+	pub fn evaluate(self, psr: &Psr) -> bool {
+		let n = psr.n() == 1;
+		let z = psr.z() == 1;
+		let c = psr.c() == 1;
+		let v = psr.v() == 1;
+
+		match self {
+			ConditionCode::Always => true,
+			ConditionCode::Never => false,
+			ConditionCode::Equal => z,
+			ConditionCode::NotEqual => !z,
+			ConditionCode::Negative => n,
+			ConditionCode::NotNegative => !n,
+			ConditionCode::Overflow => v,
+			ConditionCode::NotOverflow => !v,
+			ConditionCode::Lower => c,
+			ConditionCode::HigherSame => !c,
+			ConditionCode::Higher => !c && !z,
+			ConditionCode::LowerSame => c || z,
+			ConditionCode::LessThan => n != v,
+			ConditionCode::LessEqual => z || n != v,
+			ConditionCode::GreaterThan => !z && n == v,
+			ConditionCode::GreaterEqual => n == v,
+		}
+	}
+}
+
+/// Condition `instr` is predicated on, `None` for formats that always execute
+pub fn condition(instr: &Instruction) -> Option<Condition> {
+	match instr {
+		Instruction::Rrr(i) => Some(i.cond),
+		Instruction::Rri(i) => Some(i.cond),
+		Instruction::Memory(memory::Instruction::Rr(i)) => Some(i.cond),
+		Instruction::Memory(memory::Instruction::Ri(i)) => Some(i.cond),
+		Instruction::Jump(i) => Some(i.cond),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::state::util::{
+		binop_flags,
+		execute_binop,
+	};
+	use bibe_instr::BinOp;
+
+	// Bit `i` is set if the condition holds for PSR flags `i`, i.e. NZCV from msb to lsb
+	const TRUTH_TABLE: [(ConditionCode, u16); 16] = [
+		(ConditionCode::Always, 0b1111111111111111),
+		(ConditionCode::Never, 0b0000000000000000),
+		(ConditionCode::Equal, 0b1111000011110000),
+		(ConditionCode::NotEqual, 0b0000111100001111),
+		(ConditionCode::Negative, 0b1111111100000000),
+		(ConditionCode::NotNegative, 0b0000000011111111),
+		(ConditionCode::Overflow, 0b1010101010101010),
+		(ConditionCode::NotOverflow, 0b0101010101010101),
+		(ConditionCode::Lower, 0b1100110011001100),
+		(ConditionCode::HigherSame, 0b0011001100110011),
+		(ConditionCode::Higher, 0b0000001100000011),
+		(ConditionCode::LowerSame, 0b1111110011111100),
+		(ConditionCode::LessThan, 0b0101010110101010),
+		(ConditionCode::LessEqual, 0b1111010111111010),
+		(ConditionCode::GreaterThan, 0b0000101000000101),
+		(ConditionCode::GreaterEqual, 0b1010101001010101),
+	];
+
+	#[test]
+	fn test_truth_table() {
+		for (cond, table) in TRUTH_TABLE {
+			for flags in 0..16 {
+				let expected = table & (1 << flags) != 0;
+				assert_eq!(cond.evaluate(&Psr(flags)), expected, "{cond:?} flags {flags:04b}");
+			}
+		}
+	}
+
+	#[test]
+	fn test_isa_conditions() {
+		let pairs = [
+			(Condition::Always, ConditionCode::Always),
+			(Condition::Overflow, ConditionCode::Overflow),
+			(Condition::Carry, ConditionCode::Lower),
+			(Condition::Zero, ConditionCode::Equal),
+			(Condition::Negative, ConditionCode::LessThan),
+			(Condition::NotZero, ConditionCode::NotEqual),
+			(Condition::NotNegative, ConditionCode::GreaterEqual),
+			(Condition::GreaterThan, ConditionCode::GreaterThan),
+		];
+
+		for (cond, code) in pairs {
+			for flags in 0..16 {
+				let psr = Psr(flags);
+				assert_eq!(psr.should_execute(cond), code.evaluate(&psr), "{cond:?} flags {flags:04b}");
+			}
+		}
+	}
+
+	#[test]
+	fn test_compare() {
+		let values = [0, 1, 2, 0x7fffffff, 0x80000000, 0x80000001, 0xfffffffe, 0xffffffff];
+
+		// Conditions after `cmp lhs, rhs` must match the comparison they name
+		for lhs in values {
+			for rhs in values {
+				let res = execute_binop(BinOp::Subcc, lhs, rhs).unwrap();
+				let mut psr = Psr(0);
+				binop_flags(BinOp::Subcc, lhs, rhs, res).apply(&mut psr);
+
+				let (ilhs, irhs) = (lhs as i32, rhs as i32);
+				assert_eq!(ConditionCode::Equal.evaluate(&psr), lhs == rhs);
+				assert_eq!(ConditionCode::NotEqual.evaluate(&psr), lhs != rhs);
+				assert_eq!(ConditionCode::LessThan.evaluate(&psr), ilhs < irhs, "{ilhs} < {irhs}");
+				assert_eq!(ConditionCode::LessEqual.evaluate(&psr), ilhs <= irhs);
+				assert_eq!(ConditionCode::GreaterThan.evaluate(&psr), ilhs > irhs);
+				assert_eq!(ConditionCode::GreaterEqual.evaluate(&psr), ilhs >= irhs);
+				assert_eq!(ConditionCode::Lower.evaluate(&psr), lhs < rhs, "{lhs} < {rhs}");
+				assert_eq!(ConditionCode::LowerSame.evaluate(&psr), lhs <= rhs);
+				assert_eq!(ConditionCode::Higher.evaluate(&psr), lhs > rhs);
+				assert_eq!(ConditionCode::HigherSame.evaluate(&psr), lhs >= rhs);
+				assert_eq!(ConditionCode::Overflow.evaluate(&psr), ilhs.checked_sub(irhs).is_none());
+			}
+		}
+	}
+}
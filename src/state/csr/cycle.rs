@@ -0,0 +1,77 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use super::CsrBlock;
+use crate::state::CoreState;
+
+use bibe_instr::Width;
+
+/// Implementation defined block, placed above the ISA defined CSRs by default
+pub const CYCLE_BASE: u32 = 0x0001_0000;
+pub const CYCLE_SIZE: u32 = 8;
+/// Offset of the low word of the cycle count
+pub const CYCLE_LO_OFFSET: u32 = 0;
+/// Offset of the high word of the cycle count
+pub const CYCLE_HI_OFFSET: u32 = 4;
+/// Low word of the cycle count at the default base
+pub const CYCLE_LO_REG: u32 = CYCLE_BASE + CYCLE_LO_OFFSET;
+/// High word of the cycle count at the default base
+pub const CYCLE_HI_REG: u32 = CYCLE_BASE + CYCLE_HI_OFFSET;
+
+/// Read-only view of the core's cycle counter
+///
+/// The count is kept by the core, it is cleared by a cold reset and keeps counting across
+/// warm resets.
+pub struct CycleBlock {
+	base: u32,
+}
+
+impl CycleBlock {
+	pub fn new() -> CycleBlock {
+		Self::with_base(CYCLE_BASE)
+	}
+
+	/// Place the block at `base` instead of `CYCLE_BASE`
+	pub fn with_base(base: u32) -> CycleBlock {
+		CycleBlock {
+			base,
+		}
+	}
+}
+
+impl CsrBlock for CycleBlock
+{
+	fn read(&self, state: &CoreState, reg: u32, width: Width) -> Option<u32> {
+		if width != Width::Word {
+			return None;
+		}
+
+		match reg.wrapping_sub(self.base) {
+			CYCLE_LO_OFFSET => Some(state.cycles() as u32),
+			CYCLE_HI_OFFSET => Some((state.cycles() >> 32) as u32),
+			_ => None,
+		}
+	}
+
+	fn write(&mut self, _state: &CoreState, _reg: u32, _width: Width, _value: u32) -> Option<()> {
+		None
+	}
+
+	fn reset(&mut self) {
+	}
+
+	fn has_reg(&self, reg: u32) -> bool {
+		let offset = reg.wrapping_sub(self.base);
+		offset == CYCLE_LO_OFFSET || offset == CYCLE_HI_OFFSET
+	}
+
+	fn base_reg(&self) -> u32 {
+		self.base
+	}
+
+	fn size(&self) -> u32 {
+		CYCLE_SIZE
+	}
+
+	fn name(&self) -> &'static str {
+		"cycle"
+	}
+}
@@ -18,14 +18,15 @@ impl DbgOutBlock {
 
 impl CsrBlock for DbgOutBlock
 {
-	fn read(&mut self, _state: &CoreState, _reg: u32, _width: Width) -> Option<u32> {
+	fn read(&self, _state: &CoreState, _reg: u32, _width: Width) -> Option<u32> {
 		None
 	}
 
 	fn write(&mut self, _state: &CoreState, reg: u32, _width: Width, value: u32) -> Option<()> {
 		log::debug!("Dbg write {reg:08x} {value:08x}");
 		if reg == DBG_OUT_CHAR_OUT0_REG {
-			print!("{}", char::from_u32(value).unwrap());
+			// Invalid characters are rejected like any other invalid write
+			print!("{}", char::from_u32(value)?);
 			return Some(())
 		}
 
@@ -51,4 +52,8 @@ impl CsrBlock for DbgOutBlock
 	fn size(&self) -> u32 {
 		DBG_OUT_SIZE
 	}
+
+	fn name(&self) -> &'static str {
+		"dbg_out"
+	}
 }
\ No newline at end of file
@@ -7,6 +7,8 @@ use bibe_instr::csr::regs::*;
 
 pub struct IsrBlock(pub(crate) [u32; ISR_SIZE as usize / 4]);
 
+const BASE_IDX: usize = ((ISR_BASE_REG - ISR_BASE) / 4) as usize;
+
 impl IsrBlock {
 	pub fn new() -> IsrBlock {
 		IsrBlock([0; ISR_SIZE as usize / 4])
@@ -15,7 +17,7 @@ impl IsrBlock {
 
 impl CsrBlock for IsrBlock
 {
-	fn read(&mut self, _state: &CoreState, reg: u32, width: Width) -> Option<u32> {
+	fn read(&self, _state: &CoreState, reg: u32, width: Width) -> Option<u32> {
 		if width != Width::Word {
 			return None;
 		}
@@ -38,6 +40,13 @@ impl CsrBlock for IsrBlock
 		}
 	}
 
+	/// The vector table survives a warm reset, the banked registers don't
+	fn warm_reset(&mut self) {
+		let base = self.0[BASE_IDX];
+		self.reset();
+		self.0[BASE_IDX] = base;
+	}
+
 	fn has_reg(&self, reg: u32) -> bool {
 		if reg < ISR_BASE && reg >= ISR_BASE + ISR_SIZE {
 			return false;
@@ -54,6 +63,10 @@ impl CsrBlock for IsrBlock
 		ISR_SIZE
 	}
 
+	fn name(&self) -> &'static str {
+		"isr"
+	}
+
 	fn as_isr(&self) -> Option<&IsrBlock> {
 		Some(self)
 	}
@@ -61,4 +74,27 @@ impl CsrBlock for IsrBlock
 	fn as_isr_mut(&mut self) -> Option<&mut IsrBlock> {
 		Some(self)
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_warm_reset() {
+		let mut block = IsrBlock::new();
+		let core = CoreState::new();
+		for reg in [ISR_BASE_REG, ISR_ERR1_REG, ISR_R1_REG, ISR_PC_REG] {
+			assert!(block.write(&core, reg, Width::Word, 0x1000 + reg).is_some());
+		}
+
+		block.warm_reset();
+		assert_eq!(block.read(&core, ISR_BASE_REG, Width::Word), Some(0x1000 + ISR_BASE_REG));
+		for reg in [ISR_ERR1_REG, ISR_R1_REG, ISR_PC_REG] {
+			assert_eq!(block.read(&core, reg, Width::Word), Some(0));
+		}
+
+		block.reset();
+		assert_eq!(block.read(&core, ISR_BASE_REG, Width::Word), Some(0));
+	}
+}
@@ -1,13 +1,15 @@
-use bibe_instr::Width;
+use bibe_instr::{
+	LoadStore,
+	Width,
+};
 use bibe_instr::csr::Instruction;
-use bibe_instr::csr::regs::*;
 
 use crate::memory::Memory;
 use crate::target::Target;
 use crate::{
+	Interrupt,
 	Result,
 	state::State,
-	Interrupt,
 };
 
 #[cfg(feature = "std")]
@@ -19,10 +21,12 @@ use std::{
 	boxed::Box,
 };
 
+mod cycle;
 mod dbg_out;
 mod isr;
 mod psr;
 
+pub use cycle::*;
 pub use dbg_out::*;
 pub use isr::*;
 pub use psr::*;
@@ -31,31 +35,58 @@ use super::CoreState;
 
 pub trait CsrBlock
 {
-	fn read(&mut self, state: &CoreState, reg: u32, width: Width) -> Option<u32>;
+	/// Reads can't change the block, blocks that need read side effects use interior mutability
+	fn read(&self, state: &CoreState, reg: u32, width: Width) -> Option<u32>;
 	fn write(&mut self, state: &CoreState, reg: u32, width: Width, value: u32) -> Option<()>;
+	/// Restore the block's reset values, called on cold reset
 	fn reset(&mut self);
+	/// Called on warm reset, blocks that keep state across a reset pin assertion override this
+	fn warm_reset(&mut self) {
+		self.reset();
+	}
 
 	fn has_reg(&self, reg: u32) -> bool;
 	fn base_reg(&self) -> u32;
 	fn size(&self) -> u32;
 
+	/// Short name shown by tools
+	fn name(&self) -> &'static str {
+		"unknown"
+	}
+
 	// Downcasting helpers, these should only be added for ISA defined blocks
 	fn as_isr(&self) -> Option<&IsrBlock> { None }
 	fn as_isr_mut(&mut self) -> Option<&mut IsrBlock> { None }
+	fn as_psr(&self) -> Option<&PsrBlock> { None }
+	fn as_psr_mut(&mut self) -> Option<&mut PsrBlock> { None }
 }
 
+/// The CSR blocks of a state
+///
+/// The ISA defined blocks have fixed positions, the PSR block is first and the ISR block second.
 pub trait CsrCollection
 {
 	fn len(&self) -> usize;
 
+	/// Whether the PSR and ISR blocks are where `get_psr` and `get_isr` expect them
+	///
+	/// Collections that keep the ISA defined blocks elsewhere override this along with the getters.
+	fn is_valid(&self) -> bool {
+		self.len() > ISR_IDX
+			&& self.index(PSR_IDX).as_psr().is_some()
+			&& self.index(ISR_IDX).as_isr().is_some()
+	}
+
 	fn index(&self, i: usize) -> &dyn CsrBlock;
 	fn index_mut(&mut self, i: usize) -> &mut dyn CsrBlock;
 
 	fn get_isr(&self) -> &IsrBlock;
 	fn get_isr_mut(&mut self) -> &mut IsrBlock;
+	fn get_psr(&self) -> &PsrBlock;
+	fn get_psr_mut(&mut self) -> &mut PsrBlock;
 }
 
-#[cfg(feature = "std")]
+const PSR_IDX: usize = 0;
 const ISR_IDX: usize = 1;
 
 #[cfg(feature = "std")]
@@ -79,6 +110,14 @@ impl CsrCollection for Vec<Box<dyn CsrBlock>> {
 	fn get_isr_mut(&mut self) -> &mut IsrBlock {
 		self[ISR_IDX].as_mut().as_isr_mut().unwrap()
 	}
+
+	fn get_psr(&self) -> &PsrBlock {
+		self[PSR_IDX].as_ref().as_psr().unwrap()
+	}
+
+	fn get_psr_mut(&mut self) -> &mut PsrBlock {
+		self[PSR_IDX].as_mut().as_psr_mut().unwrap()
+	}
 }
 
 pub(super) fn execute<T, M, C>(s: &mut State<T, M, C>, instr: &Instruction) -> Result<()>
@@ -89,16 +128,15 @@ where
 {
 	let width = instr.op.width;
 
+	// Accesses to registers that don't exist are invalid instructions
 	if  instr.op.is_load() {
-		let value = s.read_csr(instr.imm, width).unwrap();
-			s.core.borrow_mut().write_reg(instr.reg, value);
+		let value = s.read_csr(instr.imm, width).ok_or_else(Interrupt::opcode)?;
+		s.core_mut().write_reg(instr.reg, value);
+		s.csr_accessed(LoadStore::Load, instr.imm);
 	} else {
-		//TODO: remove this hack
-		if instr.imm == ISR_ENTER_REG {
-			return Err(Interrupt::swi());
-		}
-		let val = s.core.borrow().read_reg(instr.reg);
-		s.write_csr(instr.imm, val, width).unwrap();
+		let val = s.core().read_reg(instr.reg);
+		s.write_csr(instr.imm, val, width).ok_or_else(Interrupt::opcode)?;
+		s.csr_accessed(LoadStore::Store, instr.imm);
 	}
 
 	Ok(())
@@ -22,11 +22,15 @@ impl CsrBlock for PsrBlock
 		PSR_SIZE
 	}
 
+	fn name(&self) -> &'static str {
+		"psr"
+	}
+
 	fn has_reg(&self, reg: u32) -> bool {
 		reg == PSR_PSR0_REG
 	}
 
-	fn read(&mut self, _state: &CoreState, reg: u32, width: Width) -> Option<u32> {
+	fn read(&self, _state: &CoreState, reg: u32, width: Width) -> Option<u32> {
 		if width != Width::Word {
 			return None;
 		}
@@ -54,4 +58,12 @@ impl CsrBlock for PsrBlock
 	fn reset(&mut self) {
 		self.0 = 0;
 	}
+
+	fn as_psr(&self) -> Option<&PsrBlock> {
+		Some(self)
+	}
+
+	fn as_psr_mut(&mut self) -> Option<&mut PsrBlock> {
+		Some(self)
+	}
 }
\ No newline at end of file
@@ -0,0 +1,109 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+/// Number of entries, must be a power of two
+const ENTRIES: usize = 256;
+
+/// Direct-mapped cache of decoded instructions, keyed by PC
+///
+/// Entries are only valid as long as the code bytes they were decoded from are unchanged,
+/// stores must be reported through `invalidate`.
+pub(super) struct DecodeCache<I> {
+	entries: [Option<(u32, I)>; ENTRIES],
+	enabled: bool,
+}
+
+impl<I> DecodeCache<I> {
+	pub fn new() -> DecodeCache<I> {
+		DecodeCache {
+			entries: core::array::from_fn(|_| None),
+			enabled: true,
+		}
+	}
+
+	fn index(pc: u32) -> usize {
+		(pc >> 2) as usize & (ENTRIES - 1)
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	pub fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+		self.clear();
+	}
+
+	pub fn get(&self, pc: u32) -> Option<&I> {
+		match &self.entries[Self::index(pc)] {
+			Some((tag, instr)) if *tag == pc => Some(instr),
+			_ => None,
+		}
+	}
+
+	pub fn insert(&mut self, pc: u32, instr: I) {
+		if self.enabled {
+			self.entries[Self::index(pc)] = Some((pc, instr));
+		}
+	}
+
+	/// Drop entries decoded from any of the `len` bytes starting at `addr`
+	pub fn invalidate(&mut self, addr: u32, len: usize) {
+		if len == 0 {
+			return;
+		}
+
+		if len >= ENTRIES * 4 {
+			self.clear();
+			return;
+		}
+
+		let first = addr & !3;
+		let last = addr.wrapping_add(len as u32 - 1) & !3;
+		let mut word = first;
+		loop {
+			let entry = &mut self.entries[Self::index(word)];
+			if matches!(entry, Some((tag, _)) if *tag == word) {
+				*entry = None;
+			}
+
+			if word == last {
+				break;
+			}
+			word = word.wrapping_add(4);
+		}
+	}
+
+	pub fn clear(&mut self) {
+		for entry in &mut self.entries {
+			*entry = None;
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_invalidate() {
+		let mut cache = DecodeCache::new();
+		cache.insert(0x100, 1u32);
+		cache.insert(0x104, 2);
+
+		// Entries are tagged with the full pc
+		assert_eq!(cache.get(0x100), Some(&1));
+		assert!(cache.get(0x100 + 4 * ENTRIES as u32).is_none());
+
+		// Unaligned store that touches both words
+		cache.invalidate(0x103, 2);
+		assert!(cache.get(0x100).is_none());
+		assert!(cache.get(0x104).is_none());
+
+		cache.insert(0x100, 3);
+		cache.invalidate(0x100 + 4 * ENTRIES as u32, 4);
+		assert!(cache.get(0x100).is_some());
+
+		cache.set_enabled(false);
+		cache.insert(0x100, 3);
+		assert!(cache.get(0x100).is_none());
+	}
+}
@@ -1,15 +1,33 @@
-use crate::{memory::Memory, target::Target};
-use bibe_instr::jump::Instruction;
+use crate::{abi::LINK_REGISTER, memory::Memory, target::Target};
+use bibe_instr::{
+	jump::Instruction,
+	Register,
+};
 
-use super::csr::CsrCollection;
+use super::{
+	csr::CsrCollection,
+	Psr,
+};
 
+/// PC-relative branch, the displacement is in instructions
+///
+/// Register-indirect jumps don't need a separate format, they are any RRR or RRI
+/// instruction with `pc` as the destination.
 pub(super) fn execute<T, M, C>(s: &mut super::State<T, M, C>, i: &Instruction) -> crate::Result<()>
 where
 	T: Target,
 	M: Memory,
 	C: CsrCollection,
 {
-	let mut core = s.core.borrow_mut();
-	core.write_pc((i.imm as u32) << 2);
+	if !Psr(s.read_psr()).should_execute(i.cond) {
+		return Ok(());
+	}
+
+	let pc = s.core().read_pc();
+	if i.link {
+		s.core_mut().write_reg(Register::new(LINK_REGISTER).unwrap(), pc.wrapping_add(4));
+	}
+
+	s.core_mut().write_pc(pc.wrapping_add((i.imm as u32) << 2));
 	Ok(())
-}
\ No newline at end of file
+}
@@ -13,7 +13,7 @@ use crate::{
 };
 
 use super::{
-	csr::CsrCollection, shift, State
+	csr::CsrCollection, shift, Psr, State
 };
 
 fn execute_rr<T, M, C>(s: &mut State<T, M, C>, instr: &rr::Instruction) -> Result<()>
@@ -22,17 +22,21 @@ where
 	M: MemTrait,
 	C: CsrCollection,
 {
-	let rs = s.core.borrow().read_reg(instr.rs);
-	let rq = s.core.borrow().read_reg(instr.rq);
+	if !Psr(s.read_psr()).should_execute(instr.cond) {
+		return Ok(());
+	}
+
+	let rs = s.core().read_reg(instr.rs);
+	let rq = s.core().read_reg(instr.rq);
 	let addr = rs + shift(&instr.shift, rq);
 	match instr.op.op {
 		LoadStore::Load => {
-			let value = s.read(addr, instr.op.width)?;
-			s.core.borrow_mut().write_reg(instr.rd, value);
+			let value = s.load(addr, instr.op.width)?;
+			s.core_mut().write_reg(instr.rd, value);
 		},
 		LoadStore::Store => {
-			let value = s.core.borrow_mut().read_reg(instr.rd);
-			s.write(addr, instr.op.width, value)?;
+			let value = s.core_mut().read_reg(instr.rd);
+			s.store(addr, instr.op.width, value)?;
 		},
 	}
 	Ok(())
@@ -44,16 +48,20 @@ where
 	M: MemTrait,
 	C: CsrCollection,
 {
-	let rs = s.core.borrow().read_reg(instr.rs);
+	if !Psr(s.read_psr()).should_execute(instr.cond) {
+		return Ok(());
+	}
+
+	let rs = s.core().read_reg(instr.rs);
 	let addr = rs.wrapping_add(instr.imm as u32);
 	match instr.op.op {
 		LoadStore::Load => {
-			let value = s.read(addr, instr.op.width)?;
-			s.core.borrow_mut().write_reg(instr.rd, value);
+			let value = s.load(addr, instr.op.width)?;
+			s.core_mut().write_reg(instr.rd, value);
 		},
 		LoadStore::Store => {
-			let value = s.core.borrow().read_reg(instr.rd);
-			s.write(addr, instr.op.width, value)?;
+			let value = s.core().read_reg(instr.rd);
+			s.store(addr, instr.op.width, value)?;
 		}
 	}
 	Ok(())
@@ -1,7 +1,4 @@
-use core::{
-	cell::RefCell,
-	fmt,
-};
+use core::fmt;
 
 use bibe_instr::{
 	Encode,
@@ -10,28 +7,80 @@ use bibe_instr::{
 	Shift,
 	ShiftKind,
 	Width, Condition,
+	LoadStore,
 };
 use bibe_instr::csr::regs::*;
 
 use crate::{
-	memory::Memory, 
+	abi::RegisterName,
+	loader::{
+		self,
+		Format,
+		LoadError,
+	},
+	memory::{
+		width_bytes,
+		Memory,
+	},
 	Interrupt, 
 	InterruptKind,
 	Result,
-	target::Target,
+	target::{
+		InstructionClass,
+		Target,
+	},
+	trace::Disasm,
 };
 
 use bitfield::bitfield;
 use log::debug;
 
 mod memory;
+mod block;
+mod cond;
 pub mod csr;
+mod icache;
 mod rrr;
 mod rri;
 mod jump;
+pub mod observer;
+#[cfg(test)]
+mod reference;
+pub mod semihost;
+mod swi;
 mod util;
 
+#[cfg(feature = "std")]
+use self::block::BlockCache;
 use self::csr::CsrCollection;
+use self::icache::DecodeCache;
+#[cfg(feature = "std")]
+use self::semihost::Semihost;
+use self::observer::{
+	Access,
+	CsrAccess,
+	Observer,
+	Retired,
+};
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "std")]
+use std::{
+	boxed::Box,
+	vec::Vec,
+};
+#[cfg(feature = "std")]
+use crate::{
+	symbols::{
+		Described,
+		SymbolMap,
+	},
+	trace::Symbols,
+};
+pub use self::cond::{
+	condition,
+	ConditionCode,
+};
 
 bitfield! {
 	pub struct Psr(u32);
@@ -53,22 +102,16 @@ impl Psr {
 	}
 
 	pub fn should_execute(&self, cond: Condition) -> bool {
-		match cond {
-			Condition::Always => true,
-			Condition::Overflow => self.v() == 1,
-			Condition::Carry => self.c() == 1,
-			Condition::Zero => self.z() == 1,
-			Condition::Negative => self.n() == 1,
-			Condition::NotZero => self.z() == 0,
-			Condition::NotNegative => self.n() == 0,
-			Condition::GreaterThan => self.n() == 0 && self.z() == 0,
-		}
+		ConditionCode::from(cond).evaluate(self)
 	}
 }
 
+#[derive(Clone, Debug)]
 pub struct CoreState {
 	regs: [u32; 31],
 	pc_touched: bool,
+	cycles: u64,
+	retired: u64,
 }
 
 impl CoreState {
@@ -76,9 +119,21 @@ impl CoreState {
 		CoreState {
 			regs: [0; 31],
 			pc_touched: false,
+			cycles: 0,
+			retired: 0,
 		}
 	}
 
+	/// Cycles elapsed since the last cold reset
+	pub fn cycles(&self) -> u64 {
+		self.cycles
+	}
+
+	/// Instructions executed since the last cold reset
+	pub fn retired(&self) -> u64 {
+		self.retired
+	}
+
 	pub fn read_reg(&self, r: Register) -> u32 {
 		if r.as_u8() == 0 {
 			0
@@ -114,25 +169,106 @@ impl CoreState {
 	}
 
 	pub fn reset(&mut self) {
+		self.warm_reset();
+		self.cycles = 0;
+		self.retired = 0;
+	}
+
+	/// Clear the registers, the cycle and retired counters keep counting across a warm reset
+	pub fn warm_reset(&mut self) {
 		for reg in &mut self.regs {
 			*reg = 0;
 		}
 	}
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ResetKind {
+	/// Power-on reset, every CSR block returns to its reset values
+	Cold,
+	/// Reset pin or reset interrupt, CSR blocks may preserve state, see `CsrBlock::warm_reset`
+	Warm,
+}
+
+/// Implementation defined values loaded on reset
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResetConfig {
+	/// Address execution starts from
+	pub reset_vector: u32,
+	/// Initial value of `ISR_BASE_REG`
+	pub isr_base: u32,
+}
+
+/// Machine state when an interrupt was taken
+#[derive(Clone, Debug)]
+pub struct FaultRecord {
+	pub kind: InterruptKind,
+	pub err1: u32,
+	pub err2: u32,
+	pub psr: u32,
+	/// Registers of the bank that was active when the interrupt was raised
+	pub core: CoreState,
+}
+
+/// Fault chain that led to a lockup
+#[derive(Clone, Debug)]
+pub struct Lockup {
+	/// Interrupt that entered the ISR, `None` if interrupt mode was entered by writing the PSR
+	pub original: Option<FaultRecord>,
+	pub double_fault: FaultRecord,
+	/// Fault raised by the double fault handler
+	pub fault: FaultRecord,
+}
+
+/// How `State::step` executes instructions
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Engine {
+	/// Fetch, decode and execute a single instruction
+	Interpreter,
+	/// Execute a basic block translated to threaded code
+	#[cfg(feature = "std")]
+	Block,
+}
+
+/// Reason the state stopped executing instructions
+#[derive(Clone, Debug)]
+pub enum StopReason {
+	/// Fault while handling a double fault
+	Lockup(Lockup),
+	/// The cycle count reached the budget set with `set_cycle_budget`
+	CycleBudget,
+	/// The guest exited through semihosting with the given status
+	Exit(i32),
+}
+
 pub struct State<T, M, C>
 where
 	T: Target,
 	M: Memory,
 	C: CsrCollection,
 {
-	pub core: RefCell<CoreState>,
+	core: CoreState,
 	memory: Option<M>,
 	target: T,
 
-	csr_blocks: RefCell<C>,
-
-	double_fault: bool,
+	csr_blocks: C,
+
+	original_fault: Option<FaultRecord>,
+	double_fault: Option<FaultRecord>,
+	stop: Option<StopReason>,
+	reset_config: ResetConfig,
+	reset_pending: bool,
+	cycle_budget: Option<u64>,
+	decode_cache: DecodeCache<Instruction>,
+	engine: Engine,
+	#[cfg(feature = "std")]
+	blocks: BlockCache<T, M, C>,
+	#[cfg(feature = "std")]
+	semihost: Option<Semihost>,
+	#[cfg(feature = "std")]
+	symbols: Option<SymbolMap>,
+	#[cfg(feature = "std")]
+	observers: Vec<Box<dyn Observer>>,
 }
 
 const PC: usize = 31;
@@ -142,14 +278,16 @@ pub fn shift(s: &Shift, value: u32) -> u32 {
 		kind,
 		shift: amount,
 	} = s;
+	let amount = *amount as u32;
 
+	// Arithmetic and logical left shifts produce the same value
 	match kind {
-		ShiftKind::Shl => value << amount,
-		ShiftKind::Shr => value >> amount,
-		ShiftKind::Asl => ((value as i32) << amount) as u32,
-		ShiftKind::Asr => ((value as i32) >> amount) as u32,
-		ShiftKind::Rol => value.rotate_left(*amount as u32),
-		ShiftKind::Ror => value.rotate_right(*amount as u32)
+		ShiftKind::Shl
+		| ShiftKind::Asl => util::shl(value, amount),
+		ShiftKind::Shr => util::shr(value, amount),
+		ShiftKind::Asr => util::asr(value, amount),
+		ShiftKind::Rol => value.rotate_left(amount),
+		ShiftKind::Ror => value.rotate_right(amount),
 	}
 }
 
@@ -160,31 +298,172 @@ where
 	C: CsrCollection,
 {
 	pub fn new(target: T, memory: Option<M>, csr_blocks: C) -> State<T, M, C> {
-		State {
-			core: RefCell::new(CoreState::new()),
+		Self::with_reset_config(target, memory, csr_blocks, ResetConfig::default())
+	}
+
+	/// Create a state that has been cold reset using `config`
+	///
+	/// # Panics
+	/// If `csr_blocks` doesn't start with the PSR and ISR blocks, see [`CsrCollection::is_valid`]
+	pub fn with_reset_config(target: T, memory: Option<M>, csr_blocks: C, config: ResetConfig) -> State<T, M, C> {
+		assert!(csr_blocks.is_valid(), "CSR blocks must start with a PsrBlock followed by an IsrBlock");
+
+		let mut state = State {
+			core: CoreState::new(),
 			memory,
 			target,
-			csr_blocks: RefCell::new(csr_blocks),
-			double_fault: false,
-		}
+			csr_blocks,
+			original_fault: None,
+			double_fault: None,
+			stop: None,
+			reset_config: config,
+			reset_pending: false,
+			cycle_budget: None,
+			decode_cache: DecodeCache::new(),
+			engine: Engine::Interpreter,
+			#[cfg(feature = "std")]
+			blocks: BlockCache::new(),
+			#[cfg(feature = "std")]
+			semihost: None,
+			#[cfg(feature = "std")]
+			symbols: None,
+			#[cfg(feature = "std")]
+			observers: Vec::new(),
+		};
+
+		state.reset();
+		state
+	}
+
+	pub fn reset_config(&self) -> ResetConfig {
+		self.reset_config
+	}
+
+	/// Takes effect on the next reset
+	pub fn set_reset_config(&mut self, config: ResetConfig) {
+		self.reset_config = config;
+	}
+
+	pub fn memory(&self) -> Option<&M> {
+		self.memory.as_ref()
 	}
 
 	pub fn attach_memory(&mut self, memory: Option<M>) {
-		self.memory = memory
+		self.memory = memory;
+		self.flush_decode_cache();
+	}
+
+	/// Cache decoded instructions by pc, enabled by default
+	///
+	/// Stores through `State` invalidate cached instructions, memory changed by other means
+	/// must be followed by a call to `flush_decode_cache`.
+	/// This only applies to the interpreter, see `Engine`.
+	pub fn set_decode_cache(&mut self, enabled: bool) {
+		self.decode_cache.set_enabled(enabled);
+	}
+
+	pub fn decode_cache_enabled(&self) -> bool {
+		self.decode_cache.is_enabled()
+	}
+
+	/// Drop all cached instructions and translated blocks
+	pub fn flush_decode_cache(&mut self) {
+		self.decode_cache.clear();
+		#[cfg(feature = "std")]
+		self.blocks.clear();
+	}
+
+	/// Host services for the guest, see `semihost`
+	#[cfg(feature = "std")]
+	pub fn set_semihost(&mut self, semihost: Option<Semihost>) {
+		self.semihost = semihost;
+	}
+
+	#[cfg(feature = "std")]
+	pub fn semihost(&self) -> Option<&Semihost> {
+		self.semihost.as_ref()
+	}
+
+	/// Symbols used to describe addresses in diagnostics
+	#[cfg(feature = "std")]
+	pub fn set_symbols(&mut self, symbols: Option<SymbolMap>) {
+		self.symbols = symbols;
+	}
+
+	#[cfg(feature = "std")]
+	pub fn symbols(&self) -> Option<&SymbolMap> {
+		self.symbols.as_ref()
+	}
+
+	/// Notify `observer` of execution events, keep an `Rc<RefCell<_>>` clone to read it back
+	#[cfg(feature = "std")]
+	pub fn add_observer(&mut self, observer: Box<dyn Observer>) {
+		self.observers.push(observer);
+	}
+
+	#[cfg(feature = "std")]
+	pub fn clear_observers(&mut self) {
+		self.observers.clear();
+	}
+
+	/// Displays `addr` with the symbol containing it, if any
+	#[cfg(feature = "std")]
+	pub fn describe(&self, addr: u32) -> Described<'_> {
+		Described {
+			addr,
+			symbol: self.symbols.as_ref().and_then(|symbols| symbols.lookup(addr)),
+		}
+	}
+
+	pub fn engine(&self) -> Engine {
+		self.engine
+	}
+
+	pub fn set_engine(&mut self, engine: Engine) {
+		self.engine = engine;
+	}
+
+	/// Stores to code invalidate cached instructions and blocks
+	fn invalidate_code(&mut self, addr: u32, len: usize) {
+		self.decode_cache.invalidate(addr, len);
+		#[cfg(feature = "std")]
+		self.blocks.invalidate(addr, len);
+	}
+
+	/// Load an image into memory, execution starts at the image's entry address if it has one
+	pub fn load_image(&mut self, format: Format, src: &str) -> loader::Result<()> {
+		let memory = self.memory.as_mut().ok_or(LoadError::NoMemory)?;
+
+		let entry = loader::load(format, src, memory);
+		self.flush_decode_cache();
+
+		if let Some(entry) = entry? {
+			self.core.write_pc(entry);
+		}
+
+		Ok(())
+	}
+
+	pub fn core(&self) -> &CoreState {
+		&self.core
+	}
+
+	pub fn core_mut(&mut self) -> &mut CoreState {
+		&mut self.core
 	}
 
 	pub fn read_psr(&self) -> u32 {
-		self.read_csr(PSR_PSR0_REG, Width::Word).unwrap()
+		self.csr_blocks.get_psr().0
 	}
 
 	pub fn write_psr(&mut self, value: u32) {
-		self.write_csr(PSR_PSR0_REG, value, Width::Word).unwrap()
+		self.csr_blocks.get_psr_mut().0 = value;
 	}
 
 	pub fn read_csr(&self, reg: u32, width: Width) -> Option<u32> {
 		let mut index = 0;
 		let mut found = false;
-		let mut blocks = self.csr_blocks.borrow_mut();
+		let blocks = &self.csr_blocks;
 
 		for i in 0..blocks.len() {
 			let block = blocks.index(i);
@@ -197,7 +476,7 @@ where
 		}
 
 		if found {
-			return blocks.index_mut(index).read(&self.core.borrow(), reg, width);
+			return blocks.index(index).read(&self.core, reg, width);
 		}
 
 		None
@@ -206,7 +485,7 @@ where
 	pub fn write_csr(&mut self, reg: u32, value: u32, width: Width) -> Option<()> {
 		let mut index = 0;
 		let mut found = false;
-		let mut blocks = self.csr_blocks.borrow_mut();
+		let blocks = &mut self.csr_blocks;
 
 		debug!("CSR write: reg: {reg:#x}, value: {value:#x}, width: {width:?}");
 		for i in 0..blocks.len() {
@@ -225,31 +504,68 @@ where
 
 		if found {
 			debug!("CSR block index {index}");
-			return blocks.index_mut(index).write(&self.core.borrow_mut(), reg, width, value);
+			return blocks.index_mut(index).write(&self.core, reg, width, value);
 		}
 
 		debug!("Invalid CSR write");
 		None
 	}
 
+	pub fn csr_blocks(&self) -> &C {
+		&self.csr_blocks
+	}
+
 	pub fn target<'a>(&'a self) -> &'a T {
 		&self.target
 	}
 
+	/// Cold reset
 	pub fn reset(&mut self) {
-		self.core.borrow_mut().reset();
-		for i in 0..self.csr_blocks.borrow().len() {
-			self.csr_blocks.borrow_mut().index_mut(i).reset();
+		self.reset_with(ResetKind::Cold);
+	}
+
+	/// Reset the core and CSR blocks, execution resumes from the reset vector
+	///
+	/// Registers and PSR are zeroed. A cold reset loads `ISR_BASE_REG` from the reset config
+	/// and clears the cycle counter, both survive a warm reset. Memory is untouched.
+	pub fn reset_with(&mut self, kind: ResetKind) {
+		match kind {
+			ResetKind::Cold => self.core.reset(),
+			ResetKind::Warm => self.core.warm_reset(),
 		}
 
-		self.double_fault = false;
-		debug!("Reset");
+		for i in 0..self.csr_blocks.len() {
+			match kind {
+				ResetKind::Cold => self.csr_blocks.index_mut(i).reset(),
+				ResetKind::Warm => self.csr_blocks.index_mut(i).warm_reset(),
+			}
+		}
+
+		let ResetConfig {
+			reset_vector,
+			isr_base,
+		} = self.reset_config;
+
+		if kind == ResetKind::Cold {
+			self.write_csr(ISR_BASE_REG, isr_base, Width::Word);
+		}
+		self.core.write_pc(reset_vector);
+
+		self.original_fault = None;
+		self.double_fault = None;
+		self.stop = None;
+		self.reset_pending = false;
+		debug!("{kind:?} reset, pc: {reset_vector:08x}");
+	}
+
+	/// Assert the reset pin, a warm reset is performed before the next instruction
+	pub fn assert_reset(&mut self) {
+		self.reset_pending = true;
 	}
 
 	fn swap_interrupt_banks(&mut self) {
-		let mut core = self.core.borrow_mut();
-		let mut csr_blocks = self.csr_blocks.borrow_mut();
-		let isr = csr_blocks.get_isr_mut();
+		let core = &mut self.core;
+		let isr = self.csr_blocks.get_isr_mut();
 		let mut tmp = [0u32; 31];
 
 		let isr_pc_idx = ((ISR_PC_REG - ISR_BASE) / 4) as usize;
@@ -262,48 +578,63 @@ where
 	}
 
 	pub fn handle_interrupt(&mut self, e: &Interrupt) {
+		if e.kind != InterruptKind::IsrExit {
+			self.notify(|observer| observer.interrupt(e));
+		}
+
 		let mut psr = Psr(self.read_psr());
 
-		if psr.interrupt_mode() == 1 {
-			if e.kind == InterruptKind::IsrExit {
-				self.swap_interrupt_banks();
-				
-				self.write_csr(ISR_ERR1_REG, 0, Width::Word);
-				self.write_csr(ISR_ERR2_REG, 0, Width::Word);
+		if e.kind == InterruptKind::Reset {
+			self.reset_with(ResetKind::Warm);
+		} else if e.kind == InterruptKind::IsrExit {
+			if psr.interrupt_mode() == 0 {
+				// Nothing to return from
+				debug!("ISR exit outside of an interrupt");
+				self.handle_interrupt(&Interrupt::opcode());
+				return;
+			}
 
-				psr.set_exception_enabled(1);
-				psr.set_interrupt_mode(0);
-				self.write_psr(psr.0);
+			self.swap_interrupt_banks();
+
+			self.write_csr(ISR_ERR1_REG, 0, Width::Word);
+			self.write_csr(ISR_ERR2_REG, 0, Width::Word);
 
-				debug!("ISR exit sp: {:08x}, pc: {:08x}", self.core.borrow().read_sp(), self.core.borrow().read_pc());
+			psr.set_exception_enabled(1);
+			psr.set_interrupt_mode(0);
+			self.write_psr(psr.0);
+			self.original_fault = None;
+			self.double_fault = None;
+
+			debug!("ISR exit sp: {:08x}, pc: {:08x}", self.core.read_sp(), self.core.read_pc());
+		} else if psr.interrupt_mode() == 1 {
+			// Interrupt while handling an interrupt
+			// NMIs are always processed, trigger a double fault if we haven't already
+			debug!("Interrupt {:?} while already handling interrupt", e);
+			let index = if e.kind == InterruptKind::Nmi {
+				InterruptKind::Nmi.to_index().unwrap()
+			} else if self.double_fault.is_none() {
+				self.double_fault = Some(self.fault_record(e));
+				InterruptKind::DoubleFault.to_index().unwrap()
 			} else {
-				// Interrupt while handling an interrupt
-				// NMIs are always processed, trigger a double fault if we haven't already
-				let mut index = 0;
-				
-				if e.kind == InterruptKind::Nmi {
-					index = InterruptKind::Nmi.to_index().unwrap();
-				} else {
-					if !self.double_fault {
-						self.double_fault = true;
-						index = InterruptKind::DoubleFault.to_index().unwrap();
-					}
-				}
-
-				let handler = self.read_csr(ISR_BASE_REG, Width::Word).unwrap() + 4 * index;
-				self.core.borrow_mut().write_reg(Register::pc(), handler);
-				debug!("Interrupt {:?} while already handling interrupt", e);
-				if index == 0 {
-					self.reset();
-				}
-			}
-		}
-		else {
-			let old_sp = self.core.borrow().read_sp();
-			let old_pc = self.core.borrow().read_pc();
+				// Nothing left to handle a fault in the double fault handler
+				self.lockup(e);
+				return;
+			};
+
+			let handler = self.read_csr(ISR_BASE_REG, Width::Word).unwrap() + 4 * index;
+			self.core.write_reg(Register::pc(), handler);
+		} else {
+			let old_sp = self.core.read_sp();
+			let old_pc = self.core.read_pc();
+			self.original_fault = Some(self.fault_record(e));
 
 			self.swap_interrupt_banks();
 
+			// Faults return to the faulting instruction, SWIs to the one after
+			if e.kind == InterruptKind::Swi {
+				self.write_csr(ISR_PC_REG, old_pc.wrapping_add(4), Width::Word);
+			}
+
 			psr.set_exception_enabled(0);
 			psr.set_interrupt_mode(1);
 			self.write_psr(psr.0);
@@ -313,41 +644,218 @@ where
 
 			let index: u32 = e.kind.to_index().unwrap();
 			let handler = self.read_csr(ISR_BASE_REG, Width::Word).unwrap() + 4 * index;
-			self.core.borrow_mut().write_reg(Register::pc(), handler);
+			self.core.write_reg(Register::pc(), handler);
 
-			debug!("Interrupt {:?} old_sp: {:08x}, old_pc: {:08x} sp: {:08x}, pc: {:08x}", e, old_sp, old_pc, self.core.borrow().read_sp(), self.core.borrow().read_pc());
+			debug!("Interrupt {:?} old_sp: {:08x}, old_pc: {:08x} sp: {:08x}, pc: {:08x}", e, old_sp, old_pc, self.core.read_sp(), self.core.read_pc());
+			#[cfg(feature = "std")]
+			debug!("Interrupt raised at {}, handler {}", self.describe(old_pc), self.describe(handler));
+		}
+	}
+
+	fn fault_record(&self, e: &Interrupt) -> FaultRecord {
+		FaultRecord {
+			kind: e.kind,
+			err1: e.err1,
+			err2: e.err2,
+			psr: self.read_psr(),
+			core: self.core.clone(),
+		}
+	}
+
+	fn lockup(&mut self, e: &Interrupt) {
+		let lockup = Lockup {
+			original: self.original_fault.clone(),
+			double_fault: self.double_fault.clone().unwrap(),
+			fault: self.fault_record(e),
+		};
+
+		debug!("Lockup {:?}", lockup);
+		self.stop = Some(StopReason::Lockup(lockup));
+	}
+
+	/// Reason execution stopped, execution can be resumed with a reset
+	pub fn stop_reason(&self) -> Option<&StopReason> {
+		self.stop.as_ref()
+	}
+
+	pub fn is_stopped(&self) -> bool {
+		self.stop.is_some()
+	}
+
+	pub fn cycles(&self) -> u64 {
+		self.core.cycles()
+	}
+
+	pub fn retired(&self) -> u64 {
+		self.core.retired()
+	}
+
+	/// Stop once the cycle count reaches `budget`, changing the budget resumes a state stopped by it
+	pub fn set_cycle_budget(&mut self, budget: Option<u64>) {
+		self.cycle_budget = budget;
+		if matches!(self.stop, Some(StopReason::CycleBudget)) {
+			self.stop = None;
+		}
+		self.check_cycle_budget();
+	}
+
+	fn add_cycles(&mut self, cycles: u32) {
+		self.core.cycles += cycles as u64;
+	}
+
+	fn check_cycle_budget(&mut self) {
+		if self.stop.is_none() && self.cycle_budget.map_or(false, |budget| self.cycles() >= budget) {
+			debug!("Cycle budget reached at {}", self.cycles());
+			self.stop = Some(StopReason::CycleBudget);
+		}
+	}
+
+	/// Data read by an instruction, includes the memory's wait states in the cycle count
+	pub(super) fn load(&mut self, addr: u32, width: Width) -> Result<u32> {
+		let value = self.read(addr, width)?;
+		self.add_cycles(self.wait_states(addr, width));
+		self.accessed(LoadStore::Load, addr, width);
+		Ok(value)
+	}
+
+	/// Data write by an instruction, includes the memory's wait states in the cycle count
+	pub(super) fn store(&mut self, addr: u32, width: Width, value: u32) -> Result<()> {
+		self.write(addr, width, value)?;
+		self.add_cycles(self.wait_states(addr, width));
+		self.accessed(LoadStore::Store, addr, width);
+		Ok(())
+	}
+
+	fn accessed(&mut self, op: LoadStore, addr: u32, width: Width) {
+		if self.is_observed() {
+			let access = Access {
+				op,
+				addr,
+				width,
+			};
+			self.notify(|observer| observer.memory_access(&access));
 		}
 	}
 
 	pub fn execute(&mut self, instr: &Instruction) -> Result<()>{
-		debug!("Executing {:08x} {:?}", instr.encode(), instr);
-		self.core.borrow_mut().pc_touched = false;
+		self.execute_from(instr, self.cycles())
+	}
+
+	/// Execute `instr`, its cycles are counted from `start`
+	fn execute_from(&mut self, instr: &Instruction, start: u64) -> Result<()> {
+		debug!("Executing {:08x} {}", instr.encode(), Disasm(instr));
+		self.core.pc_touched = false;
+		let pc = self.core.read_pc();
+		let passed = self.condition_passed(instr);
 
 		let res = match instr {
 			Instruction::Rrr(i) => rrr::execute(self, i),
 			Instruction::Rri(i) => rri::execute(self, i),
 			Instruction::Memory(i) => memory::execute(self, i),
+			Instruction::Csr(i) if swi::is_swi(i) => swi::execute(self, i),
+			#[cfg(feature = "std")]
+			Instruction::Csr(i) if semihost::is_call(i) => semihost::execute(self, i),
 			Instruction::Csr(i) => csr::execute(self, i),
 			Instruction::Jump(i) => jump::execute(self, i),
-			_ => panic!("Unsupported instruction type")
+			// Formats without an implementation are reported to the guest
+			_ => {
+				debug!("Unimplemented instruction format");
+				Err(Interrupt::opcode())
+			},
 		};
 
 		if res.is_err() {
 			return res;
 		}
 
+		self.retire(pc, instr, InstructionClass::of(instr), start, passed);
+		Ok(())
+	}
+
+	/// Whether the condition of `instr` holds, only evaluated when something observes it
+	fn condition_passed(&self, instr: &Instruction) -> bool {
+		!self.is_observed() || condition(instr).map_or(true, |cond| Psr(self.read_psr()).should_execute(cond))
+	}
+
+	#[cfg(feature = "std")]
+	fn is_observed(&self) -> bool {
+		!self.observers.is_empty()
+	}
+
+	#[cfg(not(feature = "std"))]
+	fn is_observed(&self) -> bool {
+		false
+	}
+
+	/// Call `f` with every observer
+	#[cfg(feature = "std")]
+	fn notify<F: FnMut(&mut dyn Observer)>(&mut self, mut f: F) {
+		// Taken out so observers can't be changed while they are notified
+		let mut observers = core::mem::take(&mut self.observers);
+		for observer in &mut observers {
+			f(observer.as_mut());
+		}
+		self.observers = observers;
+	}
+
+	#[cfg(not(feature = "std"))]
+	fn notify<F: FnMut(&mut dyn Observer)>(&mut self, _f: F) {}
+
+	/// Report a CSR access by an instruction to the observers
+	pub(super) fn csr_accessed(&mut self, op: LoadStore, reg: u32) {
+		if !self.is_observed() {
+			return;
+		}
+
+		let blocks = &self.csr_blocks;
+		let block = (0..blocks.len())
+			.map(|i| blocks.index(i))
+			.find(|block| reg >= block.base_reg() && reg < block.base_reg() + block.size())
+			.map_or("unknown", |block| block.name());
+
+		let access = CsrAccess {
+			op,
+			reg,
+			block,
+		};
+		self.notify(|observer| observer.csr_access(&access));
+	}
+
+	/// Account for the instruction at `pc` and advance the pc, `start` is the cycle count before it was fetched
+	/// and `passed` whether its condition held
+	fn retire(&mut self, pc: u32, instr: &Instruction, class: InstructionClass, start: u64, passed: bool) {
+		self.add_cycles(self.target.cycles(class));
+		self.core.retired += 1;
+
 		// If pc wasn't updated by a jump, advance to next instruction
-		if !self.core.borrow().pc_touched {
-			let new_pc = self.core.borrow().read_pc() + 4;
-			self.core.borrow_mut().write_pc(new_pc);
+		if !self.core.pc_touched {
+			let new_pc = self.core.read_pc() + 4;
+			self.core.write_pc(new_pc);
+		} else {
+			self.add_cycles(self.target.taken_branch_penalty());
 		}
 
 		debug!("{}", self);
-		Ok(())
+
+		if self.is_observed() {
+			let event = Retired {
+				pc,
+				instr,
+				cycles: self.cycles() - start,
+				next_pc: self.core.read_pc(),
+				passed,
+			};
+			self.notify(|observer| observer.retired(&event));
+		}
 	}
 
 	pub fn execute_instructions(&mut self, instrs: &[Instruction]) {
 		for instr in instrs {
+			self.check_cycle_budget();
+			if self.is_stopped() {
+				break;
+			}
+
 			if let Err(interrupt) = self.execute(instr) {
 				self.handle_interrupt(&interrupt);
 			}
@@ -355,7 +863,7 @@ where
 	}
 
 	pub fn fetch(&self) -> Result<u32> {
-		let core = self.core.borrow();
+		let core = &self.core;
 		debug!("Fetching instruction at {:08x}", core.read_pc());
 		let res = self.memory.as_ref().unwrap().read(core.read_pc(), Width::Word);
 		if res.is_err() {
@@ -373,24 +881,44 @@ where
 		Ok(instruction.unwrap())
 	}
 
-	pub fn execute_one(&mut self) {
-		if self.memory.is_none() {
-			return;
+	/// Execute the next instruction, or the next block with `Engine::Block`
+	pub fn step(&mut self) {
+		match self.engine {
+			Engine::Interpreter => self.execute_one(),
+			#[cfg(feature = "std")]
+			Engine::Block => self.execute_block(),
 		}
+	}
 
-		let instr = self.fetch();
-		if let Err(int) = instr {
-			self.handle_interrupt(&int);
-			return;
+	pub fn execute_one(&mut self) {
+		if self.reset_pending {
+			self.reset_with(ResetKind::Warm);
 		}
 
-		let instr = self.decode(instr.unwrap());
-		if let Err(int) = instr {
-			self.handle_interrupt(&int);
+		self.check_cycle_budget();
+		if self.memory.is_none() || self.is_stopped() {
 			return;
 		}
 
-		let res = self.execute(&instr.unwrap());
+		let pc = self.core.read_pc();
+		let instr = match self.decode_cache.get(pc) {
+			Some(instr) => instr.clone(),
+			None => match self.fetch().and_then(|word| self.decode(word)) {
+				Ok(instr) => {
+					self.decode_cache.insert(pc, instr.clone());
+					instr
+				},
+				Err(int) => {
+					self.handle_interrupt(&int);
+					return;
+				},
+			},
+		};
+
+		let start = self.cycles();
+		self.add_cycles(self.wait_states(pc, Width::Word));
+
+		let res = self.execute_from(&instr, start);
 		if let Err(int) = res {
 			self.handle_interrupt(&int);
 		}
@@ -411,6 +939,14 @@ where
 		self.memory.as_ref().unwrap().size()
 	}
 
+	fn contains(&self, addr: u32) -> bool {
+		self.memory.as_ref().map_or(false, |memory| memory.contains(addr))
+	}
+
+	fn next_populated(&self, addr: u32) -> Option<(u32, u64)> {
+		self.memory.as_ref()?.next_populated(addr)
+	}
+
 	fn read(&self, addr: u32, width: Width) -> Result<u32> {
 		if self.memory.is_none() {
 			return Err(Interrupt::mem_fault(addr));
@@ -424,8 +960,33 @@ where
 			return Err(Interrupt::mem_fault(addr));
 		}
 
+		self.invalidate_code(addr, width_bytes(width) as usize);
 		self.memory.as_mut().unwrap().write(addr, width, val)
 	}
+
+	fn wait_states(&self, addr: u32, width: Width) -> u32 {
+		match &self.memory {
+			Some(memory) => memory.wait_states(addr, width),
+			None => 0,
+		}
+	}
+
+	fn read_bytes(&self, addr: u32, buf: &mut [u8]) -> Result<()> {
+		if self.memory.is_none() {
+			return Err(Interrupt::mem_fault(addr));
+		}
+
+		self.memory.as_ref().unwrap().read_bytes(addr, buf)
+	}
+
+	fn write_bytes(&mut self, addr: u32, data: &[u8]) -> Result<()> {
+		if self.memory.is_none() {
+			return Err(Interrupt::mem_fault(addr));
+		}
+
+		self.invalidate_code(addr, data.len());
+		self.memory.as_mut().unwrap().write_bytes(addr, data)
+	}
 }
 
 impl<T,M, C> fmt::Display for State<T, M, C>
@@ -435,12 +996,268 @@ where
 	C: CsrCollection,
 {
 	fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-		let core = self.core.borrow();
-		formatter.write_str("Core State\n")?;
-		for i in 0..32 {
-			write!(formatter, "\tr{}: 0x{:08x}\n", i, core.read_reg(Register::new(i).unwrap())).unwrap();
+		let core = &self.core;
+		for row in 0..8 {
+			for col in 0..4 {
+				let reg = Register::new(row * 4 + col).unwrap();
+				write!(formatter, "{:>4}: {:08x}  ", RegisterName(reg), core.read_reg(reg))?;
+			}
+			formatter.write_str("\n")?;
 		}
-		write!(formatter, "\tpsr: 0x{:08x}\n", self.read_psr()).unwrap();
-		Ok(())
+
+		let psr = Psr(self.read_psr());
+		writeln!(
+			formatter,
+			" psr: {:08x}  n={} z={} c={} v={} interrupt_mode={}",
+			psr.0, psr.n(), psr.z(), psr.c(), psr.v(), psr.interrupt_mode(),
+		)?;
+		write!(formatter, "cycles: {}  retired: {}", core.cycles(), core.retired())
+	}
+}
+
+#[cfg(test)]
+#[cfg(feature = "std")]
+mod test {
+	extern crate std;
+
+	use super::*;
+	use super::csr::*;
+	use crate::{
+		memory::Mock,
+		target::StdTarget,
+	};
+	use std::{
+		boxed::Box,
+		vec,
+		vec::Vec,
+	};
+
+	const ISR_BASE_ADDR: u32 = 0x1000;
+
+	fn state() -> State<StdTarget, Mock, Vec<Box<dyn CsrBlock>>> {
+		let mut state: State<_, Mock, Vec<Box<dyn CsrBlock>>> = State::new(StdTarget::new(), None, vec![
+			Box::new(PsrBlock::new()),
+			Box::new(IsrBlock::new()),
+		]);
+
+		state.write_csr(ISR_BASE_REG, ISR_BASE_ADDR, Width::Word);
+		state
+	}
+
+	fn handler(kind: InterruptKind) -> u32 {
+		ISR_BASE_ADDR + 4 * kind.to_index().unwrap()
+	}
+
+	fn pc<T: Target, M: Memory, C: CsrCollection>(state: &State<T, M, C>) -> u32 {
+		state.core().read_pc()
+	}
+
+	#[test]
+	fn test_isr_return() {
+		let mut state = state();
+		state.core_mut().write_pc(0x100);
+		state.core_mut().write_sp(0x8000);
+
+		state.handle_interrupt(&Interrupt::swi());
+		let psr = Psr(state.read_psr());
+		assert_eq!(psr.interrupt_mode(), 1);
+		assert_eq!(psr.exception_enabled(), 0);
+		assert_eq!(pc(&state), handler(InterruptKind::Swi));
+
+		// Handler runs on its own bank, SWI returns to the next instruction
+		assert_eq!(state.core().read_sp(), 0);
+		assert_eq!(state.read_csr(ISR_PC_REG, Width::Word), Some(0x104));
+		state.core_mut().write_sp(0x4000);
+
+		state.handle_interrupt(&Interrupt::isr_exit());
+		let psr = Psr(state.read_psr());
+		assert_eq!(psr.interrupt_mode(), 0);
+		assert_eq!(psr.exception_enabled(), 1);
+		assert_eq!(pc(&state), 0x104);
+		assert_eq!(state.core().read_sp(), 0x8000);
+
+		// Handler state is preserved for the next interrupt
+		state.handle_interrupt(&Interrupt::swi());
+		assert_eq!(state.core().read_sp(), 0x4000);
+	}
+
+	#[test]
+	fn test_nested_fault() {
+		let mut state = state();
+		state.core_mut().write_pc(0x100);
+
+		// Faults return to the faulting instruction
+		state.handle_interrupt(&Interrupt::mem_fault(0xdead));
+		assert_eq!(pc(&state), handler(InterruptKind::MemoryFault));
+		assert_eq!(state.read_csr(ISR_ERR1_REG, Width::Word), Some(0xdead));
+		assert_eq!(state.read_csr(ISR_PC_REG, Width::Word), Some(0x100));
+
+		// Fault inside the handler
+		state.handle_interrupt(&Interrupt::opcode());
+		assert_eq!(pc(&state), handler(InterruptKind::DoubleFault));
+		assert!(state.double_fault.is_some());
+
+		state.handle_interrupt(&Interrupt::isr_exit());
+		assert_eq!(pc(&state), 0x100);
+		assert!(state.double_fault.is_none());
+		assert_eq!(Psr(state.read_psr()).interrupt_mode(), 0);
+		assert_eq!(state.read_csr(ISR_ERR1_REG, Width::Word), Some(0));
+	}
+
+	#[test]
+	fn test_reset() {
+		let config = ResetConfig {
+			reset_vector: 0x2000,
+			isr_base: ISR_BASE_ADDR,
+		};
+		let mut state: State<_, Mock, Vec<Box<dyn CsrBlock>>> = State::with_reset_config(StdTarget::new(), None, vec![
+			Box::new(PsrBlock::new()),
+			Box::new(IsrBlock::new()),
+		], config);
+
+		assert_eq!(pc(&state), 0x2000);
+		assert_eq!(state.read_csr(ISR_BASE_REG, Width::Word), Some(ISR_BASE_ADDR));
+
+		state.core_mut().write_sp(0x8000);
+		state.handle_interrupt(&Interrupt::mem_fault(0xdead));
+		state.handle_interrupt(&Interrupt::opcode());
+
+		state.reset();
+		assert_eq!(pc(&state), 0x2000);
+		assert_eq!(state.core().read_sp(), 0);
+		assert_eq!(state.read_psr(), 0);
+		assert_eq!(state.read_csr(ISR_BASE_REG, Width::Word), Some(ISR_BASE_ADDR));
+		assert_eq!(state.read_csr(ISR_ERR1_REG, Width::Word), Some(0));
+		assert_eq!(state.read_csr(ISR_PC_REG, Width::Word), Some(0));
+		assert!(state.double_fault.is_none());
+
+		// Reset interrupt
+		state.core_mut().write_pc(0x100);
+		state.handle_interrupt(&Interrupt {
+			kind: InterruptKind::Reset,
+			err1: 0,
+			err2: 0,
+		});
+		assert_eq!(pc(&state), 0x2000);
+	}
+
+	#[test]
+	fn test_warm_reset() {
+		let mut state: State<_, Mock, Vec<Box<dyn CsrBlock>>> = State::new(StdTarget::new(), None, vec![
+			Box::new(PsrBlock::new()),
+			Box::new(IsrBlock::new()),
+			Box::new(CycleBlock::new()),
+		]);
+		state.set_reset_config(ResetConfig {
+			reset_vector: 0x2000,
+			isr_base: ISR_BASE_ADDR,
+		});
+		state.reset();
+
+		// Moved by the guest after boot
+		state.write_csr(ISR_BASE_REG, 0x3000, Width::Word);
+		state.core_mut().write_sp(0x8000);
+		state.add_cycles(100);
+
+		state.reset_with(ResetKind::Warm);
+		assert_eq!(pc(&state), 0x2000);
+		assert_eq!(state.core().read_sp(), 0);
+		assert_eq!(state.read_csr(ISR_BASE_REG, Width::Word), Some(0x3000));
+		assert_eq!(state.read_csr(CYCLE_LO_REG, Width::Word), Some(100));
+
+		state.reset_with(ResetKind::Cold);
+		assert_eq!(state.read_csr(ISR_BASE_REG, Width::Word), Some(ISR_BASE_ADDR));
+		assert_eq!(state.read_csr(CYCLE_LO_REG, Width::Word), Some(0));
+	}
+
+	#[test]
+	#[should_panic(expected = "CSR blocks must start with a PsrBlock followed by an IsrBlock")]
+	fn test_csr_block_order() {
+		let _: State<_, Mock, Vec<Box<dyn CsrBlock>>> = State::new(StdTarget::new(), None, vec![
+			Box::new(IsrBlock::new()),
+			Box::new(PsrBlock::new()),
+		]);
+	}
+
+	#[test]
+	#[should_panic(expected = "CSR blocks must start with a PsrBlock followed by an IsrBlock")]
+	fn test_missing_csr_blocks() {
+		let _: State<_, Mock, Vec<Box<dyn CsrBlock>>> = State::new(StdTarget::new(), None, Vec::new());
+	}
+
+	#[test]
+	fn test_load_without_memory() {
+		let mut state = state();
+		let res = state.load_image(Format::IntelHex, ":00000001FF\n");
+		assert!(matches!(res, Err(LoadError::NoMemory)), "{res:?}");
+	}
+
+	#[test]
+	fn test_reset_pin() {
+		let mut memory = Mock::new(0x10000);
+		memory.should_fail = true;
+
+		let mut state = state();
+		state.set_reset_config(ResetConfig {
+			reset_vector: 0x2000,
+			isr_base: ISR_BASE_ADDR,
+		});
+		state.attach_memory(Some(memory));
+		state.core_mut().write_pc(0x100);
+
+		// The reset is taken before the fetch, which faults at the reset vector
+		state.assert_reset();
+		state.execute_one();
+		assert_eq!(pc(&state), handler(InterruptKind::MemoryFault));
+		assert_eq!(state.read_csr(ISR_ERR1_REG, Width::Word), Some(0x2000));
+	}
+
+	#[test]
+	fn test_lockup() {
+		let mut memory = Mock::new(0x10000);
+		memory.should_fail = true;
+
+		let mut state = state();
+		state.attach_memory(Some(memory));
+		state.core_mut().write_pc(0x100);
+		state.core_mut().write_sp(0x8000);
+
+		// Every fetch faults, first at 0x100, then in the fault and double fault handlers
+		state.execute_one();
+		state.execute_one();
+		assert!(!state.is_stopped());
+		state.execute_one();
+
+		let lockup = match state.stop_reason() {
+			Some(StopReason::Lockup(lockup)) => lockup.clone(),
+			_ => panic!("Expected lockup"),
+		};
+
+		let original = lockup.original.unwrap();
+		assert_eq!(original.kind, InterruptKind::MemoryFault);
+		assert_eq!(original.err1, 0x100);
+		assert_eq!(original.core.read_sp(), 0x8000);
+
+		assert_eq!(lockup.double_fault.kind, InterruptKind::MemoryFault);
+		assert_eq!(lockup.double_fault.err1, handler(InterruptKind::MemoryFault));
+		assert_eq!(lockup.fault.err1, handler(InterruptKind::DoubleFault));
+		assert_eq!(lockup.fault.core.read_pc(), handler(InterruptKind::DoubleFault));
+
+		// Stopped states don't execute until reset
+		state.execute_one();
+		assert_eq!(pc(&state), handler(InterruptKind::DoubleFault));
+
+		state.reset();
+		assert!(!state.is_stopped());
+	}
+
+	#[test]
+	fn test_exit_outside_isr() {
+		let mut state = state();
+		state.core_mut().write_pc(0x100);
+
+		state.handle_interrupt(&Interrupt::isr_exit());
+		assert_eq!(pc(&state), handler(InterruptKind::OpcodeFault));
+		assert_eq!(Psr(state.read_psr()).interrupt_mode(), 1);
 	}
 }
@@ -0,0 +1,78 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Hooks for tools that watch execution, e.g. profilers
+use bibe_instr::{
+	Instruction,
+	LoadStore,
+	Width,
+};
+
+use crate::Interrupt;
+
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "std")]
+use std::{
+	cell::RefCell,
+	rc::Rc,
+};
+
+/// An instruction that finished executing
+#[derive(Copy, Clone, Debug)]
+pub struct Retired<'a> {
+	pub pc: u32,
+	pub instr: &'a Instruction,
+	/// Cycles the instruction took, including fetch and memory wait states
+	pub cycles: u64,
+	/// Address of the next instruction
+	pub next_pc: u32,
+	/// Whether the instruction's condition held, always true for unconditional formats
+	pub passed: bool,
+}
+
+/// Data access made by an instruction
+#[derive(Copy, Clone, Debug)]
+pub struct Access {
+	pub op: LoadStore,
+	pub addr: u32,
+	pub width: Width,
+}
+
+/// CSR access made by an instruction
+#[derive(Copy, Clone, Debug)]
+pub struct CsrAccess {
+	pub op: LoadStore,
+	pub reg: u32,
+	/// `CsrBlock::name` of the block containing `reg`
+	pub block: &'static str,
+}
+
+/// Receives execution events from a `State`, see `State::add_observer`
+///
+/// Events are only sent for accesses that succeeded and instructions that retired.
+pub trait Observer {
+	fn retired(&mut self, _event: &Retired) {}
+	fn memory_access(&mut self, _access: &Access) {}
+	fn csr_access(&mut self, _access: &CsrAccess) {}
+	/// Interrupt about to be taken, ISR exits aren't reported
+	fn interrupt(&mut self, _interrupt: &Interrupt) {}
+}
+
+/// Lets the owner keep a handle to an observer it gave to a state
+#[cfg(feature = "std")]
+impl<O: Observer> Observer for Rc<RefCell<O>> {
+	fn retired(&mut self, event: &Retired) {
+		self.borrow_mut().retired(event)
+	}
+
+	fn memory_access(&mut self, access: &Access) {
+		self.borrow_mut().memory_access(access)
+	}
+
+	fn csr_access(&mut self, access: &CsrAccess) {
+		self.borrow_mut().csr_access(access)
+	}
+
+	fn interrupt(&mut self, interrupt: &Interrupt) {
+		self.borrow_mut().interrupt(interrupt)
+	}
+}
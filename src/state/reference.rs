@@ -0,0 +1,170 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Reference semantics for the ALU and shifter, written independently of `util`
+//! using 64-bit arithmetic, and a randomized differential test against the executor
+use bibe_instr::{
+	BinOp,
+	Shift,
+	ShiftKind,
+};
+
+use super::{
+	shift,
+	util::execute_binop,
+};
+use crate::InterruptKind;
+
+const MASK: u64 = 0xffff_ffff;
+
+fn sext(value: u32) -> i64 {
+	value as i32 as i64
+}
+
+fn rotate_left(value: u32, amount: u32) -> u32 {
+	let amount = (amount % 32) as u64;
+	let value = value as u64;
+	((value << amount | value >> (32 - amount)) & MASK) as u32
+}
+
+fn shift_model(kind: ShiftKind, value: u32, amount: u32) -> u32 {
+	let amount = amount.min(63) as u64;
+
+	match kind {
+		ShiftKind::Shl
+		| ShiftKind::Asl => (((value as u64) << amount) & MASK) as u32,
+		ShiftKind::Shr => ((value as u64) >> amount) as u32,
+		ShiftKind::Asr => (sext(value) >> amount) as u32,
+		ShiftKind::Rol => rotate_left(value, amount as u32),
+		ShiftKind::Ror => rotate_left(value, 32 - (amount as u32 % 32)),
+	}
+}
+
+/// Result of `op`, `None` if the operation faults
+fn binop_model(op: BinOp, lhs: u32, rhs: u32) -> Option<u32> {
+	let (l, r) = (lhs as u64, rhs as u64);
+
+	Some(match op {
+		BinOp::Add
+		| BinOp::Addcc => ((l + r) & MASK) as u32,
+		BinOp::Sub
+		| BinOp::Subcc => ((l + (1 << 32) - r) & MASK) as u32,
+		BinOp::Mul => ((l * r) & MASK) as u32,
+		BinOp::Div => (l.checked_div(r)?) as u32,
+		BinOp::Mod => (l.checked_rem(r)?) as u32,
+		BinOp::And => (l & r) as u32,
+		BinOp::Or => (l | r) as u32,
+		BinOp::Xor => (l ^ r) as u32,
+		BinOp::Shl => shift_model(ShiftKind::Shl, lhs, rhs),
+		BinOp::Shr => shift_model(ShiftKind::Shr, lhs, rhs),
+		BinOp::Asl => shift_model(ShiftKind::Asl, lhs, rhs),
+		BinOp::Asr => shift_model(ShiftKind::Asr, lhs, rhs),
+		BinOp::Rol => shift_model(ShiftKind::Rol, lhs, rhs),
+		BinOp::Ror => shift_model(ShiftKind::Ror, lhs, rhs),
+		BinOp::Not => (!(l + r) & MASK) as u32,
+		BinOp::Neg => (((1 << 33) - (l + r)) & MASK) as u32,
+	})
+}
+
+const BINOPS: [BinOp; 18] = [
+	BinOp::Add,
+	BinOp::Addcc,
+	BinOp::Sub,
+	BinOp::Subcc,
+	BinOp::Mul,
+	BinOp::Div,
+	BinOp::Mod,
+	BinOp::And,
+	BinOp::Or,
+	BinOp::Xor,
+	BinOp::Shl,
+	BinOp::Shr,
+	BinOp::Asl,
+	BinOp::Asr,
+	BinOp::Rol,
+	BinOp::Ror,
+	BinOp::Not,
+	BinOp::Neg,
+];
+
+const SHIFTS: [ShiftKind; 6] = [
+	ShiftKind::Shl,
+	ShiftKind::Shr,
+	ShiftKind::Asl,
+	ShiftKind::Asr,
+	ShiftKind::Rol,
+	ShiftKind::Ror,
+];
+
+const EDGES: [u32; 10] = [0, 1, 2, 31, 32, 33, 0x7fff_ffff, 0x8000_0000, 0x8000_0001, 0xffff_ffff];
+
+const ITERATIONS: usize = 10_000;
+
+/// Xorshift, deterministic so failures are reproducible
+struct Rng(u64);
+
+impl Rng {
+	fn next_u32(&mut self) -> u32 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		(self.0 >> 32) as u32
+	}
+
+	/// Random value, biased towards edge cases
+	fn operand(&mut self) -> u32 {
+		let value = self.next_u32();
+		match value % 4 {
+			0 => EDGES[(value >> 8) as usize % EDGES.len()],
+			1 => value % 64,
+			_ => self.next_u32(),
+		}
+	}
+}
+
+#[test]
+fn test_binop() {
+	let mut rng = Rng(0x5eed_1234_abcd_9876);
+
+	for op in BINOPS {
+		for lhs in EDGES {
+			for rhs in EDGES {
+				assert_eq!(execute_binop(op, lhs, rhs).ok(), binop_model(op, lhs, rhs), "{op:?} {lhs:#x} {rhs:#x}");
+			}
+		}
+
+		for _ in 0..ITERATIONS {
+			let (lhs, rhs) = (rng.operand(), rng.operand());
+			assert_eq!(execute_binop(op, lhs, rhs).ok(), binop_model(op, lhs, rhs), "{op:?} {lhs:#x} {rhs:#x}");
+		}
+	}
+}
+
+#[test]
+fn test_shift() {
+	let mut rng = Rng(0x0dd_ba11_cafe_f00d);
+
+	for kind in SHIFTS {
+		// Amounts of 32 and above shift every bit out, or rotate by the amount mod 32
+		for _ in 0..ITERATIONS {
+			let value = rng.operand();
+			let amount = match rng.next_u32() % 4 {
+				0 => [31, 32, 33, 63][rng.next_u32() as usize % 4],
+				_ => rng.next_u32() % 64,
+			};
+			let s = Shift {
+				kind,
+				shift: amount as _,
+			};
+
+			assert_eq!(shift(&s, value), shift_model(kind, value, amount), "{kind:?} {value:#x} {amount}");
+		}
+	}
+}
+
+#[test]
+fn test_div_zero() {
+	// Both division operations fault the guest rather than the host
+	for op in [BinOp::Div, BinOp::Mod] {
+		let fault = execute_binop(op, 1, 0);
+		assert!(matches!(fault, Err(ref e) if e.kind == InterruptKind::OpcodeFault));
+	}
+}
@@ -5,7 +5,7 @@ use crate::{
 };
 use super::{
 	csr::CsrCollection, util::{
-		check_binop, execute_binop, BinOpOverflow
+		binop_flags, execute_binop
 	}, State
 };
 
@@ -15,7 +15,7 @@ where
 	M: Memory,
 	C: CsrCollection,
 {
-	let src = s.core.borrow().read_reg(instr.src);
+	let src = s.core().read_reg(instr.src);
 	let imm = (instr.imm as i32) as u32;
 	let psr = Psr(s.read_psr());
 
@@ -32,23 +32,10 @@ where
 	// cc instructions touch psr
 	if instr.op.is_cc() {
 		let mut psr = psr;
-		let BinOpOverflow {
-			overflow,
-			carry
-		} = check_binop(instr.op, src, imm);
-
-		if overflow {
-			psr.set_v(1);
-		}
-
-		if carry {
-			psr.set_c(1);
-		}
-
-		psr.set_zn(res);
+		binop_flags(instr.op, src, imm, res).apply(&mut psr);
 		s.write_psr(psr.0);
 	}
-	s.core.borrow_mut().write_reg(instr.dest, res);
+	s.core_mut().write_reg(instr.dest, res);
 
 	Ok(())
 }
\ No newline at end of file
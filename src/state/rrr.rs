@@ -5,7 +5,7 @@ use crate::{
 };
 use super::{
 	csr::CsrCollection, shift, util::{
-		check_binop, execute_binop, BinOpOverflow
+		binop_flags, execute_binop
 	}, State
 };
 
@@ -15,34 +15,25 @@ where
 	M: Memory,
 	C: CsrCollection,
 {
-	let rs = s.core.borrow().read_reg(instr.lhs);
-	let rq = shift(&instr.shift, s.core.borrow().read_reg(instr.rhs));
+	let rs = s.core().read_reg(instr.lhs);
+	let rq = shift(&instr.shift, s.core().read_reg(instr.rhs));
 
 	if !s.target().supports_binop(instr.op) {
 		return Err(Interrupt::opcode());
 	}
 
+	if !Psr(s.read_psr()).should_execute(instr.cond) {
+		return Ok(());
+	}
+
 	let res = execute_binop(instr.op, rs, rq)?;
 
 	// cc instructions touch psr
 	if instr.op.is_cc() {
 		let mut psr = Psr(s.read_psr());
-		let BinOpOverflow {
-			overflow,
-			carry
-		} = check_binop(instr.op, rs, rq);
-
-		if overflow {
-			psr.set_v(1);
-		}
-
-		if carry {
-			psr.set_c(1);
-		}
-
-		psr.set_zn(res);
+		binop_flags(instr.op, rs, rq, res).apply(&mut psr);
 		s.write_psr(psr.0);
 	}
-	s.core.borrow_mut().write_reg(instr.dest, res);
+	s.core_mut().write_reg(instr.dest, res);
 	Ok(())
 }
\ No newline at end of file
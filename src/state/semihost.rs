@@ -0,0 +1,501 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Host services for guest programs
+//!
+//! A call is made by storing the operation number to `SEMIHOST_CALL_REG`. `a0` points to a
+//! block of word sized arguments and the result is returned in `o0`, `-1` on error. Host
+//! files are only reachable through relative paths below the sandbox root.
+#![cfg(feature = "std")]
+extern crate std;
+
+use std::{
+	fs::{
+		File,
+		OpenOptions,
+	},
+	io::{
+		self,
+		Read,
+		Seek,
+		SeekFrom,
+		Write,
+	},
+	path::{
+		Component,
+		Path,
+		PathBuf,
+	},
+	string::String,
+	time::{
+		SystemTime,
+		UNIX_EPOCH,
+	},
+	vec,
+	vec::Vec,
+};
+
+use bibe_instr::{
+	csr::Instruction,
+	LoadStore,
+	Register,
+	Width,
+};
+
+use crate::{
+	memory::Memory,
+	target::Target,
+	Interrupt,
+	Result,
+};
+
+use super::{
+	csr::CsrCollection,
+	State,
+	StopReason,
+};
+
+/// Implementation defined register, placed above the ISA defined CSRs
+pub const SEMIHOST_CALL_REG: u32 = 0x0001_0100;
+
+/// `open(path, mode)`, mode is one of the `MODE_*` values
+pub const SYS_OPEN: u32 = 0;
+/// `close(fd)`
+pub const SYS_CLOSE: u32 = 1;
+/// `read(fd, buf, len)`, returns the number of bytes read
+pub const SYS_READ: u32 = 2;
+/// `write(fd, buf, len)`, returns the number of bytes written
+pub const SYS_WRITE: u32 = 3;
+/// `seek(fd, offset, whence)`, whence is 0, 1 or 2 for start, current and end, returns the new position
+pub const SYS_SEEK: u32 = 4;
+/// `exit(status)`, stops the state with `StopReason::Exit`
+pub const SYS_EXIT: u32 = 5;
+/// `argc()`
+pub const SYS_ARGC: u32 = 6;
+/// `argv(index, buf, len)`, returns the length of the argument, it is only copied if it fits with its terminator
+pub const SYS_ARGV: u32 = 7;
+/// `getenv(name, buf, len)`, returns the length of the value, copied like `SYS_ARGV`
+pub const SYS_GETENV: u32 = 8;
+/// `time()`, seconds since the Unix epoch
+pub const SYS_TIME: u32 = 9;
+
+pub const MODE_READ: u32 = 0;
+/// Create or truncate
+pub const MODE_WRITE: u32 = 1;
+/// Create or append
+pub const MODE_APPEND: u32 = 2;
+pub const MODE_READ_WRITE: u32 = 3;
+
+const ERROR: u32 = u32::MAX;
+/// Longest path or environment variable name accepted
+const MAX_NAME: usize = 1024;
+/// Largest single read or write, guests see a short transfer for anything larger
+const MAX_TRANSFER: u32 = 1 << 20;
+
+enum Handle {
+	Stdin,
+	Stdout,
+	Stderr,
+	File(File),
+}
+
+impl Handle {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match self {
+			Handle::Stdin => io::stdin().read(buf),
+			Handle::File(f) => f.read(buf),
+			_ => Err(io::ErrorKind::Unsupported.into()),
+		}
+	}
+
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self {
+			Handle::Stdout => io::stdout().write(buf),
+			Handle::Stderr => io::stderr().write(buf),
+			Handle::File(f) => f.write(buf),
+			_ => Err(io::ErrorKind::Unsupported.into()),
+		}
+	}
+}
+
+/// Host side of the semihosting interface, file descriptors 0 to 2 are the host's stdio
+pub struct Semihost {
+	root: PathBuf,
+	pub args: Vec<String>,
+	pub env: Vec<(String, String)>,
+	files: Vec<Option<Handle>>,
+}
+
+impl Semihost {
+	/// Guest file access is limited to `root`
+	///
+	/// Symlinks are followed as long as they stay inside the root.
+	pub fn new<P: Into<PathBuf>>(root: P) -> Semihost {
+		Semihost {
+			root: root.into(),
+			args: Vec::new(),
+			env: Vec::new(),
+			files: vec![Some(Handle::Stdin), Some(Handle::Stdout), Some(Handle::Stderr)],
+		}
+	}
+
+	/// Host path of a guest path, `None` if it would leave the root
+	///
+	/// The existing part of the path is canonicalized, so symlinks can't lead outside the root.
+	/// Missing components are appended as they are, creating them can only happen below it.
+	fn resolve(&self, path: &str) -> Option<PathBuf> {
+		let root = self.root.canonicalize().ok()?;
+		let mut resolved = root.clone();
+
+		for component in Path::new(path).components() {
+			match component {
+				Component::Normal(c) => resolved.push(c),
+				Component::CurDir => (),
+				_ => return None,
+			}
+		}
+
+		let mut existing = resolved.as_path();
+		let mut missing = Vec::new();
+		let mut canonical = loop {
+			match existing.canonicalize() {
+				Ok(canonical) => break canonical,
+				// A dangling symlink would be followed when the file is created
+				Err(_) if existing.symlink_metadata().is_ok() => return None,
+				Err(_) => {
+					missing.push(existing.file_name()?);
+					existing = existing.parent()?;
+				},
+			}
+		};
+
+		if !canonical.starts_with(&root) {
+			return None;
+		}
+
+		canonical.extend(missing.iter().rev());
+		Some(canonical)
+	}
+
+	fn handle(&mut self, fd: u32) -> Option<&mut Handle> {
+		self.files.get_mut(fd as usize)?.as_mut()
+	}
+
+	fn open(&mut self, path: &str, mode: u32) -> u32 {
+		let path = match self.resolve(path) {
+			Some(path) => path,
+			None => return ERROR,
+		};
+
+		let mut options = OpenOptions::new();
+		match mode {
+			MODE_READ => options.read(true),
+			MODE_WRITE => options.write(true).create(true).truncate(true),
+			MODE_APPEND => options.append(true).create(true),
+			MODE_READ_WRITE => options.read(true).write(true),
+			_ => return ERROR,
+		};
+
+		let file = match options.open(path) {
+			Ok(file) => Handle::File(file),
+			Err(_) => return ERROR,
+		};
+
+		// Reuse the lowest free descriptor
+		match self.files.iter().position(Option::is_none) {
+			Some(fd) => {
+				self.files[fd] = Some(file);
+				fd as u32
+			},
+			None => {
+				self.files.push(Some(file));
+				self.files.len() as u32 - 1
+			},
+		}
+	}
+
+	/// Perform call `op` with the arguments at `args`
+	pub(super) fn call<T, M, C>(&mut self, s: &mut State<T, M, C>, op: u32, args: u32) -> Result<u32>
+	where
+		T: Target,
+		M: Memory,
+		C: CsrCollection,
+	{
+		let arg = |s: &State<T, M, C>, n: u32| s.read(args.wrapping_add(4 * n), Width::Word);
+
+		Ok(match op {
+			SYS_OPEN => {
+				let path = read_str(s, arg(s, 0)?)?;
+				match path {
+					Some(path) => self.open(&path, arg(s, 1)?),
+					None => ERROR,
+				}
+			},
+			SYS_CLOSE => match self.files.get_mut(arg(s, 0)? as usize) {
+				Some(handle @ Some(_)) => {
+					*handle = None;
+					0
+				},
+				_ => ERROR,
+			},
+			SYS_READ => {
+				let (fd, addr, len) = (arg(s, 0)?, arg(s, 1)?, arg(s, 2)?);
+				let mut buf = vec![0u8; len.min(MAX_TRANSFER) as usize];
+				match self.handle(fd).map(|h| h.read(&mut buf)) {
+					Some(Ok(count)) => {
+						s.write_bytes(addr, &buf[..count])?;
+						count as u32
+					},
+					_ => ERROR,
+				}
+			},
+			SYS_WRITE => {
+				let (fd, addr, len) = (arg(s, 0)?, arg(s, 1)?, arg(s, 2)?);
+				let mut buf = vec![0u8; len.min(MAX_TRANSFER) as usize];
+				s.read_bytes(addr, &mut buf)?;
+				match self.handle(fd).map(|h| h.write(&buf)) {
+					Some(Ok(count)) => count as u32,
+					_ => ERROR,
+				}
+			},
+			SYS_SEEK => {
+				let (fd, offset, whence) = (arg(s, 0)?, arg(s, 1)? as i32, arg(s, 2)?);
+				let pos = match whence {
+					0 if offset >= 0 => SeekFrom::Start(offset as u64),
+					1 => SeekFrom::Current(offset as i64),
+					2 => SeekFrom::End(offset as i64),
+					_ => return Ok(ERROR),
+				};
+
+				match self.handle(fd) {
+					Some(Handle::File(f)) => f.seek(pos).ok().and_then(|pos| u32::try_from(pos).ok()).unwrap_or(ERROR),
+					_ => ERROR,
+				}
+			},
+			SYS_EXIT => {
+				let status = arg(s, 0)? as i32;
+				s.stop = Some(StopReason::Exit(status));
+				0
+			},
+			SYS_ARGC => self.args.len() as u32,
+			SYS_ARGV => match self.args.get(arg(s, 0)? as usize) {
+				Some(value) => copy_str(s, value, arg(s, 1)?, arg(s, 2)?)?,
+				None => ERROR,
+			},
+			SYS_GETENV => {
+				let name = read_str(s, arg(s, 0)?)?;
+				let value = self.env.iter().find(|(key, _)| Some(key) == name.as_ref());
+				match value {
+					Some((_, value)) => copy_str(s, value, arg(s, 1)?, arg(s, 2)?)?,
+					None => ERROR,
+				}
+			},
+			SYS_TIME => SystemTime::now().duration_since(UNIX_EPOCH).map_or(ERROR, |t| t.as_secs() as u32),
+			_ => ERROR,
+		})
+	}
+}
+
+/// Guest string at `addr`, `None` if it is too long or not UTF-8
+fn read_str<T, M, C>(s: &State<T, M, C>, addr: u32) -> Result<Option<String>>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	let mut buf = [0u8; MAX_NAME];
+	let len = match s.read_cstr(addr, &mut buf) {
+		Ok(len) => len,
+		// Unterminated within the limit, but readable
+		Err(_) if s.read_bytes(addr, &mut buf).is_ok() => return Ok(None),
+		Err(e) => return Err(e),
+	};
+
+	Ok(core::str::from_utf8(&buf[..len]).ok().map(String::from))
+}
+
+/// Copy `value` with a terminator to a guest buffer if it fits, returns the length of `value`
+fn copy_str<T, M, C>(s: &mut State<T, M, C>, value: &str, addr: u32, len: u32) -> Result<u32>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	if value.len() < len as usize {
+		s.write_cstr(addr, value.as_bytes())?;
+	}
+
+	Ok(value.len() as u32)
+}
+
+pub(super) fn is_call(instr: &Instruction) -> bool {
+	!instr.op.is_load() && instr.imm == SEMIHOST_CALL_REG
+}
+
+/// Without a `Semihost` attached the register doesn't exist
+pub(super) fn execute<T, M, C>(s: &mut State<T, M, C>, instr: &Instruction) -> Result<()>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	let mut semihost = s.semihost.take().ok_or_else(Interrupt::opcode)?;
+	s.csr_accessed(LoadStore::Store, instr.imm);
+
+	let op = s.core().read_reg(instr.reg);
+	let args = s.core().read_reg(Register::a0());
+
+	let res = semihost.call(s, op, args);
+	s.semihost = Some(semihost);
+
+	s.core_mut().write_reg(Register::o0(), res?);
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use std::{
+		boxed::Box,
+		env,
+		fs,
+		string::ToString,
+	};
+	use crate::{
+		memory::SimpleImage,
+		state::csr::*,
+		target::StdTarget,
+	};
+
+	const ARGS: u32 = 0x100;
+	const STR: u32 = 0x200;
+	const BUF: u32 = 0x300;
+
+	fn state() -> State<StdTarget, SimpleImage, Vec<Box<dyn CsrBlock>>> {
+		State::new(StdTarget::new(), Some(SimpleImage::new(0x1000)), vec![
+			Box::new(PsrBlock::new()),
+			Box::new(IsrBlock::new()),
+		])
+	}
+
+	fn call<T, M, C>(semihost: &mut Semihost, s: &mut State<T, M, C>, op: u32, args: &[u32]) -> u32
+	where
+		T: Target,
+		M: Memory,
+		C: CsrCollection,
+	{
+		for (i, arg) in args.iter().enumerate() {
+			assert!(s.write(ARGS + 4 * i as u32, Width::Word, *arg).is_ok());
+		}
+
+		semihost.call(s, op, ARGS).unwrap()
+	}
+
+	#[test]
+	fn test_sandbox() {
+		let root = env::temp_dir().join(std::format!("bibe-semihost-sandbox-{}", std::process::id()));
+		fs::create_dir_all(&root).unwrap();
+
+		let semihost = Semihost::new(&root);
+		let canonical = root.canonicalize().unwrap();
+		assert_eq!(semihost.resolve("./a/b.txt"), Some(canonical.join("a/b.txt")));
+		assert_eq!(semihost.resolve("../etc/passwd"), None);
+		assert_eq!(semihost.resolve("/etc/passwd"), None);
+
+		fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_symlink_escape() {
+		use std::os::unix::fs::symlink;
+
+		let root = env::temp_dir().join(std::format!("bibe-semihost-symlink-{}", std::process::id()));
+		let outside = env::temp_dir().join(std::format!("bibe-semihost-outside-{}", std::process::id()));
+		fs::create_dir_all(root.join("dir")).unwrap();
+		fs::create_dir_all(&outside).unwrap();
+		fs::write(outside.join("secret.txt"), b"secret").unwrap();
+
+		symlink(&outside, root.join("escape")).unwrap();
+		symlink(outside.join("new.txt"), root.join("dangling")).unwrap();
+		symlink(root.join("dir"), root.join("inside")).unwrap();
+
+		let mut semihost = Semihost::new(&root);
+		assert_eq!(semihost.resolve("escape/secret.txt"), None);
+		assert_eq!(semihost.resolve("escape/new.txt"), None);
+		assert_eq!(semihost.resolve("dangling"), None);
+
+		// Links that stay inside the root still work
+		let canonical = root.canonicalize().unwrap();
+		assert_eq!(semihost.resolve("inside/a.txt"), Some(canonical.join("dir/a.txt")));
+
+		// Neither reading nor creating through the links reaches the host file system
+		let mut s = state();
+		assert!(s.write_cstr(STR, b"escape/secret.txt").is_ok());
+		assert_eq!(call(&mut semihost, &mut s, SYS_OPEN, &[STR, MODE_READ]), ERROR);
+		assert!(s.write_cstr(STR, b"dangling").is_ok());
+		assert_eq!(call(&mut semihost, &mut s, SYS_OPEN, &[STR, MODE_WRITE]), ERROR);
+		assert!(!outside.join("new.txt").exists());
+
+		fs::remove_dir_all(&root).unwrap();
+		fs::remove_dir_all(&outside).unwrap();
+	}
+
+	#[test]
+	fn test_files() {
+		let root = env::temp_dir().join(std::format!("bibe-semihost-{}", std::process::id()));
+		fs::create_dir_all(&root).unwrap();
+
+		let mut s = state();
+		let mut semihost = Semihost::new(&root);
+		assert!(s.write_cstr(STR, b"out.txt").is_ok());
+		assert!(s.write_bytes(BUF, b"hello").is_ok());
+
+		let fd = call(&mut semihost, &mut s, SYS_OPEN, &[STR, MODE_WRITE]);
+		assert_eq!(fd, 3);
+		assert_eq!(call(&mut semihost, &mut s, SYS_WRITE, &[fd, BUF, 5]), 5);
+		assert_eq!(call(&mut semihost, &mut s, SYS_CLOSE, &[fd]), 0);
+		assert_eq!(call(&mut semihost, &mut s, SYS_CLOSE, &[fd]), ERROR);
+		assert_eq!(fs::read(root.join("out.txt")).unwrap(), b"hello");
+
+		let fd = call(&mut semihost, &mut s, SYS_OPEN, &[STR, MODE_READ]);
+		assert_eq!(call(&mut semihost, &mut s, SYS_SEEK, &[fd, 1, 0]), 1);
+		assert_eq!(call(&mut semihost, &mut s, SYS_READ, &[fd, BUF + 0x10, 0x10]), 4);
+
+		let mut buf = [0u8; 4];
+		assert!(s.read_bytes(BUF + 0x10, &mut buf).is_ok());
+		assert_eq!(&buf, b"ello");
+
+		fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn test_args() {
+		let mut s = state();
+		let mut semihost = Semihost::new("/sandbox");
+		semihost.args = vec!["prog".to_string(), "--verbose".to_string()];
+		semihost.env = vec![("HOME".to_string(), "/home/guest".to_string())];
+
+		assert_eq!(call(&mut semihost, &mut s, SYS_ARGC, &[]), 2);
+		assert_eq!(call(&mut semihost, &mut s, SYS_ARGV, &[1, BUF, 0x20]), 9);
+		assert_eq!(call(&mut semihost, &mut s, SYS_ARGV, &[2, BUF, 0x20]), ERROR);
+
+		let mut buf = [0u8; 10];
+		assert!(s.read_bytes(BUF, &mut buf).is_ok());
+		assert_eq!(&buf, b"--verbose\0");
+
+		// Too small for the value and terminator
+		assert!(s.write_cstr(STR, b"HOME").is_ok());
+		assert_eq!(call(&mut semihost, &mut s, SYS_GETENV, &[STR, BUF, 11]), 11);
+		assert_eq!(call(&mut semihost, &mut s, SYS_GETENV, &[STR, BUF, 12]), 11);
+		assert!(s.read_bytes(BUF, &mut buf).is_ok());
+		assert_eq!(&buf, b"/home/gues");
+	}
+
+	#[test]
+	fn test_exit() {
+		let mut s = state();
+		let mut semihost = Semihost::new("/sandbox");
+
+		call(&mut semihost, &mut s, SYS_EXIT, &[-3i32 as u32]);
+		assert!(matches!(s.stop_reason(), Some(StopReason::Exit(-3))));
+	}
+}
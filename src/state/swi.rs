@@ -0,0 +1,47 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+use bibe_instr::{
+	csr::{
+		regs::*,
+		Instruction,
+	},
+	LoadStore,
+};
+
+use crate::{
+	memory::Memory,
+	target::Target,
+	Interrupt,
+	Result,
+};
+
+use super::{
+	csr::CsrCollection,
+	State,
+};
+
+/// SWI and ISR exit are encoded as stores to the ISR enter and exit registers
+pub(super) fn is_swi(instr: &Instruction) -> bool {
+	!instr.op.is_load() && (instr.imm == ISR_ENTER_REG || instr.imm == ISR_EXIT_REG)
+}
+
+/// Raise the interrupt that performs the bank swap, see `State::handle_interrupt`
+///
+/// The value stored by a SWI is passed to the handler in `ISR_ERR1_REG`, so it can dispatch
+/// on a service number.
+pub(super) fn execute<T, M, C>(s: &mut State<T, M, C>, instr: &Instruction) -> Result<()>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	s.csr_accessed(LoadStore::Store, instr.imm);
+
+	if instr.imm == ISR_ENTER_REG {
+		Err(Interrupt {
+			err1: s.core().read_reg(instr.reg),
+			..Interrupt::swi()
+		})
+	} else {
+		Err(Interrupt::isr_exit())
+	}
+}
@@ -4,6 +4,8 @@ use crate::{
     Result,
 };
 
+use super::Psr;
+
 use num_derive::{ FromPrimitive, ToPrimitive };
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
@@ -14,28 +16,126 @@ pub(crate) enum CmpResult {
 	Eq,
 }
 
-pub struct BinOpOverflow {
-	pub overflow: bool,
-	pub carry: bool,
+/// PSR flags written by an ALU operation
+///
+/// N and Z always reflect the result, `None` leaves C or V unchanged. Only `cc` operations
+/// write the PSR, the model is defined for every `BinOp` so that new `cc` forms follow it.
+///
+/// | Operation           | C                                      | V                         |
+/// |---------------------|----------------------------------------|---------------------------|
+/// | Add                 | unsigned carry out                     | signed overflow           |
+/// | Sub                 | borrow, set if `lhs < rhs` unsigned    | signed overflow           |
+/// | Mul                 | unsigned result doesn't fit in 32 bits | signed overflow           |
+/// | Div, Mod            | cleared                                | cleared                   |
+/// | And, Or, Xor, Not   | cleared                                | cleared                   |
+/// | Shl, Asl            | last bit shifted out, 0 past 32        | Asl: signed overflow      |
+/// | Shr                 | last bit shifted out, 0 past 32        | cleared                   |
+/// | Asr                 | last bit shifted out, sign past 32     | cleared                   |
+/// | Rol                 | bit 0 of the result                    | cleared                   |
+/// | Ror                 | bit 31 of the result                   | cleared                   |
+/// | Neg                 | borrow, set if the operand is non-zero | set for `i32::MIN`        |
+///
+/// Shifts and rotates by zero leave C unchanged. Neg and Not operate on `lhs + rhs`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Flags {
+	pub n: bool,
+	pub z: bool,
+	pub c: Option<bool>,
+	pub v: Option<bool>,
+}
+
+impl Flags {
+	pub fn apply(&self, psr: &mut Psr) {
+		psr.set_n(self.n as u32);
+		psr.set_z(self.z as u32);
+
+		if let Some(c) = self.c {
+			psr.set_c(c as u32);
+		}
+
+		if let Some(v) = self.v {
+			psr.set_v(v as u32);
+		}
+	}
 }
 
-pub(crate) fn check_binop(op: BinOp, lhs: u32, rhs: u32) -> BinOpOverflow {
+/// Last bit shifted out of `value`, `None` for a shift by zero
+fn shift_carry(op: BinOp, value: u32, amount: u32, res: u32) -> Option<bool> {
+	if amount == 0 {
+		return None;
+	}
+
+	Some(match op {
+		BinOp::Shl
+		| BinOp::Asl => amount <= 32 && (value >> (32 - amount)) & 1 == 1,
+		BinOp::Shr => amount <= 32 && (value >> (amount - 1)) & 1 == 1,
+		BinOp::Asr => ((value as i32) >> (amount.min(32) - 1)) & 1 == 1,
+		BinOp::Rol => res & 1 == 1,
+		BinOp::Ror => res >> 31 == 1,
+		_ => unreachable!(),
+	})
+}
+
+/// Flags produced by `op` with the given operands and result, see `Flags`
+pub(crate) fn binop_flags(op: BinOp, lhs: u32, rhs: u32, res: u32) -> Flags {
 	let ilhs = lhs as i32;
 	let irhs = rhs as i32;
 
-	match op {
-		BinOp::Addcc => BinOpOverflow {
-			overflow: ilhs.overflowing_add(irhs).1,
-			carry: lhs.overflowing_add(rhs).1
+	let (c, v) = match op {
+		BinOp::Add
+		| BinOp::Addcc => (Some(lhs.overflowing_add(rhs).1), Some(ilhs.overflowing_add(irhs).1)),
+		BinOp::Sub
+		| BinOp::Subcc => (Some(lhs < rhs), Some(ilhs.overflowing_sub(irhs).1)),
+		BinOp::Mul => (Some(lhs.overflowing_mul(rhs).1), Some(ilhs.overflowing_mul(irhs).1)),
+		BinOp::Div
+		| BinOp::Mod
+		| BinOp::And
+		| BinOp::Or
+		| BinOp::Xor
+		| BinOp::Not => (Some(false), Some(false)),
+		BinOp::Asl => {
+			let overflow = if rhs < 32 {
+				((lhs << rhs) as i32) >> rhs != ilhs
+			} else {
+				lhs != 0
+			};
+
+			(shift_carry(op, lhs, rhs, res), Some(overflow))
 		},
-		BinOp::Subcc => BinOpOverflow {
-			overflow: ilhs.overflowing_sub(irhs).1,
-			carry: lhs.overflowing_sub(rhs).1
+		BinOp::Shl
+		| BinOp::Shr
+		| BinOp::Asr
+		| BinOp::Rol
+		| BinOp::Ror => (shift_carry(op, lhs, rhs, res), Some(false)),
+		BinOp::Neg => {
+			let operand = lhs.wrapping_add(rhs);
+			(Some(operand != 0), Some(operand == 0x80000000))
 		},
-		_ => BinOpOverflow { overflow: false, carry: false },
+	};
+
+	Flags {
+		n: (res as i32) < 0,
+		z: res == 0,
+		c,
+		v,
 	}
 }
 
+/// Logical shift left, shifting by 32 or more produces 0
+pub(crate) fn shl(value: u32, amount: u32) -> u32 {
+	value.checked_shl(amount).unwrap_or(0)
+}
+
+/// Logical shift right, shifting by 32 or more produces 0
+pub(crate) fn shr(value: u32, amount: u32) -> u32 {
+	value.checked_shr(amount).unwrap_or(0)
+}
+
+/// Arithmetic shift right, shifting by 32 or more fills with the sign bit
+pub(crate) fn asr(value: u32, amount: u32) -> u32 {
+	((value as i32) >> amount.min(31)) as u32
+}
+
 pub(crate) fn execute_binop(op: BinOp, lhs: u32, rhs: u32) -> Result<u32> {
 	match op {
 		BinOp::Add
@@ -43,27 +143,91 @@ pub(crate) fn execute_binop(op: BinOp, lhs: u32, rhs: u32) -> Result<u32> {
 		BinOp::Sub 
 		| BinOp::Subcc => Ok(lhs.wrapping_sub(rhs)),
 		BinOp::Mul => Ok(lhs.wrapping_mul(rhs)),
-		BinOp::Div => if rhs == 0 {
-			Err(Interrupt::opcode())
-		}
-		else {
-			Ok(lhs.wrapping_div(lhs / rhs))
-		},
-			
-		BinOp::Mod => Ok(lhs.wrapping_rem(rhs)),
+		// The ISA has no divide by zero exception, it's reported as an invalid instruction
+		BinOp::Div => lhs.checked_div(rhs).ok_or_else(Interrupt::opcode),
+		BinOp::Mod => lhs.checked_rem(rhs).ok_or_else(Interrupt::opcode),
 
 		BinOp::And => Ok(lhs & rhs),
 		BinOp::Or => Ok(lhs | rhs),
 		BinOp::Xor => Ok(lhs ^ rhs),
 
-		BinOp::Shl => Ok(lhs << rhs),
-		BinOp::Shr => Ok(lhs >> rhs),
-		BinOp::Asl => Ok(((lhs as i32) >> rhs) as u32),
-		BinOp::Asr => Ok(((lhs as i32) << rhs) as u32),
+		// Arithmetic and logical left shifts only differ in how they set V
+		BinOp::Shl
+		| BinOp::Asl => Ok(shl(lhs, rhs)),
+		BinOp::Shr => Ok(shr(lhs, rhs)),
+		BinOp::Asr => Ok(asr(lhs, rhs)),
 		BinOp::Rol => Ok(lhs.rotate_left(rhs)),
 		BinOp::Ror => Ok(lhs.rotate_right(rhs)),
 
-		BinOp::Not => Ok(!(lhs + rhs)),
-		BinOp::Neg => Ok(-((lhs + rhs) as i32) as u32),
+		BinOp::Not => Ok(!lhs.wrapping_add(rhs)),
+		BinOp::Neg => Ok(lhs.wrapping_add(rhs).wrapping_neg()),
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn flags(n: bool, z: bool, c: Option<bool>, v: Option<bool>) -> Flags {
+		Flags { n, z, c, v }
+	}
+
+	#[test]
+	fn test_flags() {
+		const T: Option<bool> = Some(true);
+		const F: Option<bool> = Some(false);
+		const U: Option<bool> = None;
+
+		// Operation, lhs, rhs, result, expected flags
+		let golden = [
+			(BinOp::Addcc, 0xffffffff, 1, 0, flags(false, true, T, F)),
+			(BinOp::Addcc, 0x7fffffff, 1, 0x80000000, flags(true, false, F, T)),
+			(BinOp::Addcc, 1, 2, 3, flags(false, false, F, F)),
+			(BinOp::Addcc, 0x80000000, 0x80000000, 0, flags(false, true, T, T)),
+			(BinOp::Subcc, 0, 1, 0xffffffff, flags(true, false, T, F)),
+			(BinOp::Subcc, 0x80000000, 1, 0x7fffffff, flags(false, false, F, T)),
+			(BinOp::Subcc, 5, 5, 0, flags(false, true, F, F)),
+			(BinOp::Subcc, 1, 0xffffffff, 2, flags(false, false, T, F)),
+			(BinOp::Mul, 0x10000, 0x10000, 0, flags(false, true, T, T)),
+			(BinOp::Mul, 0xffffffff, 0xffffffff, 1, flags(false, false, T, F)),
+			(BinOp::Mul, 0x40000000, 2, 0x80000000, flags(true, false, F, T)),
+			(BinOp::Div, 7, 2, 3, flags(false, false, F, F)),
+			(BinOp::And, 0xffffffff, 0, 0, flags(false, true, F, F)),
+			(BinOp::Xor, 0xffffffff, 0, 0xffffffff, flags(true, false, F, F)),
+			(BinOp::Shl, 0x80000001, 1, 2, flags(false, false, T, F)),
+			(BinOp::Shl, 1, 0, 1, flags(false, false, U, F)),
+			(BinOp::Shl, 1, 32, 0, flags(false, true, T, F)),
+			(BinOp::Shl, 1, 33, 0, flags(false, true, F, F)),
+			(BinOp::Asl, 0x40000000, 1, 0x80000000, flags(true, false, F, T)),
+			(BinOp::Asl, 0xc0000000, 1, 0x80000000, flags(true, false, T, F)),
+			(BinOp::Shr, 3, 1, 1, flags(false, false, T, F)),
+			(BinOp::Shr, 0x80000000, 32, 0, flags(false, true, T, F)),
+			(BinOp::Shr, 0x80000000, 40, 0, flags(false, true, F, F)),
+			(BinOp::Asr, 0x80000000, 40, 0xffffffff, flags(true, false, T, F)),
+			(BinOp::Asr, 5, 1, 2, flags(false, false, T, F)),
+			(BinOp::Rol, 0x80000000, 1, 1, flags(false, false, T, F)),
+			(BinOp::Rol, 1, 32, 1, flags(false, false, T, F)),
+			(BinOp::Ror, 1, 1, 0x80000000, flags(true, false, T, F)),
+			(BinOp::Ror, 2, 1, 1, flags(false, false, F, F)),
+			(BinOp::Neg, 0, 0, 0, flags(false, true, F, F)),
+			(BinOp::Neg, 0x80000000, 0, 0x80000000, flags(true, false, T, T)),
+			(BinOp::Neg, 1, 0, 0xffffffff, flags(true, false, T, F)),
+		];
+
+		for (op, lhs, rhs, res, expected) in golden {
+			assert_eq!(binop_flags(op, lhs, rhs, res), expected, "{op:?} {lhs:#x} {rhs:#x}");
+		}
+	}
+
+	#[test]
+	fn test_apply() {
+		let mut psr = Psr(0xf);
+
+		// Unchanged flags survive, computed flags are cleared as well as set
+		flags(false, false, None, Some(false)).apply(&mut psr);
+		assert_eq!(psr.0, 0b0010);
+
+		flags(true, true, Some(false), Some(true)).apply(&mut psr);
+		assert_eq!(psr.0, 0b1101);
+	}
+}
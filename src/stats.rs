@@ -0,0 +1,448 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Instruction mix and memory access statistics
+//!
+//! Attach a `Statistics` to a state with `State::add_observer`. Counts are read through its
+//! accessors, and its `Display` implementation is a summary meant to be printed at exit.
+#![cfg(feature = "std")]
+extern crate std;
+
+use core::{
+	cmp::Reverse,
+	fmt,
+	mem::discriminant,
+};
+use std::{
+	collections::BTreeMap,
+	format,
+	vec::Vec,
+};
+
+use bibe_instr::{
+	memory,
+	BinOp,
+	Condition,
+	Instruction,
+	LoadStore,
+	Width,
+};
+
+use crate::{
+	state::{
+		condition,
+		observer::{
+			Access,
+			CsrAccess,
+			Observer,
+			Retired,
+		},
+	},
+	Interrupt,
+	InterruptKind,
+};
+
+/// Buckets in the address histogram of a region
+const HISTOGRAM_BUCKETS: u32 = 16;
+
+/// Counts keyed by enum variant, keeping a value of each variant to show it
+///
+/// Variants are kept in the order they were first counted, so iteration is deterministic.
+#[derive(Debug)]
+pub struct Counter<T, C = u64> {
+	counts: Vec<(T, C)>,
+}
+
+impl<T, C> Default for Counter<T, C> {
+	fn default() -> Self {
+		Self {
+			counts: Vec::new(),
+		}
+	}
+}
+
+impl<T: Copy, C: Default> Counter<T, C> {
+	fn position(&self, value: T) -> Option<usize> {
+		self.counts.iter().position(|(v, _)| discriminant(v) == discriminant(&value))
+	}
+
+	fn entry(&mut self, value: T) -> &mut C {
+		let index = match self.position(value) {
+			Some(index) => index,
+			None => {
+				self.counts.push((value, C::default()));
+				self.counts.len() - 1
+			},
+		};
+
+		&mut self.counts[index].1
+	}
+
+	pub fn get(&self, value: T) -> Option<&C> {
+		self.position(value).map(|index| &self.counts[index].1)
+	}
+
+	/// Counts in the order their variants were first counted
+	pub fn iter(&self) -> impl Iterator<Item = &(T, C)> {
+		self.counts.iter()
+	}
+}
+
+/// Instruction formats counted by `Statistics::format`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum InstructionFormat {
+	Rrr,
+	Rri,
+	MemoryRr,
+	MemoryRi,
+	Csr,
+	Jump,
+	Other,
+}
+
+impl InstructionFormat {
+	pub fn of(instr: &Instruction) -> Self {
+		match instr {
+			Instruction::Rrr(_) => Self::Rrr,
+			Instruction::Rri(_) => Self::Rri,
+			Instruction::Memory(memory::Instruction::Rr(_)) => Self::MemoryRr,
+			Instruction::Memory(memory::Instruction::Ri(_)) => Self::MemoryRi,
+			Instruction::Csr(_) => Self::Csr,
+			Instruction::Jump(_) => Self::Jump,
+			_ => Self::Other,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Rrr => "rrr",
+			Self::Rri => "rri",
+			Self::MemoryRr => "memory rr",
+			Self::MemoryRi => "memory ri",
+			Self::Csr => "csr",
+			Self::Jump => "jump",
+			Self::Other => "other",
+		}
+	}
+}
+
+/// Executed and taken counts of a condition
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConditionCounts {
+	pub executed: u64,
+	pub taken: u64,
+}
+
+/// Loads and stores
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessCounts {
+	pub loads: u64,
+	pub stores: u64,
+}
+
+impl AccessCounts {
+	fn add(&mut self, op: LoadStore) {
+		match op {
+			LoadStore::Load => self.loads += 1,
+			LoadStore::Store => self.stores += 1,
+		}
+	}
+
+	pub fn total(&self) -> u64 {
+		self.loads + self.stores
+	}
+}
+
+/// Memory traffic of one region
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionStats {
+	pub start: u32,
+	pub size: u32,
+	pub accesses: AccessCounts,
+	/// Accesses per `bucket_size` bytes, keyed by bucket start address
+	pub histogram: BTreeMap<u32, u64>,
+	pub bucket_size: u32,
+}
+
+impl RegionStats {
+	fn new(start: u32, size: u32) -> Self {
+		// Power of two so buckets line up with addresses
+		let bucket_size = size.div_ceil(HISTOGRAM_BUCKETS).max(4).next_power_of_two();
+
+		Self {
+			start,
+			size,
+			accesses: AccessCounts::default(),
+			histogram: BTreeMap::new(),
+			bucket_size,
+		}
+	}
+
+	fn contains(&self, addr: u32) -> bool {
+		addr.wrapping_sub(self.start) < self.size
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct Statistics {
+	instructions: u64,
+	formats: BTreeMap<InstructionFormat, u64>,
+	binops: Counter<BinOp>,
+	conditions: Counter<Condition, ConditionCounts>,
+	widths: Counter<Width, AccessCounts>,
+	/// Keyed by `CsrBlock::name`
+	csr_blocks: BTreeMap<&'static str, AccessCounts>,
+	/// In the order they were first taken
+	interrupts: Vec<(InterruptKind, u64)>,
+	regions: Vec<RegionStats>,
+	/// Accesses outside every region
+	unmapped: AccessCounts,
+}
+
+impl Statistics {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Collect a histogram of the accesses to the `size` bytes at `start`
+	pub fn add_region(&mut self, start: u32, size: u32) {
+		self.regions.push(RegionStats::new(start, size));
+	}
+
+	/// Statistics with a histogram of every region in `regions`, e.g. `Mapped::regions`
+	pub fn with_regions<I: IntoIterator<Item = (u32, u32)>>(regions: I) -> Self {
+		let mut stats = Self::new();
+		for (start, size) in regions {
+			stats.add_region(start, size);
+		}
+		stats
+	}
+
+	pub fn instructions(&self) -> u64 {
+		self.instructions
+	}
+
+	/// Retired instructions of the given format
+	pub fn format(&self, format: InstructionFormat) -> u64 {
+		self.formats.get(&format).copied().unwrap_or(0)
+	}
+
+	/// ALU operations whose condition held
+	pub fn binops(&self) -> &Counter<BinOp> {
+		&self.binops
+	}
+
+	pub fn conditions(&self) -> &Counter<Condition, ConditionCounts> {
+		&self.conditions
+	}
+
+	/// Data accesses per width
+	pub fn widths(&self) -> &Counter<Width, AccessCounts> {
+		&self.widths
+	}
+
+	/// CSR reads and writes per `CsrBlock::name`
+	pub fn csr_blocks(&self) -> &BTreeMap<&'static str, AccessCounts> {
+		&self.csr_blocks
+	}
+
+	/// Interrupts taken of each kind, in the order they were first taken
+	pub fn interrupts(&self) -> &[(InterruptKind, u64)] {
+		&self.interrupts
+	}
+
+	pub fn interrupt_count(&self, kind: InterruptKind) -> u64 {
+		self.interrupts.iter().find(|(k, _)| *k == kind).map_or(0, |(_, count)| *count)
+	}
+
+	pub fn regions(&self) -> &[RegionStats] {
+		&self.regions
+	}
+
+	/// Data accesses outside every region
+	pub fn unmapped(&self) -> AccessCounts {
+		self.unmapped
+	}
+}
+
+impl Observer for Statistics {
+	fn retired(&mut self, event: &Retired) {
+		self.instructions += 1;
+		*self.formats.entry(InstructionFormat::of(event.instr)).or_default() += 1;
+
+		match event.instr {
+			Instruction::Rrr(i) if event.passed => *self.binops.entry(i.op) += 1,
+			Instruction::Rri(i) if event.passed => *self.binops.entry(i.op) += 1,
+			_ => (),
+		}
+
+		if let Some(cond) = condition(event.instr) {
+			let counts = self.conditions.entry(cond);
+			counts.executed += 1;
+			counts.taken += event.passed as u64;
+		}
+	}
+
+	fn memory_access(&mut self, access: &Access) {
+		self.widths.entry(access.width).add(access.op);
+
+		match self.regions.iter_mut().find(|region| region.contains(access.addr)) {
+			Some(region) => {
+				region.accesses.add(access.op);
+				let bucket = access.addr & !(region.bucket_size - 1);
+				*region.histogram.entry(bucket).or_default() += 1;
+			},
+			None => self.unmapped.add(access.op),
+		}
+	}
+
+	fn csr_access(&mut self, access: &CsrAccess) {
+		self.csr_blocks.entry(access.block).or_default().add(access.op);
+	}
+
+	fn interrupt(&mut self, interrupt: &Interrupt) {
+		match self.interrupts.iter_mut().find(|(kind, _)| *kind == interrupt.kind) {
+			Some((_, count)) => *count += 1,
+			None => self.interrupts.push((interrupt.kind, 1)),
+		}
+	}
+}
+
+/// Share of `count` in `total` as a percentage
+fn percent(count: u64, total: u64) -> f64 {
+	match total {
+		0 => 0.0,
+		total => 100.0 * count as f64 / total as f64,
+	}
+}
+
+/// Entries sorted by descending count, ties keep the order they were first counted in
+fn sorted<T: Copy, C: Copy, K: Fn(&C) -> u64>(counter: &Counter<T, C>, key: K) -> Vec<(T, C)> {
+	let mut entries: Vec<(T, C)> = counter.iter().copied().collect();
+	entries.sort_by_key(|(_, count)| Reverse(key(count)));
+	entries
+}
+
+impl fmt::Display for Statistics {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let total = self.instructions;
+		writeln!(f, "instructions: {total}")?;
+
+		writeln!(f, "formats:")?;
+		for (format, count) in &self.formats {
+			writeln!(f, "  {:<12} {count:>12} {:>6.2}%", format.name(), percent(*count, total))?;
+		}
+
+		writeln!(f, "ALU operations:")?;
+		for (op, count) in sorted(&self.binops, |count| *count) {
+			writeln!(f, "  {:<12} {count:>12} {:>6.2}%", format!("{op:?}"), percent(count, total))?;
+		}
+
+		writeln!(f, "conditions:")?;
+		for (cond, counts) in sorted(&self.conditions, |counts| counts.executed) {
+			writeln!(
+				f,
+				"  {:<12} {:>12} taken {:>12} {:>6.2}%",
+				format!("{cond:?}"), counts.executed, counts.taken, percent(counts.taken, counts.executed),
+			)?;
+		}
+
+		writeln!(f, "data accesses:")?;
+		for (width, counts) in sorted(&self.widths, AccessCounts::total) {
+			writeln!(f, "  {:<12} loads {:>12} stores {:>12}", format!("{width:?}"), counts.loads, counts.stores)?;
+		}
+
+		if !self.csr_blocks.is_empty() {
+			writeln!(f, "CSR accesses:")?;
+			for (block, counts) in &self.csr_blocks {
+				writeln!(f, "  {block:<12} reads {:>12} writes {:>12}", counts.loads, counts.stores)?;
+			}
+		}
+
+		if !self.interrupts.is_empty() {
+			writeln!(f, "interrupts:")?;
+			for (kind, count) in &self.interrupts {
+				writeln!(f, "  {:<12} {count:>12}", format!("{kind:?}"))?;
+			}
+		}
+
+		for region in self.regions.iter().filter(|region| region.accesses.total() > 0) {
+			writeln!(
+				f,
+				"region {:08x}..{:08x}: loads {} stores {}",
+				region.start, region.start as u64 + region.size as u64, region.accesses.loads, region.accesses.stores,
+			)?;
+
+			let busiest = region.histogram.values().copied().max().unwrap_or(1);
+			for (bucket, count) in &region.histogram {
+				// Bars are scaled to the busiest bucket
+				let bar = (40 * count).div_ceil(busiest) as usize;
+				writeln!(f, "  {bucket:08x} {count:>12} {:#<bar$}", "")?;
+			}
+		}
+
+		if self.unmapped.total() > 0 {
+			writeln!(f, "outside regions: loads {} stores {}", self.unmapped.loads, self.unmapped.stores)?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	fn access(stats: &mut Statistics, op: LoadStore, addr: u32, width: Width) {
+		stats.memory_access(&Access { op, addr, width });
+	}
+
+	#[test]
+	fn test_regions() {
+		let mut stats = Statistics::with_regions([(0, 0x1000), (0x8000, 0x100)]);
+		assert_eq!(stats.regions()[0].bucket_size, 0x100);
+		assert_eq!(stats.regions()[1].bucket_size, 0x10);
+
+		access(&mut stats, LoadStore::Load, 0x10, Width::Word);
+		access(&mut stats, LoadStore::Store, 0xfc, Width::Word);
+		access(&mut stats, LoadStore::Load, 0x804, Width::Byte);
+		access(&mut stats, LoadStore::Store, 0x80ff, Width::Byte);
+		access(&mut stats, LoadStore::Load, 0x9000, Width::Short);
+
+		let ram = &stats.regions()[0];
+		assert_eq!(ram.accesses, AccessCounts { loads: 2, stores: 1 });
+		assert_eq!(ram.histogram.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>(), [(0, 2), (0x800, 1)]);
+		assert_eq!(stats.regions()[1].histogram.get(&0x80f0), Some(&1));
+		assert_eq!(stats.unmapped(), AccessCounts { loads: 1, stores: 0 });
+
+		assert_eq!(stats.widths().get(Width::Word), Some(&AccessCounts { loads: 1, stores: 1 }));
+		assert_eq!(stats.widths().get(Width::Byte), Some(&AccessCounts { loads: 1, stores: 1 }));
+		assert_eq!(stats.widths().get(Width::Short), Some(&AccessCounts { loads: 1, stores: 0 }));
+	}
+
+	#[test]
+	fn test_interrupts() {
+		let mut stats = Statistics::new();
+		stats.interrupt(&Interrupt::swi());
+		stats.interrupt(&Interrupt::irq(3));
+		stats.interrupt(&Interrupt::swi());
+		stats.csr_access(&CsrAccess { op: LoadStore::Load, reg: 0, block: "psr" });
+
+		assert_eq!(stats.interrupts(), [(InterruptKind::Swi, 2), (InterruptKind::Irq(3), 1)]);
+		assert_eq!(stats.interrupt_count(InterruptKind::Irq(4)), 0);
+		assert_eq!(stats.csr_blocks().get("psr"), Some(&AccessCounts { loads: 1, stores: 0 }));
+	}
+
+	#[test]
+	fn test_sorted_ties() {
+		let mut counter = Counter::default();
+		*counter.entry(BinOp::Sub) += 1;
+		*counter.entry(BinOp::Add) += 2;
+		*counter.entry(BinOp::Xor) += 1;
+		*counter.entry(BinOp::And) += 1;
+
+		// Equal counts keep the order they were first counted in
+		let order: Vec<_> = sorted(&counter, |count| *count).iter()
+			.map(|(op, count)| format!("{op:?} {count}"))
+			.collect();
+		assert_eq!(order, ["Add 2", "Sub 1", "Xor 1", "And 1"]);
+	}
+}
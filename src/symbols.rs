@@ -0,0 +1,265 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Address to symbol maps
+//!
+//! Maps can be built from an ELF symbol table, from a text file with one `addr name` pair
+//! per line, or from any list of labels such as the one bibe-asm builds while linking.
+#![cfg(feature = "std")]
+extern crate std;
+
+use core::fmt;
+use std::{
+	string::{
+		String,
+		ToString,
+	},
+	vec::Vec,
+};
+
+use crate::{
+	loader::{
+		elf_symbols,
+		LoadError,
+	},
+	trace::Symbols,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+	pub name: String,
+	pub addr: u32,
+	/// Size in bytes, zero if unknown. Symbols without a size extend to the next symbol
+	pub size: u32,
+}
+
+#[derive(Debug)]
+pub enum SymbolError {
+	/// Line of a text map isn't an `addr name` pair
+	Malformed { line: usize },
+	Elf(LoadError),
+}
+
+impl fmt::Display for SymbolError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SymbolError::Malformed { line } => write!(f, "line {line}: expected an address and a name"),
+			SymbolError::Elf(e) => write!(f, "{e}"),
+		}
+	}
+}
+
+/// Symbols sorted by address
+#[derive(Clone, Debug, Default)]
+pub struct SymbolMap {
+	symbols: Vec<Symbol>,
+}
+
+impl SymbolMap {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Symbols of a little endian ELF32 file
+	pub fn from_elf(data: &[u8]) -> Result<Self, SymbolError> {
+		let mut map = Self::new();
+		elf_symbols(data, |name, addr, size| map.push(name, addr, size)).map_err(SymbolError::Elf)?;
+		map.sort();
+		Ok(map)
+	}
+
+	/// Parse `addr name` lines, addresses are hex with an optional `0x` prefix
+	///
+	/// Blank lines and lines starting with `#` are ignored. `nm` output, where a type letter
+	/// separates the address and name, is also accepted.
+	pub fn from_text(src: &str) -> Result<Self, SymbolError> {
+		let mut map = Self::new();
+
+		for (i, line) in src.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let malformed = SymbolError::Malformed { line: i + 1 };
+			let fields: Vec<&str> = line.split_whitespace().collect();
+			let (addr, name) = match fields.as_slice() {
+				[addr, name] | [addr, _, name] => (*addr, *name),
+				_ => return Err(malformed),
+			};
+
+			let addr = addr.strip_prefix("0x").unwrap_or(addr);
+			let addr = u32::from_str_radix(addr, 16).map_err(|_| malformed)?;
+			map.push(name, addr, 0);
+		}
+
+		map.sort();
+		Ok(map)
+	}
+
+	/// Labels and their addresses, e.g. the symbol table bibe-asm links against
+	pub fn from_labels<I, S>(labels: I) -> Self
+	where
+		I: IntoIterator<Item = (S, u32)>,
+		S: ToString,
+	{
+		let mut map = Self::new();
+		for (name, addr) in labels {
+			map.push(&name.to_string(), addr, 0);
+		}
+		map.sort();
+		map
+	}
+
+	/// Add a single symbol, keeping the map sorted
+	pub fn insert(&mut self, name: &str, addr: u32, size: u32) {
+		let i = self.symbols.partition_point(|s| s.addr <= addr);
+		self.symbols.insert(i, Symbol {
+			name: name.to_string(),
+			addr,
+			size,
+		});
+	}
+
+	/// Append a symbol without keeping the map sorted, `sort` must be called before lookups
+	fn push(&mut self, name: &str, addr: u32, size: u32) {
+		self.symbols.push(Symbol {
+			name: name.to_string(),
+			addr,
+			size,
+		});
+	}
+
+	/// Stable, so symbols at the same address keep the order they were added in
+	fn sort(&mut self) {
+		self.symbols.sort_by_key(|s| s.addr);
+	}
+
+	/// Add the symbols of `other`, e.g. when several images are loaded into one machine
+	pub fn merge(&mut self, other: SymbolMap) {
+		self.symbols.extend(other.symbols);
+		self.sort();
+	}
+
+	pub fn len(&self) -> usize {
+		self.symbols.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.symbols.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+		self.symbols.iter()
+	}
+
+	/// Address of the symbol called `name`
+	pub fn address_of(&self, name: &str) -> Option<u32> {
+		self.symbols.iter().find(|s| s.name == name).map(|s| s.addr)
+	}
+
+	/// Symbol containing `addr`
+	pub fn containing(&self, addr: u32) -> Option<&Symbol> {
+		let i = self.symbols.partition_point(|s| s.addr <= addr).checked_sub(1)?;
+		let symbol = &self.symbols[i];
+
+		if symbol.size == 0 || addr - symbol.addr < symbol.size {
+			Some(symbol)
+		} else {
+			None
+		}
+	}
+
+	/// Displays `addr` as `00001234 <name+0x10>`, or just the address if it has no symbol
+	pub fn describe(&self, addr: u32) -> Described<'_> {
+		Described {
+			addr,
+			symbol: self.lookup(addr),
+		}
+	}
+}
+
+impl Symbols for SymbolMap {
+	fn lookup(&self, addr: u32) -> Option<(&str, u32)> {
+		self.containing(addr).map(|s| (s.name.as_str(), addr - s.addr))
+	}
+}
+
+/// See `SymbolMap::describe`
+#[derive(Copy, Clone, Debug)]
+pub struct Described<'a> {
+	pub addr: u32,
+	pub symbol: Option<(&'a str, u32)>,
+}
+
+impl fmt::Display for Described<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:08x}", self.addr)?;
+		match self.symbol {
+			Some((name, 0)) => write!(f, " <{name}>"),
+			Some((name, offset)) => write!(f, " <{name}+{offset:#x}>"),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn test_lookup() {
+		let mut map = SymbolMap::new();
+		map.insert("data", 0x2000, 4);
+		map.insert("main", 0x100, 0);
+		map.insert("start", 0, 0);
+
+		assert_eq!(map.lookup(0x0), Some(("start", 0)));
+		assert_eq!(map.lookup(0xfc), Some(("start", 0xfc)));
+		assert_eq!(map.lookup(0x104), Some(("main", 4)));
+		assert_eq!(map.lookup(0x2003), Some(("data", 3)));
+
+		// Sized symbols end where their size says
+		assert_eq!(map.lookup(0x2004), None);
+		assert_eq!(map.address_of("main"), Some(0x100));
+		assert_eq!(map.address_of("missing"), None);
+
+		assert_eq!(std::format!("{}", map.describe(0x108)), "00000108 <main+0x8>");
+		assert_eq!(std::format!("{}", map.describe(0x2000)), "00002000 <data>");
+		assert_eq!(std::format!("{}", map.describe(0x3000)), "00003000");
+	}
+
+	#[test]
+	fn test_text() {
+		let map = SymbolMap::from_text("\
+			# comment\n\
+			0x100 main\n\
+			\n\
+			00000200 T helper\n\
+		").unwrap();
+
+		assert_eq!(map.len(), 2);
+		assert_eq!(map.address_of("main"), Some(0x100));
+		assert_eq!(map.address_of("helper"), Some(0x200));
+
+		assert!(matches!(SymbolMap::from_text("0x100 main\nzz name\n"), Err(SymbolError::Malformed { line: 2 })));
+		assert!(matches!(SymbolMap::from_text("main\n"), Err(SymbolError::Malformed { line: 1 })));
+	}
+
+	#[test]
+	fn test_merge() {
+		let mut map = SymbolMap::from_text("0x100 main\n0x0 start\n").unwrap();
+		map.merge(SymbolMap::from_text("0x2000 driver\n0x80 vectors\n").unwrap());
+
+		assert_eq!(map.len(), 4);
+		let addrs: Vec<u32> = map.iter().map(|s| s.addr).collect();
+		assert_eq!(addrs, [0, 0x80, 0x100, 0x2000]);
+		assert_eq!(map.lookup(0x84), Some(("vectors", 4)));
+		assert_eq!(map.lookup(0x2010), Some(("driver", 0x10)));
+	}
+
+	#[test]
+	fn test_labels() {
+		let map = SymbolMap::from_labels([("loop", 0xc), ("end", 0x28)]);
+		assert_eq!(map.lookup(0x10), Some(("loop", 4)));
+		assert_eq!(map.lookup(0x28), Some(("end", 0)));
+	}
+}
@@ -1,13 +1,77 @@
-use bibe_instr::BinOp;
+use bibe_instr::{
+	memory,
+	BinOp,
+	Instruction,
+	LoadStore,
+};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Extension {
 	IntegerMultplication,
 }
 
+/// Groups of instructions that share timing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InstructionClass {
+	Alu,
+	Multiply,
+	Divide,
+	Load,
+	Store,
+	Csr,
+	Branch,
+	Other,
+}
+
+impl InstructionClass {
+	pub fn of(instr: &Instruction) -> Self {
+		match instr {
+			Instruction::Rrr(i) => Self::of_binop(i.op),
+			Instruction::Rri(i) => Self::of_binop(i.op),
+			Instruction::Memory(memory::Instruction::Rr(i)) => Self::of_load_store(&i.op.op),
+			Instruction::Memory(memory::Instruction::Ri(i)) => Self::of_load_store(&i.op.op),
+			Instruction::Csr(_) => InstructionClass::Csr,
+			Instruction::Jump(_) => InstructionClass::Branch,
+			_ => InstructionClass::Other,
+		}
+	}
+
+	fn of_binop(op: BinOp) -> Self {
+		match op {
+			BinOp::Mul => InstructionClass::Multiply,
+			BinOp::Div
+			| BinOp::Mod => InstructionClass::Divide,
+			_ => InstructionClass::Alu,
+		}
+	}
+
+	fn of_load_store(op: &LoadStore) -> Self {
+		match op {
+			LoadStore::Load => InstructionClass::Load,
+			LoadStore::Store => InstructionClass::Store,
+		}
+	}
+}
+
 pub trait Target {
 	fn supports_binop(&self, op: BinOp) -> bool;
 	fn has_extension(&self, extension: Extension) -> bool;
+
+	/// Cycles taken by an instruction of the given class, not including memory wait states
+	fn cycles(&self, class: InstructionClass) -> u32 {
+		match class {
+			InstructionClass::Multiply => 3,
+			InstructionClass::Divide => 16,
+			InstructionClass::Load
+			| InstructionClass::Store => 2,
+			_ => 1,
+		}
+	}
+
+	/// Extra cycles taken when an instruction changes the flow of execution
+	fn taken_branch_penalty(&self) -> u32 {
+		2
+	}
 }
 
 #[cfg(feature = "std")]
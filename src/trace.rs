@@ -0,0 +1,325 @@
+/* Copyright 2024 Robert Zieba, see LICENSE file for full license. */
+//! Human readable execution traces
+//!
+//! Each step is rendered as a line of bibe-asm with the registers and flags it changed:
+//!
+//! ```text
+//! 00000010 <loop+4>: add.gt %r4, %r2, %r3  ; r4=0x00000005 z=0
+//! ```
+use core::fmt;
+
+use bibe_instr::{
+	memory,
+	BinOp,
+	Condition,
+	Instruction,
+	LoadStore,
+	Register,
+	ShiftKind,
+	Width,
+};
+
+use crate::{
+	abi::RegisterName,
+	memory::Memory,
+	state::{
+		csr::CsrCollection,
+		Psr,
+		State,
+	},
+	target::Target,
+};
+
+/// Resolves addresses to the symbol containing them
+pub trait Symbols {
+	/// Name of the symbol containing `addr` and the offset of `addr` into it
+	fn lookup(&self, addr: u32) -> Option<(&str, u32)>;
+}
+
+fn binop_mnemonic(op: BinOp) -> &'static str {
+	match op {
+		BinOp::Add => "add",
+		BinOp::Addcc => "addcc",
+		BinOp::Sub => "sub",
+		BinOp::Subcc => "subcc",
+		BinOp::Mul => "mul",
+		BinOp::Div => "div",
+		BinOp::Mod => "mod",
+		BinOp::And => "and",
+		BinOp::Or => "or",
+		BinOp::Xor => "xor",
+		BinOp::Shl => "shl",
+		BinOp::Shr => "shr",
+		BinOp::Asl => "asl",
+		BinOp::Asr => "asr",
+		BinOp::Rol => "rol",
+		BinOp::Ror => "ror",
+		BinOp::Not => "not",
+		BinOp::Neg => "neg",
+	}
+}
+
+fn shift_mnemonic(kind: ShiftKind) -> &'static str {
+	match kind {
+		ShiftKind::Shl => "shl",
+		ShiftKind::Shr => "shr",
+		ShiftKind::Asl => "asl",
+		ShiftKind::Asr => "asr",
+		ShiftKind::Rol => "rol",
+		ShiftKind::Ror => "ror",
+	}
+}
+
+/// Suffixes are the comparisons the conditions evaluate, see `ConditionCode`
+fn condition_suffix(cond: Condition) -> &'static str {
+	match cond {
+		Condition::Always => "",
+		Condition::Overflow => ".vs",
+		Condition::Carry => ".cs",
+		Condition::Zero => ".eq",
+		Condition::Negative => ".lt",
+		Condition::NotZero => ".ne",
+		Condition::NotNegative => ".ge",
+		Condition::GreaterThan => ".gt",
+	}
+}
+
+fn width_suffix(width: Width) -> &'static str {
+	match width {
+		Width::Byte => "b",
+		Width::Short => "s",
+		Width::Word => "w",
+	}
+}
+
+fn reg(r: Register) -> RegisterName {
+	RegisterName(r)
+}
+
+/// Displays an instruction in bibe-asm syntax
+///
+/// Without a pc, branches show their displacement in words like the assembler's immediate
+/// operand, use `Line` for absolute targets.
+#[derive(Copy, Clone, Debug)]
+pub struct Disasm<'a>(pub &'a Instruction);
+
+impl Disasm<'_> {
+	fn fmt_at(&self, f: &mut fmt::Formatter, pc: Option<u32>, symbols: Option<&dyn Symbols>) -> fmt::Result {
+		match self.0 {
+			Instruction::Rrr(i) => {
+				write!(f, "{}{} %{}, %{}, %{}", binop_mnemonic(i.op), condition_suffix(i.cond), reg(i.dest), reg(i.lhs), reg(i.rhs))?;
+				if i.shift.shift != 0 {
+					write!(f, ", {} {}", shift_mnemonic(i.shift.kind), i.shift.shift)?;
+				}
+				Ok(())
+			},
+			Instruction::Rri(i) => {
+				write!(f, "{}{} %{}, %{}, {}", binop_mnemonic(i.op), condition_suffix(i.cond), reg(i.dest), reg(i.src), i.imm)
+			},
+			Instruction::Memory(memory::Instruction::Rr(i)) => {
+				let op = if i.op.op == LoadStore::Load { "ld" } else { "st" };
+				write!(f, "{op}{}{} %{}, [%{} + %{}", width_suffix(i.op.width), condition_suffix(i.cond), reg(i.rd), reg(i.rs), reg(i.rq))?;
+				if i.shift.shift != 0 {
+					write!(f, ", {} {}", shift_mnemonic(i.shift.kind), i.shift.shift)?;
+				}
+				f.write_str("]")
+			},
+			Instruction::Memory(memory::Instruction::Ri(i)) => {
+				let op = if i.op.op == LoadStore::Load { "ld" } else { "st" };
+				write!(f, "{op}{}{} %{}, [%{} + {}]", width_suffix(i.op.width), condition_suffix(i.cond), reg(i.rd), reg(i.rs), i.imm)
+			},
+			Instruction::Csr(i) => {
+				let op = if i.op.is_load() { "ldcsr" } else { "stcsr" };
+				write!(f, "{op}{} %{}, {:#x}", width_suffix(i.op.width), reg(i.reg), i.imm)
+			},
+			Instruction::Jump(i) => {
+				let op = if i.link { "bl" } else { "b" };
+				write!(f, "{op}{} ", condition_suffix(i.cond))?;
+				match pc {
+					Some(pc) => {
+						let target = pc.wrapping_add((i.imm as u32) << 2);
+						write!(f, "{target:#010x}")?;
+						if let Some((name, offset)) = symbols.and_then(|s| s.lookup(target)) {
+							write_symbol(f, name, offset)?;
+						}
+						Ok(())
+					},
+					None => write!(f, "{}", i.imm),
+				}
+			},
+			instr => write!(f, "{instr:?}"),
+		}
+	}
+}
+
+impl fmt::Display for Disasm<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		self.fmt_at(f, None, None)
+	}
+}
+
+fn write_symbol(f: &mut dyn fmt::Write, name: &str, offset: u32) -> fmt::Result {
+	match offset {
+		0 => write!(f, " <{name}>"),
+		_ => write!(f, " <{name}+{offset:#x}>"),
+	}
+}
+
+/// NZCV bits of the PSR
+const NZCV_MASK: u32 = 0xf;
+
+/// Registers and PSR flags at one point of execution
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+	pub regs: [u32; 32],
+	pub psr: u32,
+}
+
+impl Snapshot {
+	pub fn of<T, M, C>(state: &State<T, M, C>) -> Self
+	where
+		T: Target,
+		M: Memory,
+		C: CsrCollection,
+	{
+		let mut regs = [0; 32];
+		for (i, value) in regs.iter_mut().enumerate() {
+			*value = state.core().read_reg(Register::new(i as u8).unwrap());
+		}
+
+		Self {
+			regs,
+			psr: state.read_psr(),
+		}
+	}
+
+	/// Whether `after` differs in anything but the pc
+	pub fn changed(&self, after: &Snapshot) -> bool {
+		let pc = Register::pc().as_u8() as usize;
+		let regs = self.regs.iter().zip(after.regs.iter()).enumerate().any(|(i, (old, new))| i != pc && old != new);
+		regs || self.psr & NZCV_MASK != after.psr & NZCV_MASK
+	}
+
+	/// Write ` reg=value` for every register other than pc, and ` flag=value` for every NZCV
+	/// flag, that differs in `after`
+	pub fn write_changes(&self, after: &Snapshot, out: &mut dyn fmt::Write) -> fmt::Result {
+		let pc = Register::pc().as_u8() as usize;
+		for (i, (old, new)) in self.regs.iter().zip(after.regs.iter()).enumerate() {
+			if i != pc && old != new {
+				write!(out, " {}={new:#010x}", reg(Register::new(i as u8).unwrap()))?;
+			}
+		}
+
+		let (old, new) = (Psr(self.psr), Psr(after.psr));
+		let flags = [
+			("n", old.n(), new.n()),
+			("z", old.z(), new.z()),
+			("c", old.c(), new.c()),
+			("v", old.v(), new.v()),
+		];
+		for (name, old, new) in flags {
+			if old != new {
+				write!(out, " {name}={new}")?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// One executed instruction, see the module documentation for the format
+pub struct Line<'a> {
+	pub pc: u32,
+	pub instr: Option<&'a Instruction>,
+	pub before: &'a Snapshot,
+	pub after: &'a Snapshot,
+	pub symbols: Option<&'a dyn Symbols>,
+}
+
+impl fmt::Display for Line<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:08x}", self.pc)?;
+		if let Some((name, offset)) = self.symbols.and_then(|s| s.lookup(self.pc)) {
+			write_symbol(f, name, offset)?;
+		}
+		f.write_str(": ")?;
+
+		match self.instr {
+			Some(instr) => Disasm(instr).fmt_at(f, Some(self.pc), self.symbols)?,
+			None => f.write_str("<invalid>")?,
+		}
+
+		if self.before.changed(self.after) {
+			f.write_str("  ;")?;
+			self.before.write_changes(self.after, f)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(feature = "std")]
+fn state_symbols<T, M, C>(state: &State<T, M, C>) -> Option<&dyn Symbols>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	state.symbols().map(|symbols| symbols as &dyn Symbols)
+}
+
+#[cfg(not(feature = "std"))]
+fn state_symbols<T, M, C>(_state: &State<T, M, C>) -> Option<&dyn Symbols>
+where
+	T: Target,
+	M: Memory,
+	C: CsrCollection,
+{
+	None
+}
+
+/// Produces a trace line for every step of a state
+pub struct Tracer<'a> {
+	last: Snapshot,
+	symbols: Option<&'a dyn Symbols>,
+}
+
+impl<'a> Tracer<'a> {
+	/// Start tracing from the current state of `state`
+	pub fn new<T, M, C>(state: &State<T, M, C>) -> Self
+	where
+		T: Target,
+		M: Memory,
+		C: CsrCollection,
+	{
+		Self {
+			last: Snapshot::of(state),
+			symbols: None,
+		}
+	}
+
+	/// Symbols to use instead of the ones attached to the state
+	pub fn set_symbols(&mut self, symbols: Option<&'a dyn Symbols>) {
+		self.symbols = symbols;
+	}
+
+	/// Write the line for the instruction at `pc` that was just executed by `state`
+	pub fn step<T, M, C>(&mut self, pc: u32, instr: Option<&Instruction>, state: &State<T, M, C>, out: &mut dyn fmt::Write) -> fmt::Result
+	where
+		T: Target,
+		M: Memory,
+		C: CsrCollection,
+	{
+		let after = Snapshot::of(state);
+		let line = Line {
+			pc,
+			instr,
+			before: &self.last,
+			after: &after,
+			symbols: self.symbols.or_else(|| state_symbols(state)),
+		};
+
+		let res = write!(out, "{line}");
+		self.last = after;
+		res
+	}
+}
@@ -1,18 +1,28 @@
 #![cfg(feature = "std")]
 extern crate std;
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
 use bibe_asm::asm::emitter::link_instruction;
 use bibe_asm::asm::Directive;
 use bibe_emu::state::csr::*;
-use bibe_emu::{memory::Mock, InterruptKind};
-use bibe_emu::state::State;
+use bibe_emu::{memory::{Memory, SimpleImage}, Interrupt, InterruptKind};
+use bibe_emu::state::observer::Observer;
+use bibe_emu::state::{Engine, State};
+use bibe_emu::symbols::SymbolMap;
 use bibe_emu::target::StdTarget;
-use bibe_instr::{Encode, Instruction, Register};
+use bibe_instr::csr::regs::ISR_ENTER_REG;
+use bibe_instr::{Encode, Instruction, Register, Width};
 use bibe_asm::parser::{ tokenize, parse };
 
 pub fn assemble(program: &str) -> Vec<Instruction> {
+	assemble_with_symbols(program).0
+}
+
+/// Assemble `program`, also returns its labels
+pub fn assemble_with_symbols(program: &str) -> (Vec<Instruction>, SymbolMap) {
 	let tokens = tokenize(program);
 	if tokens.is_err() {
 		panic!("Failed to tokenize program: {tokens:?}");
@@ -75,40 +85,109 @@ pub fn assemble(program: &str) -> Vec<Instruction> {
 		}
 	}
 
-	instructions
+	let symbols = SymbolMap::from_labels(symbols.iter().map(|(name, addr)| (name.to_string(), *addr)));
+	(instructions, symbols)
+}
+
+/// Write `program` to memory starting at address 0
+pub fn write_program<M: Memory>(memory: &mut M, program: &[Instruction]) {
+	for (i, instr) in program.iter().enumerate() {
+		if memory.write(4 * i as u32, Width::Word, instr.encode()).is_err() {
+			panic!("Failed to write instruction #{i}");
+		}
+	}
 }
 
 const EXECUTION_LIMIT: usize = 100_000;
 
+/// Every engine, program tests run on each of them
+pub const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::Block];
+
+/// Kinds and first error values of the interrupts taken
+#[derive(Default)]
+pub struct Interrupts(pub Vec<(InterruptKind, u32)>);
+
+impl Observer for Interrupts {
+	fn interrupt(&mut self, interrupt: &Interrupt) {
+		self.0.push((interrupt.kind, interrupt.err1));
+	}
+}
+
+fn is_swi(instr: &Instruction) -> bool {
+	matches!(instr, Instruction::Csr(i) if !i.op.is_load() && i.imm == ISR_ENTER_REG)
+}
+
+/// Run `program` from memory with every engine until it reaches a `swi`, returns o0
+///
+/// Panics if the program takes any other interrupt or the engines don't agree on o0
 pub fn run(program: &Vec<Instruction>, a0: u32) -> u32 {
-	let mut state: State<_, Mock, Vec<Box<dyn CsrBlock>>> = State::new(StdTarget::new(), None, vec![
+	let results = ENGINES.map(|engine| run_to_swi(engine, program, a0));
+	assert!(results.iter().all(|res| *res == results[0]), "Engines disagree: {ENGINES:?} {results:?}");
+	results[0]
+}
+
+/// Run `program` from memory using `engine` until the pc reaches a `swi`, returns o0
+///
+/// The `swi` itself isn't executed, it would swap o0 out with the rest of the register bank.
+pub fn run_to_swi(engine: Engine, program: &Vec<Instruction>, a0: u32) -> u32 {
+	let mut state = machine();
+	write_program(&mut state, program);
+	state.set_engine(engine);
+	state.core_mut().write_reg(Register::a0(), a0);
+
+	let interrupts = Rc::new(RefCell::new(Interrupts::default()));
+	state.add_observer(Box::new(interrupts.clone()));
+
+	for _ in 0..EXECUTION_LIMIT {
+		let i = (state.core().read_pc() / 4) as usize;
+		if program.get(i).is_some_and(is_swi) {
+			return state.core().read_reg(Register::o0());
+		}
+
+		state.step();
+		if let Some(interrupt) = interrupts.borrow().0.first() {
+			panic!("{engine:?} interrupt: {interrupt:?}");
+		}
+	}
+
+	panic!("Execution limit exceeded");
+}
+
+/// State with the ISA defined CSR blocks
+pub type Machine<M = SimpleImage> = State<StdTarget, M, Vec<Box<dyn CsrBlock>>>;
+
+/// 4K of RAM at address 0 and the ISA defined CSR blocks
+pub fn machine() -> Machine {
+	machine_with(SimpleImage::new(0x1000))
+}
+
+/// Like `machine`, with `memory` instead of the default RAM
+pub fn machine_with<M: Memory>(memory: M) -> Machine<M> {
+	State::new(StdTarget::new(), Some(memory), vec![
 		Box::new(PsrBlock::new()),
 		Box::new(IsrBlock::new()),
-	]);
-	let mut executed = 0;
+	])
+}
 
-	state.core.borrow_mut().write_reg(Register::a0(), a0);
+/// Run `program` from memory using `engine` until it reaches its final instruction
+pub fn run_engine(engine: Engine, program: &Vec<Instruction>, a0: u32) -> u32 {
+	let mut state = machine();
+	let end = 4 * (program.len() as u32 - 1);
+	let mut steps = 0;
 
-	loop {
-		let pc = state.core.borrow().read_pc();
-		let i = (pc / 4) as usize;
-		let res = state.execute(&program[i]);
+	write_program(&mut state, program);
+	state.set_engine(engine);
+	state.core_mut().write_reg(Register::a0(), a0);
 
-		if let Err(interrupt) = res {
-			match interrupt.kind {
-				InterruptKind::Swi => break,
-				_ => panic!("Interrupt: {interrupt:?}")
-			}
-		}
+	while state.core().read_pc() != end {
+		state.step();
 
-		if executed > EXECUTION_LIMIT {
+		if steps > EXECUTION_LIMIT {
 			panic!("Execution limit exceeded");
 		}
 
-		executed += 1;
+		steps += 1;
 	}
 
-	
-	let val = state.core.borrow().read_reg(Register::o0());
-	val
-}
\ No newline at end of file
+	state.core().read_reg(Register::o0())
+}
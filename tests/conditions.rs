@@ -0,0 +1,46 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_instr::{
+	Condition,
+	Instruction,
+};
+
+/// o0 is 1 if the branch is taken after `cmp a0, 1`
+fn branch(cond: &str) -> String {
+	format!("\
+	mov %l0, 1
+	cmp %a0, %l0
+	b.{cond} taken
+	mov %o0, 0
+	swi
+taken:
+	mov %o0, 1
+	swi
+")
+}
+
+#[test]
+fn signed_overflow() {
+	let ge = assemble(&branch("ge"));
+	let gt = assemble(&branch("gt"));
+
+	// b.ge has no condition of its own, it's NotNegative evaluated as a signed >=
+	let Instruction::Jump(jump) = &ge[2] else {
+		panic!("Not a jump: {:?}", ge[2]);
+	};
+	assert!(matches!(jump.cond, Condition::NotNegative));
+
+	assert_eq!(run(&ge, 5), 1);
+	assert_eq!(run(&ge, 1), 1);
+	assert_eq!(run(&ge, 0), 0);
+	assert_eq!(run(&gt, 5), 1);
+	assert_eq!(run(&gt, 1), 0);
+
+	// 0x80000000 - 1 overflows to a positive result, a signed compare has to look at V
+	assert_eq!(run(&gt, 0x8000_0000), 0);
+	assert_eq!(run(&ge, 0x8000_0000), 0);
+	assert_eq!(run(&ge, 0x7fff_ffff), 1);
+}
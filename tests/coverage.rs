@@ -0,0 +1,70 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bibe_emu::coverage::Coverage;
+use bibe_emu::state::Engine;
+use bibe_instr::Register;
+
+const PROGRAM: &'static str = "\
+	mov %l0, 0
+loop:
+	cmp %l0, %a0
+	b.ge end
+	add %l0, %l0, 1
+	b loop
+end:
+	mov %o0, %l0
+	swi
+";
+
+const END: u32 = 0x18;
+
+fn cover(engine: Engine, a0: u32) -> Coverage {
+	let mut state = machine();
+	write_program(&mut state, &assemble(PROGRAM));
+	state.set_engine(engine);
+	state.core_mut().write_reg(Register::a0(), a0);
+
+	let coverage = Rc::new(RefCell::new(Coverage::new()));
+	state.add_observer(Box::new(coverage.clone()));
+
+	while state.core().read_pc() != END {
+		state.step();
+	}
+
+	coverage.take()
+}
+
+#[test]
+fn branches() {
+	for engine in [Engine::Interpreter, Engine::Block] {
+		let coverage = cover(engine, 3);
+
+		// The loop runs three times, then b.ge is taken once
+		assert_eq!(coverage.get(0x4).unwrap().executed, 4, "{engine:?}");
+		assert_eq!(coverage.get(0x8).unwrap().branches, Some((1, 3)), "{engine:?}");
+		assert_eq!(coverage.get(0xc).unwrap().executed, 3);
+		assert_eq!(coverage.get(0xc).unwrap().branches, None);
+		assert!(coverage.get(END).is_none());
+	}
+}
+
+#[test]
+fn merge_runs() {
+	let mut coverage = cover(Engine::Interpreter, 0);
+	assert_eq!(coverage.get(0x8).unwrap().branches, Some((1, 0)));
+	assert!(coverage.get(0xc).is_none());
+
+	coverage.merge(&cover(Engine::Interpreter, 1));
+	assert_eq!(coverage.get(0x8).unwrap().branches, Some((2, 1)));
+	assert_eq!(coverage.get(0xc).unwrap().executed, 1);
+
+	let mut listing = String::new();
+	coverage.write_listing(&mut listing).unwrap();
+	assert_eq!(Coverage::from_listing(&listing).unwrap(), coverage);
+}
@@ -0,0 +1,170 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::trace::Disasm;
+use bibe_instr::{
+	memory,
+	BinOp,
+	Condition,
+	Encode,
+	Instruction,
+	Register,
+	ShiftKind,
+};
+
+/// One instruction of every format
+const FORMATS: &'static str = "\
+	add %l0, %l1, %l2
+	add %l0, %l1, 5
+	ldw %l0, [%l1 + %l2]
+	stw %l0, [%l1 + 4]
+	swi
+	b end
+	bl end
+end:
+	b end
+";
+
+const CONDITIONS: [Condition; 8] = [
+	Condition::Always,
+	Condition::Overflow,
+	Condition::Carry,
+	Condition::Zero,
+	Condition::Negative,
+	Condition::NotZero,
+	Condition::NotNegative,
+	Condition::GreaterThan,
+];
+
+const OPS: [BinOp; 18] = [
+	BinOp::Add,
+	BinOp::Addcc,
+	BinOp::Sub,
+	BinOp::Subcc,
+	BinOp::Mul,
+	BinOp::Div,
+	BinOp::Mod,
+	BinOp::And,
+	BinOp::Or,
+	BinOp::Xor,
+	BinOp::Shl,
+	BinOp::Shr,
+	BinOp::Asl,
+	BinOp::Asr,
+	BinOp::Rol,
+	BinOp::Ror,
+	BinOp::Not,
+	BinOp::Neg,
+];
+
+const SHIFTS: [ShiftKind; 6] = [
+	ShiftKind::Shl,
+	ShiftKind::Shr,
+	ShiftKind::Asl,
+	ShiftKind::Asr,
+	ShiftKind::Rol,
+	ShiftKind::Ror,
+];
+
+/// Assembling the disassembly of `instr` must give back the same encoding
+fn round_trip(instr: &Instruction) {
+	let text = Disasm(instr).to_string();
+	let reassembled = assemble(&text);
+
+	assert_eq!(reassembled.len(), 1, "{text}");
+	assert_eq!(reassembled[0].encode(), instr.encode(), "{text}");
+}
+
+#[test]
+fn formats() {
+	for instr in assemble(FORMATS) {
+		round_trip(&instr);
+	}
+}
+
+#[test]
+fn conditions() {
+	for instr in assemble(FORMATS) {
+		for cond in CONDITIONS {
+			let mut instr = instr.clone();
+			match &mut instr {
+				Instruction::Rrr(i) => i.cond = cond,
+				Instruction::Rri(i) => i.cond = cond,
+				Instruction::Memory(memory::Instruction::Rr(i)) => i.cond = cond,
+				Instruction::Memory(memory::Instruction::Ri(i)) => i.cond = cond,
+				Instruction::Jump(i) => i.cond = cond,
+				// CSR accesses have no condition
+				_ => continue,
+			}
+
+			round_trip(&instr);
+		}
+	}
+}
+
+#[test]
+fn ops() {
+	let program = assemble(FORMATS);
+	for op in OPS {
+		let (mut rrr, mut rri) = (program[0].clone(), program[1].clone());
+		if let Instruction::Rrr(i) = &mut rrr {
+			i.op = op;
+		}
+		if let Instruction::Rri(i) = &mut rri {
+			i.op = op;
+		}
+
+		round_trip(&rrr);
+		round_trip(&rri);
+	}
+}
+
+#[test]
+fn shifts() {
+	let program = assemble(FORMATS);
+	for kind in SHIFTS {
+		let (mut rrr, mut rr) = (program[0].clone(), program[2].clone());
+		if let Instruction::Rrr(i) = &mut rrr {
+			i.shift.kind = kind;
+			i.shift.shift = 2;
+		}
+		if let Instruction::Memory(memory::Instruction::Rr(i)) = &mut rr {
+			i.shift.kind = kind;
+			i.shift.shift = 2;
+		}
+
+		round_trip(&rrr);
+		round_trip(&rr);
+	}
+}
+
+#[test]
+fn registers() {
+	let program = assemble(FORMATS);
+	for n in 0..32 {
+		let reg = Register::new(n).unwrap();
+		let (mut rrr, mut rr) = (program[0].clone(), program[2].clone());
+		if let Instruction::Rrr(i) = &mut rrr {
+			(i.dest, i.lhs, i.rhs) = (reg, reg, reg);
+		}
+		if let Instruction::Memory(memory::Instruction::Rr(i)) = &mut rr {
+			(i.rd, i.rs, i.rq) = (reg, reg, reg);
+		}
+
+		// Every name, including the link register's, has to be one the assembler accepts
+		round_trip(&rrr);
+		round_trip(&rr);
+	}
+}
+
+#[test]
+fn branch_without_pc() {
+	let program = assemble(FORMATS);
+
+	// The displacement in words, as the assembler takes it
+	assert_eq!(Disasm(&program[5]).to_string(), "b 2");
+	assert_eq!(Disasm(&program[6]).to_string(), "bl 1");
+	assert_eq!(Disasm(&program[7]).to_string(), "b 0");
+}
@@ -0,0 +1,78 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::memory::Memory;
+use bibe_emu::state::Engine;
+use bibe_instr::{
+	Encode,
+	Register,
+	Width,
+};
+
+fn state(engine: Engine, program: &str) -> Machine {
+	let mut state = machine();
+
+	write_program(&mut state, &assemble(program));
+	state.set_engine(engine);
+	state
+}
+
+fn run_to(state: &mut Machine, start: u32, end: u32) {
+	state.core_mut().write_pc(start);
+	for _ in 0..1000 {
+		if state.core().read_pc() == end {
+			return;
+		}
+		state.step();
+	}
+
+	panic!("Execution limit exceeded");
+}
+
+const LOOP: &'static str = "\
+	mov %l0, 0
+loop:
+	cmp %l0, %a0
+	b.ge end
+	add %l0, %l0, 1
+	b loop
+end:
+	mov %o0, %l0
+	swi
+";
+
+#[test]
+fn same_cycles() {
+	let mut cycles = Vec::new();
+	for engine in [Engine::Interpreter, Engine::Block] {
+		let mut state = state(engine, LOOP);
+		state.core_mut().write_reg(Register::a0(), 10);
+		run_to(&mut state, 0, 24);
+
+		assert_eq!(state.core().read_reg(Register::o0()), 10);
+		cycles.push(state.cycles());
+	}
+
+	assert_eq!(cycles[0], cycles[1]);
+}
+
+#[test]
+fn invalidate_block() {
+	let mut state = state(Engine::Block, "\
+		mov %o0, 1
+		mov %o1, 2
+		swi
+	");
+
+	run_to(&mut state, 0, 8);
+	assert_eq!(state.core().read_reg(Register::o0()), 1);
+
+	// Patching any instruction of the block drops the translation
+	let patched = assemble("mov %o0, 3");
+	assert!(state.write(0, Width::Word, patched[0].encode()).is_ok());
+
+	run_to(&mut state, 0, 8);
+	assert_eq!(state.core().read_reg(Register::o0()), 3);
+}
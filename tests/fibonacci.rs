@@ -2,6 +2,8 @@
 mod common;
 use common::*;
 
+use bibe_emu::state::Engine;
+
 const PROGRAM: &'static str = "\
 	mov %l0, 0
 	mov %l1, 0
@@ -31,3 +33,13 @@ fn fibonacci() {
 	assert_eq!(run(&program, 6), 8);
 	assert_eq!(run(&program, 7), 13);
 }
+
+#[test]
+fn fibonacci_engines() {
+	let program = assemble(PROGRAM);
+	for engine in [Engine::Interpreter, Engine::Block] {
+		for (n, expected) in [0, 1, 1, 2, 3, 5, 8, 13].into_iter().enumerate() {
+			assert_eq!(run_engine(engine, &program, n as u32), expected, "{engine:?}");
+		}
+	}
+}
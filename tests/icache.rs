@@ -0,0 +1,48 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::memory::Memory;
+use bibe_instr::{
+	Encode,
+	Register,
+	Width,
+};
+
+fn state(program: &str) -> Machine {
+	let mut state = machine();
+
+	write_program(&mut state, &assemble(program));
+	state
+}
+
+/// Execute from `start` until the pc reaches `end`
+fn run_to(state: &mut Machine, start: u32, end: u32) {
+	let mut executed = 0;
+	state.core_mut().write_pc(start);
+
+	while state.core().read_pc() != end {
+		state.execute_one();
+		executed += 1;
+		assert!(executed < 10_000, "Execution limit exceeded");
+	}
+}
+
+#[test]
+fn invalidate_on_store() {
+	let mut state = state("\
+		mov %o0, 1
+		swi
+	");
+
+	run_to(&mut state, 0, 4);
+	assert_eq!(state.core().read_reg(Register::o0()), 1);
+
+	// Replace the cached instruction, the new one must be executed
+	let patched = assemble("mov %o0, 2");
+	assert!(state.write(0, Width::Word, patched[0].encode()).is_ok());
+
+	run_to(&mut state, 0, 4);
+	assert_eq!(state.core().read_reg(Register::o0()), 2);
+}
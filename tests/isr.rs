@@ -0,0 +1,57 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::InterruptKind;
+use bibe_instr::csr::regs::*;
+use bibe_instr::{
+	Instruction,
+	Width,
+};
+
+const ISR_BASE: u32 = 0x100;
+
+#[test]
+fn swi_value() {
+	let mut state = machine();
+	assert!(state.write_csr(ISR_BASE_REG, ISR_BASE, Width::Word).is_some());
+
+	let swi = assemble("swi").remove(0);
+	let Instruction::Csr(store) = &swi else {
+		panic!("swi isn't a CSR store: {swi:?}");
+	};
+
+	// The handler receives whatever the SWI stored
+	state.core_mut().write_reg(store.reg, 0x1234);
+	let value = state.core().read_reg(store.reg);
+
+	let interrupt = state.execute(&swi).unwrap_err();
+	assert_eq!(interrupt.kind, InterruptKind::Swi);
+	assert_eq!(interrupt.err1, value);
+
+	state.handle_interrupt(&interrupt);
+	assert_eq!(state.read_csr(ISR_ERR1_REG, Width::Word), Some(value));
+	assert_eq!(state.core().read_pc(), ISR_BASE + 4 * InterruptKind::Swi.to_index().unwrap());
+}
+
+#[test]
+fn swi_from_memory() {
+	let swi = assemble("swi");
+	let Instruction::Csr(store) = &swi[0] else {
+		panic!("swi isn't a CSR store: {:?}", swi[0]);
+	};
+
+	for engine in ENGINES {
+		let mut state = machine();
+		write_program(&mut state, &swi);
+		state.set_engine(engine);
+		assert!(state.write_csr(ISR_BASE_REG, ISR_BASE, Width::Word).is_some());
+		state.core_mut().write_reg(store.reg, 0x1234);
+
+		state.step();
+		assert_eq!(state.read_csr(ISR_ERR1_REG, Width::Word), Some(0x1234), "{engine:?}");
+		assert_eq!(state.read_csr(ISR_PC_REG, Width::Word), Some(4), "{engine:?}");
+		assert_eq!(state.core().read_pc(), ISR_BASE + 4 * InterruptKind::Swi.to_index().unwrap(), "{engine:?}");
+	}
+}
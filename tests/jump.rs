@@ -0,0 +1,108 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::state::Engine;
+use bibe_instr::{
+	Instruction,
+	Register,
+};
+
+const FORWARD_BACKWARD: &'static str = "\
+	mov %o0, 0
+	b forward
+back:
+	add %o0, %o0, 1
+	swi
+forward:
+	add %o0, %o0, 2
+	b back
+";
+
+/// o0 is 1 if `b.ge` is taken after `cmp a0, 1`
+const CONDITIONAL: &'static str = "\
+	mov %o0, 0
+	mov %l0, 1
+	cmp %a0, %l0
+	b.ge taken
+	swi
+taken:
+	mov %o0, 1
+	swi
+";
+
+/// The callee returns its link register
+const CALL: &'static str = "\
+	bl func
+	swi
+func:
+	mov %o0, %r30
+	mov %pc, %r30
+";
+
+/// Skips the first `swi` by writing pc with an ALU instruction
+const PC_WRITE: &'static str = "\
+	mov %pc, 12
+	mov %o0, 1
+	swi
+	mov %o0, 2
+	swi
+";
+
+#[test]
+fn displacement() {
+	let program = assemble(FORWARD_BACKWARD);
+
+	// The displacement is in words, relative to the branch
+	let Instruction::Jump(forward) = &program[1] else {
+		panic!("Not a jump: {:?}", program[1]);
+	};
+	assert_eq!(forward.imm, 3);
+	assert!(!forward.link);
+
+	let Instruction::Jump(back) = &program[5] else {
+		panic!("Not a jump: {:?}", program[5]);
+	};
+	assert_eq!(back.imm, -3);
+
+	assert_eq!(run(&program, 0), 3);
+}
+
+#[test]
+fn conditional() {
+	let program = assemble(CONDITIONAL);
+	assert_eq!(run(&program, 2), 1);
+	assert_eq!(run(&program, 0), 0);
+}
+
+#[test]
+fn call_return() {
+	let program = assemble(CALL);
+
+	let Instruction::Jump(call) = &program[0] else {
+		panic!("Not a jump: {:?}", program[0]);
+	};
+	assert!(call.link);
+
+	// The link register holds the address after the call, returning through it reaches the swi
+	assert_eq!(run(&program, 0), 4);
+}
+
+#[test]
+fn pc_write() {
+	let program = assemble(PC_WRITE);
+	assert_eq!(run(&program, 0), 2);
+
+	for engine in [Engine::Interpreter, Engine::Block] {
+		let mut state = machine();
+		write_program(&mut state, &program);
+		state.set_engine(engine);
+
+		// Writing pc is a taken branch, so it also ends a translated block
+		state.step();
+		assert_eq!(state.core().read_pc(), 12, "{engine:?}");
+		assert_eq!(state.core().read_reg(Register::o0()), 0, "{engine:?}");
+		assert_eq!(state.retired(), 1, "{engine:?}");
+	}
+}
@@ -0,0 +1,107 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::monitor::Monitor;
+use bibe_emu::state::Psr;
+use bibe_emu::InterruptKind;
+use bibe_instr::Register;
+
+const PROGRAM: &'static str = "\
+	mov %o0, 1
+	mov %o0, 2
+	mov %o0, 3
+	swi
+";
+
+fn run(commands: &str) -> (String, Machine) {
+	let mut state = machine();
+	write_program(&mut state, &assemble(PROGRAM));
+
+	(monitor(&mut state, commands), state)
+}
+
+fn monitor(state: &mut Machine, commands: &str) -> String {
+	let mut out = Vec::new();
+	Monitor::new(state).run(&mut commands.as_bytes(), &mut out).unwrap();
+	String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn step_and_registers() {
+	let (out, state) = run("step 2\nr o0\nset a0 0x10\nc 0xc\n");
+
+	assert!(out.contains("=> 00000008"));
+	assert!(out.contains("o0: 0x00000002 (2)"));
+	assert!(out.contains("=> 0000000c"));
+	assert_eq!(state.core().read_reg(Register::a0()), 0x10);
+	assert_eq!(state.core().read_reg(Register::o0()), 3);
+}
+
+#[test]
+fn inspect() {
+	let (out, _) = run("psr\nx 0 16\ndis 0 2\ncsr\nbogus\nstep x\nquit\nstep\n");
+
+	assert!(out.contains("psr: 0x00000000 n=0 z=0 c=0 v=0"));
+	assert!(out.starts_with("(bibe) psr"));
+	assert!(out.contains("00000000  "));
+	assert!(out.contains("=> 00000000: "));
+	assert!(out.contains("0: psr"));
+	assert!(out.contains("1: isr"));
+	assert!(out.contains("unknown command bogus"));
+	assert!(out.contains("invalid arguments for step"));
+
+	// Nothing is executed after quit
+	assert!(!out.contains("=> 00000004"));
+}
+
+#[test]
+fn symbols() {
+	let mut state = machine();
+	let (program, symbols) = assemble_with_symbols("\
+	mov %o0, 1
+second:
+	mov %o0, 2
+third:
+	mov %o0, 3
+	swi
+");
+	write_program(&mut state, &program);
+	state.set_symbols(Some(symbols));
+
+	let mut out = Vec::new();
+	Monitor::new(&mut state).run(&mut "break third\nsym 0x4\nc\n".as_bytes(), &mut out).unwrap();
+	let out = String::from_utf8(out).unwrap();
+
+	assert!(out.contains("breakpoint at 00000008 <third>"), "{out}");
+	assert!(out.contains("00000004 <second>"));
+	assert!(out.contains("=> 00000008 <third>: "));
+	assert_eq!(state.core().read_reg(Register::o0()), 2);
+}
+
+#[test]
+fn irq() {
+	let mut state = machine();
+	write_program(&mut state, &assemble(PROGRAM));
+	let handler = 4 * InterruptKind::Irq(3).to_index().unwrap();
+
+	// Interrupts are masked out of reset
+	let out = monitor(&mut state, "irq 3\n");
+	assert!(out.contains("interrupts are masked, irq 3 not raised"), "{out}");
+	assert_eq!(state.core().read_pc(), 0);
+
+	let mut psr = Psr(state.read_psr());
+	psr.set_exception_enabled(1);
+	state.write_psr(psr.0);
+
+	let out = monitor(&mut state, "irq 3\n");
+	assert!(!out.contains("masked"), "{out}");
+	assert_eq!(state.core().read_pc(), handler);
+	assert_eq!(Psr(state.read_psr()).interrupt_mode(), 1);
+
+	// Entering the handler masks them again
+	let out = monitor(&mut state, "irq 4\n");
+	assert!(out.contains("interrupts are masked, irq 4 not raised"), "{out}");
+	assert_eq!(state.core().read_pc(), handler);
+}
@@ -0,0 +1,130 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::memory::{
+	Memory,
+	SimpleImage,
+};
+use bibe_emu::state::csr::*;
+use bibe_emu::state::State;
+use bibe_emu::target::StdTarget;
+use bibe_emu::InterruptKind;
+use bibe_instr::csr::regs::*;
+use bibe_instr::csr;
+use bibe_instr::{
+	Encode,
+	Instruction,
+	LoadStore,
+	Width,
+};
+
+const ISR_BASE: u32 = 0x100;
+const PROGRAM: u32 = 0x800;
+
+/// Not covered by any of the blocks used here
+const UNKNOWN_REG: u32 = 0xffff_fff0;
+
+fn state(blocks: Vec<Box<dyn CsrBlock>>) -> Machine {
+	let mut state = State::new(StdTarget::new(), Some(SimpleImage::new(0x1000)), blocks);
+	assert!(state.write_csr(ISR_BASE_REG, ISR_BASE, Width::Word).is_some());
+	state.core_mut().write_pc(PROGRAM);
+	state
+}
+
+fn isa_blocks() -> Vec<Box<dyn CsrBlock>> {
+	vec![
+		Box::new(PsrBlock::new()),
+		Box::new(IsrBlock::new()),
+	]
+}
+
+fn opcode_handler() -> u32 {
+	ISR_BASE + 4 * InterruptKind::OpcodeFault.to_index().unwrap()
+}
+
+/// A CSR store to `reg`, built from the store `swi` assembles to
+fn csr_store(reg: u32) -> csr::Instruction {
+	let swi = assemble("swi").remove(0);
+	let Instruction::Csr(mut store) = swi else {
+		panic!("swi isn't a CSR store: {swi:?}");
+	};
+
+	store.imm = reg;
+	store
+}
+
+fn csr_load(reg: u32) -> csr::Instruction {
+	let mut load = csr_store(reg);
+	load.op.op = LoadStore::Load;
+	load
+}
+
+#[test]
+fn undecodable() {
+	let word = (0..=u32::MAX).step_by(0x1_0001)
+		.find(|word| Instruction::decode(*word).is_none())
+		.expect("No undecodable encoding found");
+
+	let mut state = state(isa_blocks());
+	assert!(state.write(PROGRAM, Width::Word, word).is_ok());
+
+	// The guest faults instead of the host panicking
+	state.execute_one();
+	assert_eq!(state.core().read_pc(), opcode_handler());
+}
+
+#[test]
+fn decodable() {
+	// Whatever the format, executing a decodable word never takes the host down
+	for engine in ENGINES {
+		for word in (0..=u32::MAX).step_by(0x10_0001) {
+			let Some(instr) = Instruction::decode(word) else {
+				continue;
+			};
+
+			let mut state = state(isa_blocks());
+			state.set_engine(engine);
+			assert!(state.write(PROGRAM, Width::Word, word).is_ok());
+
+			state.step();
+			assert!(!state.is_stopped(), "{engine:?} {word:08x} {instr:?}");
+		}
+	}
+}
+
+#[test]
+fn unknown_csr() {
+	let mut state = state(isa_blocks());
+	assert_eq!(state.read_csr(UNKNOWN_REG, Width::Word), None);
+
+	let load = csr_load(UNKNOWN_REG);
+	let interrupt = state.execute(&Instruction::Csr(load)).unwrap_err();
+	assert_eq!(interrupt.kind, InterruptKind::OpcodeFault);
+
+	let store = csr_store(UNKNOWN_REG);
+	let interrupt = state.execute(&Instruction::Csr(store.clone())).unwrap_err();
+	assert_eq!(interrupt.kind, InterruptKind::OpcodeFault);
+
+	// From memory the fault is delivered to the guest
+	assert!(state.write(PROGRAM, Width::Word, Instruction::Csr(store).encode()).is_ok());
+	state.execute_one();
+	assert_eq!(state.core().read_pc(), opcode_handler());
+}
+
+#[test]
+fn dbg_out_invalid_char() {
+	let mut blocks = isa_blocks();
+	blocks.push(Box::new(DbgOutBlock::new()));
+	let mut state = state(blocks);
+
+	let store = csr_store(DBG_OUT_CHAR_OUT0_REG);
+	state.core_mut().write_reg(store.reg, 'A' as u32);
+	assert!(state.execute(&Instruction::Csr(store.clone())).is_ok());
+
+	// Surrogates aren't valid chars
+	state.core_mut().write_reg(store.reg, 0xd800);
+	let interrupt = state.execute(&Instruction::Csr(store)).unwrap_err();
+	assert_eq!(interrupt.kind, InterruptKind::OpcodeFault);
+}
@@ -0,0 +1,108 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bibe_emu::profile::{Profiler, Weight};
+use bibe_emu::symbols::SymbolMap;
+
+const PROGRAM: &'static str = "\
+	mov %o0, 0
+	bl func
+	bl func
+	swi
+func:
+	add %o0, %o0, 1
+	mov %pc, %r30
+";
+
+/// Counts down a0, the recursive call is skipped once it reaches zero, falling through to the
+/// return address of the call
+const RECURSIVE: &'static str = "\
+	mov %sp, 0x800
+	mov %a0, 2
+	bl rec
+	swi
+rec:
+	sub %sp, %sp, 4
+	stw %r30, [%sp + 0]
+	sub %a0, %a0, 1
+	cmp %a0, %l0
+	bl.gt rec
+	ldw %r30, [%sp + 0]
+	add %sp, %sp, 4
+	mov %pc, %r30
+";
+
+/// Both programs end at the `swi` after their calls
+const END: u32 = 0xc;
+
+fn profile(program: &str, profiler: Profiler) -> (Rc<RefCell<Profiler>>, SymbolMap) {
+	let mut state = machine();
+	let (program, symbols) = assemble_with_symbols(program);
+	write_program(&mut state, &program);
+
+	let profiler = Rc::new(RefCell::new(profiler));
+	state.add_observer(Box::new(profiler.clone()));
+
+	while state.core().read_pc() != END {
+		state.execute_one();
+	}
+
+	(profiler, symbols)
+}
+
+#[test]
+fn call_graph() {
+	let (profiler, symbols) = profile(PROGRAM, Profiler::new());
+	let profiler = profiler.borrow();
+
+	assert_eq!(profiler.total().instructions, 7);
+	assert_eq!(profiler.per_pc()[&0x10].instructions, 2);
+
+	let mut folded = String::new();
+	profiler.write_folded(Some(&symbols), Weight::Instructions, &mut folded).unwrap();
+	assert_eq!(folded, "[unknown] 3\n[unknown];func 4\n");
+
+	let mut folded = String::new();
+	profiler.write_folded(None, Weight::Instructions, &mut folded).unwrap();
+	assert_eq!(folded, "0x00000000 3\n0x00000000;0x00000010 4\n");
+
+	let functions = profiler.functions(Some(&symbols));
+	let func = functions.iter().find(|f| f.name == "func").unwrap();
+	assert_eq!(func.own.instructions, 4);
+	assert_eq!(func.total.instructions, 4);
+
+	let root = functions.iter().find(|f| f.name == "[unknown]").unwrap();
+	assert_eq!(root.own.instructions, 3);
+	assert_eq!(root.total.instructions, 7);
+
+	let mut report = String::new();
+	profiler.write_report(Some(&symbols), &mut report).unwrap();
+	assert!(report.lines().any(|line| line.ends_with(" func")));
+}
+
+#[test]
+fn sampling() {
+	let (profiler, _) = profile(PROGRAM, Profiler::sampling(1));
+	let profiler = profiler.borrow();
+	let total = profiler.total();
+
+	// Every cycle is a sample
+	assert_eq!(total.instructions, 0);
+	assert!(total.samples >= 7);
+}
+
+#[test]
+fn recursion() {
+	let (profiler, symbols) = profile(RECURSIVE, Profiler::new());
+	let profiler = profiler.borrow();
+
+	// The inner call falls through its skipped call and still runs to its own return
+	let mut folded = String::new();
+	profiler.write_folded(Some(&symbols), Weight::Instructions, &mut folded).unwrap();
+	assert_eq!(folded, "[unknown] 3\n[unknown];rec 8\n[unknown];rec;rec 8\n");
+}
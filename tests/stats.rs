@@ -0,0 +1,109 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bibe_emu::memory::{
+	Mapped,
+	SimpleImage,
+};
+use bibe_emu::state::Engine;
+use bibe_emu::stats::{
+	AccessCounts,
+	ConditionCounts,
+	InstructionFormat,
+	Statistics,
+};
+use bibe_instr::{
+	Condition,
+	Register,
+	Width,
+};
+
+const PROGRAM: &'static str = "\
+	mov %l0, 0
+	mov %l1, 0x1000
+loop:
+	cmp %l0, %a0
+	b.ge end
+	stw %l0, [%l1 + 0]
+	ldw %l2, [%l1 + 0]
+	add %l0, %l0, 1
+	b loop
+end:
+	swi
+";
+
+const END: u32 = 0x20;
+
+fn collect(engine: Engine, a0: u32) -> Statistics {
+	let mut memory = Mapped::new();
+	assert!(memory.map(0, Box::new(SimpleImage::new(0x100))).is_some());
+	assert!(memory.map(0x1000, Box::new(SimpleImage::new(0x100))).is_some());
+
+	let mut state = machine_with(memory);
+	write_program(&mut state, &assemble(PROGRAM));
+	state.set_engine(engine);
+	state.core_mut().write_reg(Register::a0(), a0);
+
+	let regions = state.memory().unwrap().regions();
+	let stats = Rc::new(RefCell::new(Statistics::with_regions(regions)));
+	state.add_observer(Box::new(stats.clone()));
+
+	while state.core().read_pc() != END {
+		state.step();
+	}
+
+	stats.take()
+}
+
+#[test]
+fn instruction_mix() {
+	for engine in [Engine::Interpreter, Engine::Block] {
+		let stats = collect(engine, 3);
+
+		// Two moves, then three full iterations and a final compare and branch
+		assert_eq!(stats.instructions(), 2 + 3 * 6 + 2, "{engine:?}");
+		assert_eq!(stats.format(InstructionFormat::MemoryRi), 6, "{engine:?}");
+		assert_eq!(stats.format(InstructionFormat::Jump), 7, "{engine:?}");
+		assert_eq!(stats.conditions().get(Condition::NotNegative), Some(&ConditionCounts { executed: 4, taken: 1 }));
+		assert_eq!(stats.widths().get(Width::Word), Some(&AccessCounts { loads: 3, stores: 3 }));
+		assert_eq!(stats.widths().get(Width::Byte), None);
+	}
+}
+
+#[test]
+fn region_histogram() {
+	let stats = collect(Engine::Interpreter, 2);
+
+	let [code, data] = stats.regions() else {
+		panic!("expected two regions");
+	};
+	assert_eq!(code.accesses.total(), 0);
+	assert_eq!(data.accesses, AccessCounts { loads: 2, stores: 2 });
+	assert_eq!(data.histogram.get(&0x1000), Some(&4));
+	assert_eq!(stats.unmapped().total(), 0);
+
+	let summary = stats.to_string();
+	assert!(summary.contains("region 00001000..00001100: loads 2 stores 2"), "{summary}");
+}
+
+#[test]
+fn swi_csr_access() {
+	for engine in [Engine::Interpreter, Engine::Block] {
+		let mut state = machine();
+		write_program(&mut state, &assemble("swi"));
+		state.set_engine(engine);
+
+		let stats = Rc::new(RefCell::new(Statistics::new()));
+		state.add_observer(Box::new(stats.clone()));
+		state.step();
+
+		// A SWI is a store to the ISR enter register
+		let stats = stats.take();
+		assert_eq!(stats.csr_blocks().get("isr"), Some(&AccessCounts { loads: 0, stores: 1 }), "{engine:?}");
+	}
+}
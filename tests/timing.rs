@@ -0,0 +1,149 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::memory::{
+	Mapped,
+	SimpleImage,
+};
+use bibe_emu::state::csr::*;
+use bibe_emu::state::{
+	Engine,
+	State,
+	StopReason,
+};
+use bibe_emu::target::{
+	Extension,
+	InstructionClass,
+	StdTarget,
+	Target,
+};
+use bibe_instr::{
+	BinOp,
+	Width,
+};
+
+/// Single cycle ALU, two cycle branches and a three cycle taken-branch penalty
+struct Timed(StdTarget);
+
+impl Target for Timed {
+	fn supports_binop(&self, op: BinOp) -> bool {
+		self.0.supports_binop(op)
+	}
+
+	fn has_extension(&self, extension: Extension) -> bool {
+		self.0.has_extension(extension)
+	}
+
+	fn cycles(&self, class: InstructionClass) -> u32 {
+		match class {
+			InstructionClass::Branch => 2,
+			_ => 1,
+		}
+	}
+
+	fn taken_branch_penalty(&self) -> u32 {
+		3
+	}
+}
+
+fn state(program: &str, wait_states: u32, engine: Engine) -> State<Timed, Mapped, Vec<Box<dyn CsrBlock>>> {
+	let mut memory = Mapped::new();
+	assert!(memory.map_with_wait_states(0, Box::new(SimpleImage::new(0x100)), wait_states).is_some());
+
+	let mut state: State<_, Mapped, Vec<Box<dyn CsrBlock>>> = State::new(Timed(StdTarget::new()), Some(memory), vec![
+		Box::new(PsrBlock::new()),
+		Box::new(IsrBlock::new()),
+		Box::new(CycleBlock::new()),
+	]);
+
+	write_program(&mut state, &assemble(program));
+	state.set_engine(engine);
+
+	state
+}
+
+/// Execute until the pc reaches `end` or the state stops
+fn run_to(state: &mut State<Timed, Mapped, Vec<Box<dyn CsrBlock>>>, end: u32) {
+	for _ in 0..100 {
+		if state.core().read_pc() == end || state.is_stopped() {
+			return;
+		}
+		state.step();
+	}
+
+	panic!("Execution limit exceeded");
+}
+
+const STRAIGHT: &'static str = "\
+	mov %l0, 1
+	add %l0, %l0, 2
+	swi
+";
+
+const BRANCH: &'static str = "\
+	b skip
+	mov %l0, 1
+skip:
+	mov %l0, 2
+	swi
+";
+
+const PC_WRITE: &'static str = "\
+	mov %pc, 8
+	mov %l0, 1
+	mov %l0, 2
+	swi
+";
+
+#[test]
+fn wait_states() {
+	for engine in ENGINES {
+		let mut state = state(STRAIGHT, 3, engine);
+		run_to(&mut state, 8);
+
+		// Two ALU instructions, each fetch takes three wait states
+		assert_eq!(state.cycles(), 8, "{engine:?}");
+		assert_eq!(state.read_csr(CYCLE_LO_REG, Width::Word), Some(8), "{engine:?}");
+		assert_eq!(state.read_csr(CYCLE_HI_REG, Width::Word), Some(0), "{engine:?}");
+	}
+}
+
+#[test]
+fn taken_branch() {
+	for engine in ENGINES {
+		let mut state = state(BRANCH, 0, engine);
+		run_to(&mut state, 12);
+
+		// Branch and penalty, then a single ALU instruction
+		assert_eq!(state.cycles(), 6, "{engine:?}");
+	}
+}
+
+#[test]
+fn pc_write() {
+	for engine in ENGINES {
+		let mut state = state(PC_WRITE, 0, engine);
+		run_to(&mut state, 12);
+
+		// ALU writes to pc pay the taken-branch penalty too
+		assert_eq!(state.cycles(), 5, "{engine:?}");
+	}
+}
+
+#[test]
+fn cycle_budget() {
+	for engine in ENGINES {
+		let mut state = state(STRAIGHT, 3, engine);
+		state.set_cycle_budget(Some(3));
+		run_to(&mut state, 8);
+
+		assert!(matches!(state.stop_reason(), Some(StopReason::CycleBudget)), "{engine:?}");
+		assert_eq!(state.core().read_pc(), 4, "{engine:?}");
+
+		state.set_cycle_budget(None);
+		run_to(&mut state, 8);
+		assert_eq!(state.cycles(), 8, "{engine:?}");
+	}
+}
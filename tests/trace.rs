@@ -0,0 +1,73 @@
+#![cfg(feature = "std")]
+#[allow(dead_code)]
+mod common;
+use common::*;
+
+use bibe_emu::trace::{Symbols, Tracer};
+use bibe_instr::{Instruction, Width};
+use bibe_emu::memory::Memory;
+
+const PROGRAM: &'static str = "\
+	mov %o0, 1
+	cmp %o0, %o0
+	b end
+	mov %o0, 2
+end:
+	swi
+";
+
+struct Labels;
+
+impl Symbols for Labels {
+	fn lookup(&self, addr: u32) -> Option<(&str, u32)> {
+		match addr {
+			0..=0xf => Some(("start", addr)),
+			0x10 => Some(("end", 0)),
+			_ => None,
+		}
+	}
+}
+
+fn trace(symbols: Option<&dyn Symbols>) -> Vec<String> {
+	let mut state = machine();
+	write_program(&mut state, &assemble(PROGRAM));
+
+	let mut tracer = Tracer::new(&state);
+	tracer.set_symbols(symbols);
+
+	let mut lines = Vec::new();
+	while state.core().read_pc() != 0x10 {
+		let pc = state.core().read_pc();
+		let instr = state.read(pc, Width::Word).ok().and_then(Instruction::decode);
+		state.execute_one();
+
+		let mut line = String::new();
+		tracer.step(pc, instr.as_ref(), &state, &mut line).unwrap();
+		lines.push(line);
+	}
+	lines
+}
+
+#[test]
+fn changed_only() {
+	let lines = trace(None);
+	assert_eq!(lines.len(), 3);
+
+	assert!(lines[0].starts_with("00000000: "));
+	assert!(lines[0].ends_with("; o0=0x00000001"), "{}", lines[0]);
+
+	// Comparing equal registers only touches the flags
+	assert!(lines[1].ends_with("; z=1"), "{}", lines[1]);
+
+	// A branch changes nothing but the pc
+	assert!(lines[2].starts_with("00000008: b 0x00000010"), "{}", lines[2]);
+	assert!(!lines[2].contains(';'));
+}
+
+#[test]
+fn symbols() {
+	let lines = trace(Some(&Labels));
+	assert!(lines[0].starts_with("00000000 <start>: "));
+	assert!(lines[1].starts_with("00000004 <start+0x4>: "));
+	assert!(lines[2].starts_with("00000008 <start+0x8>: b 0x00000010 <end>"), "{}", lines[2]);
+}